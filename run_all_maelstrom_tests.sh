@@ -54,6 +54,30 @@ $MAELSTROM test -w g-counter \
   --nemesis partition \
   > logs/g-counter.log 2>&1 &
 
+$MAELSTROM test -w g-set \
+  --bin $BIN/gset \
+  --node-count 3 \
+  --rate 100 \
+  --time-limit 20 \
+  --nemesis partition \
+  > logs/g-set.log 2>&1 &
+
+$MAELSTROM test -w g-counter \
+  --bin $BIN/gcounter \
+  --node-count 3 \
+  --rate 100 \
+  --time-limit 20 \
+  --nemesis partition \
+  > logs/g-counter-crdt.log 2>&1 &
+
+$MAELSTROM test -w pn-counter \
+  --bin $BIN/pncounter \
+  --node-count 3 \
+  --rate 100 \
+  --time-limit 20 \
+  --nemesis partition \
+  > logs/pn-counter.log 2>&1 &
+
 $MAELSTROM test -w kafka \
   --bin $BIN/kafkalog \
   --node-count 1 \
@@ -70,6 +94,14 @@ $MAELSTROM test -w kafka \
   --rate 1000 \
   > logs/kafka-multi.log 2>&1 &
 
+$MAELSTROM test -w kafka \
+  --bin $BIN/replicatedkafkalog \
+  --node-count 3 \
+  --concurrency 2n \
+  --time-limit 20 \
+  --rate 1000 \
+  > logs/kafka-replicated.log 2>&1 &
+
 $MAELSTROM test -w txn-rw-register \
   --bin $BIN/singletxn \
   --node-count 1 \
@@ -91,6 +123,15 @@ $MAELSTROM test -w txn-rw-register \
   --nemesis partition \
   > logs/multitxn.log 2>&1 &
 
+$MAELSTROM test -w lin-kv \
+  --bin $BIN/raft \
+  --node-count 3 \
+  --concurrency 2n \
+  --time-limit 20 \
+  --rate 100 \
+  --nemesis partition \
+  > logs/raft.log 2>&1 &
+
 wait
 
 echo "===> All Maelstrom tests completed"
@@ -105,10 +146,15 @@ declare -A TESTS=(
   [broadcast-single]="broadcast-single.log"
   [broadcast-multi]="broadcast-multi.log"
   [g-counter]="g-counter.log"
+  [g-set]="g-set.log"
+  [g-counter-crdt]="g-counter-crdt.log"
+  [pn-counter]="pn-counter.log"
   [kafka-single]="kafka-single.log"
   [kafka-multi]="kafka-multi.log"
+  [kafka-replicated]="kafka-replicated.log"
   [singletxn]="singletxn.log"
   [multitxn]="multitxn.log"
+  [raft]="raft.log"
 )
 
 FAILED=0
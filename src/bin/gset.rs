@@ -0,0 +1,8 @@
+use rustorm::crdt::GSet;
+use rustorm::mloop::main_loop;
+use rustorm::node::crdt::CrdtNode;
+use rustorm::payloads::{CrdtPayload, CrdtTimer};
+
+fn main() -> anyhow::Result<()> {
+    main_loop::<CrdtNode<GSet<usize>>, CrdtPayload<GSet<usize>>, CrdtTimer>()
+}
@@ -1,6 +1,8 @@
 use rustorm::mloop::main_loop_async;
+use rustorm::node::multikafkalog::MultiKafkaLogNode;
+use rustorm::payloads::{KafkaLogOrKvPayload, KafkaLogTimer};
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    main_loop_async().await
+    main_loop_async::<MultiKafkaLogNode, KafkaLogOrKvPayload, KafkaLogTimer>().await
 }
@@ -1,6 +1,6 @@
 use rustorm::mloop::main_loop;
 use rustorm::node::multitxn::MultiTxnNode;
-use rustorm::payloads::{TxnPayload};
+use rustorm::payloads::TxnPayload;
 
 fn main() -> anyhow::Result<()> {
     main_loop::<MultiTxnNode, TxnPayload, ()>()?;
@@ -0,0 +1,8 @@
+use rustorm::crdt::PnCounter;
+use rustorm::mloop::main_loop;
+use rustorm::node::crdt::CrdtNode;
+use rustorm::payloads::{CrdtPayload, CrdtTimer};
+
+fn main() -> anyhow::Result<()> {
+    main_loop::<CrdtNode<PnCounter>, CrdtPayload<PnCounter>, CrdtTimer>()
+}
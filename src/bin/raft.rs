@@ -0,0 +1,7 @@
+use rustorm::mloop::main_loop;
+use rustorm::node::raft::RaftNode;
+use rustorm::payloads::{RaftOrKvPayload, RaftTimer};
+
+fn main() -> anyhow::Result<()> {
+    main_loop::<RaftNode, RaftOrKvPayload, RaftTimer>()
+}
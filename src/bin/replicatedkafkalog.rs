@@ -0,0 +1,7 @@
+use rustorm::mloop::main_loop;
+use rustorm::node::replicatedkafkalog::ReplicatedKafkaLogNode;
+use rustorm::payloads::{KafkaReplicationTimer, ReplicatedKafkaLogPayload};
+
+fn main() -> anyhow::Result<()> {
+    main_loop::<ReplicatedKafkaLogNode, ReplicatedKafkaLogPayload, KafkaReplicationTimer>()
+}
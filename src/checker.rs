@@ -0,0 +1,97 @@
+pub mod broadcast;
+pub mod counter;
+pub mod kafka;
+pub mod txn;
+
+use std::time::Duration;
+
+/// Invocations and completions of client operations, in invocation order.
+#[derive(Debug, Clone)]
+pub struct History<T> {
+    ops: Vec<Operation<T>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Operation<T> {
+    pub client: String,
+    pub node: String,
+    pub invoked_at: Duration,
+    pub completed_at: Option<Duration>,
+    pub request: T,
+    pub outcome: Outcome<T>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome<T> {
+    Ok(T),
+    /// The operation definitely did not take place.
+    Fail,
+    /// No reply was received: the operation may or may not have taken place.
+    Info,
+}
+
+impl<T> History<T> {
+    pub fn new() -> Self {
+        Self { ops: Vec::new() }
+    }
+
+    /// Records an invocation and returns its index in the history.
+    pub fn invoke(&mut self, client: &str, node: &str, at: Duration, request: T) -> usize {
+        self.ops.push(Operation {
+            client: client.to_string(),
+            node: node.to_string(),
+            invoked_at: at,
+            completed_at: None,
+            request,
+            outcome: Outcome::Info,
+        });
+        self.ops.len() - 1
+    }
+
+    pub fn complete(&mut self, index: usize, at: Duration, response: T) {
+        if let Some(op) = self.ops.get_mut(index) {
+            op.completed_at = Some(at);
+            op.outcome = Outcome::Ok(response);
+        }
+    }
+
+    pub fn fail(&mut self, index: usize, at: Duration) {
+        if let Some(op) = self.ops.get_mut(index) {
+            op.completed_at = Some(at);
+            op.outcome = Outcome::Fail;
+        }
+    }
+
+    pub fn ops(&self) -> &[Operation<T>] {
+        &self.ops
+    }
+
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+}
+
+impl<T> Default for History<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Operation<T> {
+    pub fn response(&self) -> Option<&T> {
+        match &self.outcome {
+            Outcome::Ok(response) => Some(response),
+            Outcome::Fail | Outcome::Info => None,
+        }
+    }
+
+    /// Whether the operation completed before `other` was invoked.
+    pub fn precedes<U>(&self, other: &Operation<U>) -> bool {
+        self.completed_at
+            .is_some_and(|completed_at| completed_at <= other.invoked_at)
+    }
+}
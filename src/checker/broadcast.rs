@@ -0,0 +1,168 @@
+use crate::checker::{History, Outcome};
+use crate::payloads::BroadcastPayload;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::time::Duration;
+
+#[derive(Debug, Clone, Default)]
+pub struct SetFullReport {
+    pub attempted: usize,
+    pub acknowledged: usize,
+    /// Acknowledged values missing from the last read invoked after their acknowledgement.
+    pub lost: BTreeSet<usize>,
+    /// Values read but never broadcast.
+    pub unexpected: BTreeSet<usize>,
+    /// Acknowledged values missing from some later read, but present in every read after that.
+    pub stale: BTreeSet<usize>,
+    /// Acknowledged values no read was invoked after.
+    pub never_read: BTreeSet<usize>,
+    /// Time between the acknowledgement of a value and the first read
+    /// from which every read contains it.
+    pub stable_latencies: BTreeMap<usize, Duration>,
+}
+
+impl SetFullReport {
+    pub fn is_valid(&self) -> bool {
+        self.lost.is_empty() && self.unexpected.is_empty()
+    }
+}
+
+/// Set-full check of a broadcast workload history.
+pub fn check_broadcast(history: &History<BroadcastPayload>) -> SetFullReport {
+    let mut report = SetFullReport::default();
+    let mut attempted = HashSet::new();
+    let mut acknowledged = Vec::new();
+    let mut reads = Vec::new();
+
+    for op in history.ops() {
+        match (&op.request, &op.outcome) {
+            (BroadcastPayload::Broadcast { message }, outcome) => {
+                attempted.insert(*message);
+                if let (Outcome::Ok(_), Some(completed_at)) = (outcome, op.completed_at) {
+                    acknowledged.push((*message, completed_at));
+                }
+            }
+            (BroadcastPayload::Read, Outcome::Ok(BroadcastPayload::ReadOk { messages })) => {
+                reads.push((op.invoked_at, messages));
+            }
+            _ => {}
+        }
+    }
+    reads.sort_by_key(|(invoked_at, _)| *invoked_at);
+
+    report.attempted = attempted.len();
+    report.acknowledged = acknowledged.len();
+    report.unexpected = reads
+        .iter()
+        .flat_map(|(_, messages)| messages.iter())
+        .filter(|message| !attempted.contains(message))
+        .collect();
+
+    for (message, acknowledged_at) in acknowledged {
+        let reads_after = reads
+            .iter()
+            .filter(|(invoked_at, _)| *invoked_at >= acknowledged_at)
+            .collect::<Vec<_>>();
+        if reads_after.is_empty() {
+            report.never_read.insert(message);
+            continue;
+        }
+
+        let last_missing = reads_after
+            .iter()
+            .rposition(|(_, messages)| !messages.contains(&message));
+        let stable_from = match last_missing {
+            None => 0,
+            Some(i) if i == reads_after.len() - 1 => {
+                report.lost.insert(message);
+                continue;
+            }
+            Some(i) => {
+                report.stale.insert(message);
+                i + 1
+            }
+        };
+        let (stable_at, _) = reads_after[stable_from];
+        report
+            .stable_latencies
+            .insert(message, stable_at.saturating_sub(acknowledged_at));
+    }
+
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crdt::IntervalSet;
+
+    fn broadcast(history: &mut History<BroadcastPayload>, at: u64, message: usize) {
+        let index = history.invoke(
+            "c1",
+            "n0",
+            Duration::from_millis(at),
+            BroadcastPayload::Broadcast { message },
+        );
+        history.complete(
+            index,
+            Duration::from_millis(at + 1),
+            BroadcastPayload::BroadcastOk,
+        );
+    }
+
+    fn read(history: &mut History<BroadcastPayload>, at: u64, messages: &[usize]) {
+        let index = history.invoke(
+            "c2",
+            "n1",
+            Duration::from_millis(at),
+            BroadcastPayload::Read,
+        );
+        history.complete(
+            index,
+            Duration::from_millis(at + 1),
+            BroadcastPayload::ReadOk {
+                messages: messages.iter().copied().collect::<IntervalSet>(),
+            },
+        );
+    }
+
+    #[test]
+    fn accepts_values_that_stay_visible_once_read() {
+        let mut history = History::new();
+        broadcast(&mut history, 0, 1);
+        broadcast(&mut history, 2, 2);
+        read(&mut history, 4, &[1]);
+        read(&mut history, 6, &[1, 2]);
+        read(&mut history, 8, &[1, 2]);
+
+        let report = check_broadcast(&history);
+        assert!(report.is_valid(), "{:?}", report);
+        assert_eq!(report.stale, BTreeSet::from([2]));
+        assert_eq!(report.stable_latencies[&1], Duration::from_millis(3));
+        assert_eq!(report.stable_latencies[&2], Duration::from_millis(3));
+    }
+
+    #[test]
+    fn reports_lost_and_unexpected_values() {
+        let mut history = History::new();
+        broadcast(&mut history, 0, 1);
+        broadcast(&mut history, 2, 2);
+        read(&mut history, 4, &[1, 2, 7]);
+        read(&mut history, 6, &[1]);
+
+        let report = check_broadcast(&history);
+        assert_eq!(report.lost, BTreeSet::from([2]));
+        assert_eq!(report.unexpected, BTreeSet::from([7]));
+        assert!(!report.is_valid());
+    }
+
+    #[test]
+    fn values_acknowledged_after_the_last_read_are_never_read() {
+        let mut history = History::new();
+        read(&mut history, 0, &[]);
+        broadcast(&mut history, 2, 1);
+
+        let report = check_broadcast(&history);
+        assert!(report.is_valid());
+        assert_eq!(report.never_read, BTreeSet::from([1]));
+    }
+}
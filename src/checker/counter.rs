@@ -0,0 +1,201 @@
+use crate::checker::{History, Operation, Outcome};
+use crate::payloads::GoCounterPayload;
+use std::collections::HashMap;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Default)]
+pub struct CounterReport {
+    pub reads: usize,
+    pub final_reads: usize,
+    /// Final reads returning a value outside the range the counter could have had.
+    pub errors: Vec<CounterError>,
+    /// Reads (by op index) returning less than a previous read served by the same node.
+    pub nonmonotonic: Vec<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CounterError {
+    pub op: usize,
+    pub value: usize,
+    pub lower: usize,
+    pub upper: usize,
+}
+
+impl CounterReport {
+    pub fn is_valid(&self) -> bool {
+        self.errors.is_empty() && self.nonmonotonic.is_empty()
+    }
+}
+
+/// Checks the reads of a g-counter history.
+///
+/// The counter is only eventually consistent, so bounds are checked on final reads,
+/// invoked at least `settle` after every add completed or timed out, once the nodes had
+/// time to converge: they must observe every acknowledged add, and at most every add that
+/// did not fail. Reads invoked earlier may lag behind.
+/// Every node must serve non-decreasing values.
+pub fn check_g_counter(history: &History<GoCounterPayload>, settle: Duration) -> CounterReport {
+    let mut report = CounterReport::default();
+    let adds = history
+        .ops()
+        .iter()
+        .filter_map(|op| match (&op.request, &op.outcome) {
+            (GoCounterPayload::Add { .. }, Outcome::Fail) => None,
+            (GoCounterPayload::Add { delta }, _) => Some((op, *delta)),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    let lower = adds
+        .iter()
+        .filter(|(add, _)| matches!(add.outcome, Outcome::Ok(_)))
+        .map(|(_, delta)| delta)
+        .sum::<usize>();
+    let upper = adds.iter().map(|(_, delta)| delta).sum::<usize>();
+
+    let mut reads = history
+        .ops()
+        .iter()
+        .enumerate()
+        .filter_map(|(i, op)| match (&op.request, &op.outcome) {
+            (GoCounterPayload::Read, Outcome::Ok(GoCounterPayload::ReadOk { value })) => {
+                Some((i, op, *value))
+            }
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    reads.sort_by_key(|(_, op, _)| op.invoked_at);
+
+    let mut last_by_node: HashMap<&str, (&Operation<GoCounterPayload>, usize)> = HashMap::new();
+    for (i, op, value) in reads {
+        report.reads += 1;
+
+        if let Some((last, last_value)) = last_by_node.get(op.node.as_str())
+            && last.precedes(op)
+            && value < *last_value
+        {
+            report.nonmonotonic.push(i);
+        }
+        last_by_node.insert(&op.node, (op, value));
+
+        let settled = adds
+            .iter()
+            .all(|(add, _)| add.completed_at.unwrap_or(add.invoked_at) + settle <= op.invoked_at);
+        if !settled {
+            continue;
+        }
+        report.final_reads += 1;
+        if value < lower || value > upper {
+            report.errors.push(CounterError {
+                op: i,
+                value,
+                lower,
+                upper,
+            });
+        }
+    }
+
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn add(history: &mut History<GoCounterPayload>, at: u64, delta: usize, outcome: Outcome<()>) {
+        let index = history.invoke(
+            "c1",
+            "n0",
+            Duration::from_millis(at),
+            GoCounterPayload::Add { delta },
+        );
+        match outcome {
+            Outcome::Ok(()) => history.complete(
+                index,
+                Duration::from_millis(at + 1),
+                GoCounterPayload::AddOk,
+            ),
+            Outcome::Fail => history.fail(index, Duration::from_millis(at + 1)),
+            Outcome::Info => {}
+        }
+    }
+
+    fn read(history: &mut History<GoCounterPayload>, at: u64, node: &str, value: usize) {
+        let index = history.invoke(
+            "c2",
+            node,
+            Duration::from_millis(at),
+            GoCounterPayload::Read,
+        );
+        history.complete(
+            index,
+            Duration::from_millis(at + 1),
+            GoCounterPayload::ReadOk { value },
+        );
+    }
+
+    #[test]
+    fn final_reads_must_see_every_acknowledged_add() {
+        let mut history = History::new();
+        add(&mut history, 0, 2, Outcome::Ok(()));
+        add(&mut history, 2, 3, Outcome::Ok(()));
+        read(&mut history, 1, "n0", 2);
+        read(&mut history, 10, "n0", 5);
+        read(&mut history, 10, "n1", 4);
+
+        let report = check_g_counter(&history, Duration::ZERO);
+        assert_eq!((report.reads, report.final_reads), (3, 2));
+        assert_eq!(
+            report.errors,
+            vec![CounterError {
+                op: 4,
+                value: 4,
+                lower: 5,
+                upper: 5,
+            }]
+        );
+    }
+
+    #[test]
+    fn adds_that_timed_out_may_or_may_not_be_counted() {
+        let mut history = History::new();
+        add(&mut history, 0, 2, Outcome::Ok(()));
+        add(&mut history, 2, 3, Outcome::Info);
+        add(&mut history, 4, 7, Outcome::Fail);
+        read(&mut history, 10, "n0", 2);
+        read(&mut history, 10, "n1", 5);
+
+        let report = check_g_counter(&history, Duration::ZERO);
+        assert!(report.is_valid(), "{:?}", report);
+        read(&mut history, 20, "n0", 9);
+        assert_eq!(check_g_counter(&history, Duration::ZERO).errors.len(), 1);
+    }
+
+    #[test]
+    fn reports_a_node_serving_a_smaller_value_than_before() {
+        let mut history = History::new();
+        add(&mut history, 0, 2, Outcome::Ok(()));
+        read(&mut history, 2, "n0", 2);
+        read(&mut history, 4, "n0", 1);
+        read(&mut history, 4, "n1", 0);
+
+        let report = check_g_counter(&history, Duration::ZERO);
+        assert_eq!(report.nonmonotonic, vec![2]);
+    }
+
+    #[test]
+    fn reads_before_the_settle_period_may_be_stale() {
+        let mut history = History::new();
+        add(&mut history, 0, 2, Outcome::Ok(()));
+        add(&mut history, 2, 3, Outcome::Ok(()));
+        read(&mut history, 4, "n1", 2);
+        read(&mut history, 20, "n1", 5);
+
+        let settle = Duration::from_millis(10);
+        let report = check_g_counter(&history, settle);
+        assert_eq!((report.reads, report.final_reads), (2, 1));
+        assert!(report.is_valid(), "{:?}", report);
+
+        read(&mut history, 13, "n0", 2);
+        assert_eq!(check_g_counter(&history, settle).errors.len(), 1);
+    }
+}
@@ -0,0 +1,309 @@
+use crate::checker::{History, Outcome};
+use crate::payloads::KafkaLogPayload;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+#[derive(Debug, Clone, Default)]
+pub struct KafkaReport {
+    pub sends: usize,
+    pub polls: usize,
+    /// Offsets assigned to more than one acknowledged send.
+    pub duplicate_offsets: BTreeSet<(String, usize)>,
+    /// Offsets at which sends and polls disagree on the message.
+    pub inconsistent_offsets: BTreeSet<(String, usize)>,
+    /// Polls (by op index) returning offsets of a key out of order.
+    pub nonmonotonic_polls: BTreeSet<(usize, String)>,
+    /// Acknowledged offsets a poll jumped over.
+    pub skipped: BTreeSet<(usize, String, usize)>,
+    /// Acknowledged offsets below the highest polled offset of their key that no poll returned.
+    pub lost_writes: BTreeSet<(String, usize)>,
+    /// Highest low-water mark polls reported in `out_of_range` for each key: the offsets
+    /// below it were dropped by retention, and are neither skipped nor lost.
+    pub low_water_marks: BTreeMap<String, usize>,
+    /// Highest end of the compacted segments polls reported in `compacted` for each key: the
+    /// offsets below it no poll returned were compacted away, and are not lost.
+    pub compacted_ends: BTreeMap<String, usize>,
+}
+
+impl KafkaReport {
+    pub fn is_valid(&self) -> bool {
+        self.duplicate_offsets.is_empty()
+            && self.inconsistent_offsets.is_empty()
+            && self.nonmonotonic_polls.is_empty()
+            && self.skipped.is_empty()
+            && self.lost_writes.is_empty()
+    }
+}
+
+/// Checks offsets, ordering and durability of a kafka workload history.
+pub fn check_kafka(history: &History<KafkaLogPayload>) -> KafkaReport {
+    let mut report = KafkaReport::default();
+    let mut acked_by_key: HashMap<&str, BTreeMap<usize, usize>> = HashMap::new();
+    let mut polled_by_key: HashMap<&str, BTreeMap<usize, usize>> = HashMap::new();
+    let mut polls = Vec::new();
+
+    for (i, op) in history.ops().iter().enumerate() {
+        match (&op.request, &op.outcome) {
+            (
+                KafkaLogPayload::Send { key, msg },
+                Outcome::Ok(KafkaLogPayload::SendOk { offset }),
+            ) => {
+                report.sends += 1;
+                let acked = acked_by_key.entry(key).or_default();
+                if acked.insert(*offset, *msg).is_some() {
+                    report.duplicate_offsets.insert((key.clone(), *offset));
+                }
+            }
+            (
+                KafkaLogPayload::Poll { offsets },
+                Outcome::Ok(KafkaLogPayload::PollOk {
+                    msgs,
+                    out_of_range,
+                    compacted,
+                }),
+            ) => {
+                report.polls += 1;
+                polls.push((i, offsets, msgs, compacted));
+                for (key, low_water_mark) in out_of_range {
+                    let known = report.low_water_marks.entry(key.clone()).or_default();
+                    *known = (*known).max(*low_water_mark);
+                }
+                for (key, compacted_end) in compacted {
+                    let known = report.compacted_ends.entry(key.clone()).or_default();
+                    *known = (*known).max(*compacted_end);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    for (i, _, msgs, _) in &polls {
+        for (key, key_msgs) in *msgs {
+            if key_msgs.windows(2).any(|pair| pair[0].0 >= pair[1].0) {
+                report.nonmonotonic_polls.insert((*i, key.clone()));
+            }
+
+            let polled = polled_by_key.entry(key).or_default();
+            for (offset, msg) in key_msgs {
+                let acked_msg = acked_by_key
+                    .get(key.as_str())
+                    .and_then(|acked| acked.get(offset));
+                let polled_msg = polled.insert(*offset, *msg);
+                if acked_msg.is_some_and(|acked_msg| acked_msg != msg)
+                    || polled_msg.is_some_and(|polled_msg| polled_msg != *msg)
+                {
+                    report.inconsistent_offsets.insert((key.clone(), *offset));
+                }
+            }
+        }
+    }
+
+    for (i, requested, msgs, compacted) in &polls {
+        for (key, key_msgs) in *msgs {
+            let Some(acked) = acked_by_key.get(key.as_str()) else {
+                continue;
+            };
+            let low_water_mark = report.low_water_marks.get(key).copied().unwrap_or(0);
+            let compacted_end = compacted.get(key).copied().unwrap_or(0);
+            let mut expected_from = requested.get(key).copied().unwrap_or(0).max(low_water_mark);
+            for (offset, _) in key_msgs {
+                if *offset < expected_from {
+                    continue;
+                }
+                let skipped = acked
+                    .range(expected_from..*offset)
+                    .filter(|(skipped, _)| **skipped >= compacted_end);
+                for (skipped, _) in skipped {
+                    report.skipped.insert((*i, key.clone(), *skipped));
+                }
+                expected_from = offset + 1;
+            }
+        }
+    }
+
+    for (key, acked) in &acked_by_key {
+        let polled = polled_by_key.get(key);
+        let Some(max_polled) = polled.and_then(|polled| polled.keys().next_back()) else {
+            continue;
+        };
+        let low_water_mark = report.low_water_marks.get(*key).copied().unwrap_or(0);
+        let compacted_end = report.compacted_ends.get(*key).copied().unwrap_or(0);
+        let kept_from = low_water_mark.max(compacted_end).min(*max_polled);
+        for offset in acked
+            .range(kept_from..*max_polled)
+            .map(|(offset, _)| offset)
+        {
+            if !polled.is_some_and(|polled| polled.contains_key(offset)) {
+                report.lost_writes.insert((key.to_string(), *offset));
+            }
+        }
+    }
+
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn send(history: &mut History<KafkaLogPayload>, at: u64, key: &str, msg: usize, offset: usize) {
+        let index = history.invoke(
+            "c1",
+            "n0",
+            Duration::from_millis(at),
+            KafkaLogPayload::Send {
+                key: key.to_string(),
+                msg,
+            },
+        );
+        history.complete(
+            index,
+            Duration::from_millis(at + 1),
+            KafkaLogPayload::SendOk { offset },
+        );
+    }
+
+    fn poll(
+        history: &mut History<KafkaLogPayload>,
+        at: u64,
+        key: &str,
+        from: usize,
+        msgs: Vec<(usize, usize)>,
+        out_of_range: Option<usize>,
+    ) {
+        let index = history.invoke(
+            "c2",
+            "n0",
+            Duration::from_millis(at),
+            KafkaLogPayload::Poll {
+                offsets: HashMap::from([(key.to_string(), from)]),
+            },
+        );
+        let (msgs, out_of_range) = match out_of_range {
+            Some(low_water_mark) => (
+                HashMap::new(),
+                HashMap::from([(key.to_string(), low_water_mark)]),
+            ),
+            None => (HashMap::from([(key.to_string(), msgs)]), HashMap::new()),
+        };
+        history.complete(
+            index,
+            Duration::from_millis(at + 1),
+            KafkaLogPayload::PollOk {
+                msgs,
+                out_of_range,
+                compacted: HashMap::new(),
+            },
+        );
+    }
+
+    #[test]
+    fn accepts_polls_returning_every_acknowledged_send() {
+        let mut history = History::new();
+        send(&mut history, 0, "k", 10, 0);
+        send(&mut history, 2, "k", 11, 1);
+        send(&mut history, 4, "k", 12, 2);
+        poll(&mut history, 6, "k", 0, vec![(0, 10), (1, 11)], None);
+        poll(&mut history, 8, "k", 2, vec![(2, 12)], None);
+
+        let report = check_kafka(&history);
+        assert_eq!((report.sends, report.polls), (3, 2));
+        assert!(report.is_valid(), "{:?}", report);
+    }
+
+    #[test]
+    fn reports_duplicate_offsets_and_inconsistent_messages() {
+        let mut history = History::new();
+        send(&mut history, 0, "k", 10, 0);
+        send(&mut history, 2, "k", 11, 0);
+        poll(&mut history, 4, "k", 0, vec![(0, 12)], None);
+
+        let report = check_kafka(&history);
+        assert_eq!(
+            report.duplicate_offsets,
+            BTreeSet::from([("k".to_string(), 0)])
+        );
+        assert_eq!(
+            report.inconsistent_offsets,
+            BTreeSet::from([("k".to_string(), 0)])
+        );
+    }
+
+    #[test]
+    fn reports_skipped_and_lost_offsets() {
+        let mut history = History::new();
+        send(&mut history, 0, "k", 10, 0);
+        send(&mut history, 2, "k", 11, 1);
+        send(&mut history, 4, "k", 12, 2);
+        poll(&mut history, 6, "k", 0, vec![(0, 10), (2, 12)], None);
+
+        let report = check_kafka(&history);
+        assert_eq!(report.skipped, BTreeSet::from([(3, "k".to_string(), 1)]));
+        assert_eq!(report.lost_writes, BTreeSet::from([("k".to_string(), 1)]));
+        assert!(!report.is_valid());
+    }
+
+    #[test]
+    fn reports_polls_out_of_order() {
+        let mut history = History::new();
+        send(&mut history, 0, "k", 10, 0);
+        send(&mut history, 2, "k", 11, 1);
+        poll(&mut history, 4, "k", 0, vec![(1, 11), (0, 10)], None);
+
+        let report = check_kafka(&history);
+        assert_eq!(
+            report.nonmonotonic_polls,
+            BTreeSet::from([(2, "k".to_string())])
+        );
+    }
+
+    #[test]
+    fn offsets_below_a_reported_low_water_mark_are_not_lost() {
+        let mut history = History::new();
+        for offset in 0..4 {
+            send(&mut history, offset as u64 * 2, "k", 10 + offset, offset);
+        }
+        poll(&mut history, 10, "k", 0, vec![], Some(2));
+        poll(&mut history, 12, "k", 2, vec![(2, 12), (3, 13)], None);
+
+        let report = check_kafka(&history);
+        assert_eq!(
+            report.low_water_marks,
+            BTreeMap::from([("k".to_string(), 2)])
+        );
+        assert!(report.is_valid(), "{:?}", report);
+    }
+
+    #[test]
+    fn offsets_below_a_reported_compacted_end_are_neither_skipped_nor_lost() {
+        let mut history = History::new();
+        for offset in 0..4 {
+            send(&mut history, offset as u64 * 2, "k", 10 + offset, offset);
+        }
+        let index = history.invoke(
+            "c2",
+            "n0",
+            Duration::from_millis(10),
+            KafkaLogPayload::Poll {
+                offsets: HashMap::from([("k".to_string(), 0)]),
+            },
+        );
+        history.complete(
+            index,
+            Duration::from_millis(11),
+            KafkaLogPayload::PollOk {
+                msgs: HashMap::from([("k".to_string(), vec![(1, 11), (3, 13)])]),
+                out_of_range: HashMap::new(),
+                compacted: HashMap::from([("k".to_string(), 2)]),
+            },
+        );
+
+        let report = check_kafka(&history);
+        assert_eq!(
+            report.compacted_ends,
+            BTreeMap::from([("k".to_string(), 2)])
+        );
+        assert_eq!(report.skipped, BTreeSet::from([(4, "k".to_string(), 2)]));
+        assert_eq!(report.lost_writes, BTreeSet::from([("k".to_string(), 2)]));
+    }
+}
@@ -0,0 +1,353 @@
+use crate::checker::{History, Outcome};
+use crate::payloads::{TxnOperation, TxnPayload};
+use std::collections::{HashMap, HashSet, VecDeque};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConsistencyModel {
+    ReadUncommitted,
+    ReadCommitted,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TxnAnomaly {
+    /// A cycle of write-write dependencies between transactions (op indices).
+    G0 { cycle: Vec<usize> },
+    /// A committed transaction read a value written by an aborted one.
+    G1a {
+        reader: usize,
+        writer: usize,
+        key: usize,
+        value: usize,
+    },
+    /// A committed transaction read a value another transaction later overwrote itself.
+    G1b {
+        reader: usize,
+        writer: usize,
+        key: usize,
+        value: usize,
+    },
+    /// A cycle of write-write and write-read dependencies containing at least one write-read.
+    G1c { cycle: Vec<usize> },
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct TxnReport {
+    pub committed: usize,
+    pub anomalies: Vec<TxnAnomaly>,
+}
+
+impl TxnReport {
+    pub fn is_valid(&self, model: ConsistencyModel) -> bool {
+        self.anomalies.iter().all(|anomaly| match model {
+            ConsistencyModel::ReadUncommitted => !matches!(anomaly, TxnAnomaly::G0 { .. }),
+            ConsistencyModel::ReadCommitted => false,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Dependency {
+    WriteWrite,
+    WriteRead,
+}
+
+/// Detects G0, G1a, G1b and G1c anomalies in a txn-rw-register history.
+///
+/// Relies on every write of a key carrying a unique value, as Maelstrom's workload does,
+/// to tell which transaction wrote the value a read observed. The version order of a key
+/// is inferred from transactions that read it before overwriting it.
+pub fn check_txn(history: &History<TxnPayload>) -> TxnReport {
+    let mut report = TxnReport::default();
+
+    let txns = history
+        .ops()
+        .iter()
+        .map(|op| {
+            let TxnPayload::Txn { txn } = &op.request else {
+                return None;
+            };
+            match &op.outcome {
+                Outcome::Ok(TxnPayload::TxnOk { txn }) => Some((txn, TxnStatus::Committed)),
+                Outcome::Ok(_) => None,
+                Outcome::Fail => Some((txn, TxnStatus::Aborted)),
+                Outcome::Info => Some((txn, TxnStatus::Unknown)),
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let mut writer_by_value: HashMap<(usize, usize), Write> = HashMap::new();
+    for (i, (txn, status)) in txns
+        .iter()
+        .enumerate()
+        .filter_map(|(i, t)| Some((i, t.as_ref()?)))
+    {
+        let last_writes = final_writes(txn);
+        for op in txn.iter() {
+            if let TxnOperation::Write { key, value } = op {
+                writer_by_value.insert(
+                    (*key, *value),
+                    Write {
+                        op: i,
+                        status: *status,
+                        is_final: last_writes.get(key) == Some(value),
+                    },
+                );
+            }
+        }
+    }
+
+    let mut edges: Vec<(usize, usize, Dependency)> = Vec::new();
+    for (i, (txn, status)) in txns
+        .iter()
+        .enumerate()
+        .filter_map(|(i, t)| Some((i, t.as_ref()?)))
+    {
+        if *status != TxnStatus::Committed {
+            continue;
+        }
+        report.committed += 1;
+
+        let mut written_keys = HashSet::new();
+        let mut read_keys = HashSet::new();
+        for op in txn.iter() {
+            match op {
+                TxnOperation::Write { key, .. } => {
+                    written_keys.insert(*key);
+                }
+                TxnOperation::Read { key, value } => {
+                    if written_keys.contains(key) || !read_keys.insert(*key) {
+                        continue;
+                    }
+                    let Some(value) = value else {
+                        continue;
+                    };
+                    let Some(write) = writer_by_value.get(&(*key, *value)) else {
+                        continue;
+                    };
+                    if write.op == i {
+                        continue;
+                    }
+
+                    match write.status {
+                        TxnStatus::Aborted => report.anomalies.push(TxnAnomaly::G1a {
+                            reader: i,
+                            writer: write.op,
+                            key: *key,
+                            value: *value,
+                        }),
+                        _ if !write.is_final => report.anomalies.push(TxnAnomaly::G1b {
+                            reader: i,
+                            writer: write.op,
+                            key: *key,
+                            value: *value,
+                        }),
+                        _ => edges.push((write.op, i, Dependency::WriteRead)),
+                    }
+
+                    let overwrites = txn.iter().any(
+                        |op| matches!(op, TxnOperation::Write { key: written, .. } if written == key),
+                    );
+                    if overwrites && write.status != TxnStatus::Aborted {
+                        edges.push((write.op, i, Dependency::WriteWrite));
+                    }
+                }
+            }
+        }
+    }
+
+    let ww_edges = edges
+        .iter()
+        .filter(|(_, _, dependency)| *dependency == Dependency::WriteWrite)
+        .map(|(from, to, _)| (*from, *to))
+        .collect::<Vec<_>>();
+    let all_edges = edges
+        .iter()
+        .map(|(from, to, _)| (*from, *to))
+        .collect::<Vec<_>>();
+
+    if let Some(cycle) = ww_edges
+        .iter()
+        .find_map(|edge| find_cycle_through(*edge, &ww_edges))
+    {
+        report.anomalies.push(TxnAnomaly::G0 { cycle });
+    }
+    if let Some(cycle) = edges
+        .iter()
+        .filter(|(_, _, dependency)| *dependency == Dependency::WriteRead)
+        .find_map(|(from, to, _)| find_cycle_through((*from, *to), &all_edges))
+    {
+        report.anomalies.push(TxnAnomaly::G1c { cycle });
+    }
+
+    report
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TxnStatus {
+    Committed,
+    Aborted,
+    Unknown,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Write {
+    op: usize,
+    status: TxnStatus,
+    is_final: bool,
+}
+
+fn final_writes(txn: &[TxnOperation]) -> HashMap<usize, usize> {
+    txn.iter()
+        .filter_map(|op| match op {
+            TxnOperation::Write { key, value } => Some((*key, *value)),
+            TxnOperation::Read { .. } => None,
+        })
+        .collect()
+}
+
+/// Returns the cycle `from -> to -> ... -> from` if `from` is reachable from `to`.
+fn find_cycle_through((from, to): (usize, usize), edges: &[(usize, usize)]) -> Option<Vec<usize>> {
+    let mut parent_by_node = HashMap::from([(to, to)]);
+    let mut queue = VecDeque::from([to]);
+    while let Some(node) = queue.pop_front() {
+        if node == from {
+            let mut cycle = vec![from];
+            let mut current = from;
+            while current != to {
+                current = parent_by_node[&current];
+                cycle.push(current);
+            }
+            cycle.reverse();
+            cycle.insert(0, from);
+            cycle.pop();
+            return Some(cycle);
+        }
+        for (_, next) in edges.iter().filter(|(edge_from, _)| *edge_from == node) {
+            if !parent_by_node.contains_key(next) {
+                parent_by_node.insert(*next, node);
+                queue.push_back(*next);
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn r(key: usize, value: usize) -> TxnOperation {
+        TxnOperation::Read {
+            key,
+            value: Some(value),
+        }
+    }
+
+    fn w(key: usize, value: usize) -> TxnOperation {
+        TxnOperation::Write { key, value }
+    }
+
+    fn txn(
+        history: &mut History<TxnPayload>,
+        at: u64,
+        txn: Vec<TxnOperation>,
+        outcome: Outcome<()>,
+    ) {
+        let index = history.invoke(
+            "c1",
+            "n0",
+            Duration::from_millis(at),
+            TxnPayload::Txn { txn: txn.clone() },
+        );
+        match outcome {
+            Outcome::Ok(()) => history.complete(
+                index,
+                Duration::from_millis(at + 1),
+                TxnPayload::TxnOk { txn },
+            ),
+            Outcome::Fail => history.fail(index, Duration::from_millis(at + 1)),
+            Outcome::Info => {}
+        }
+    }
+
+    #[test]
+    fn accepts_reads_of_committed_final_writes() {
+        let mut history = History::new();
+        txn(&mut history, 0, vec![w(1, 10), w(2, 20)], Outcome::Ok(()));
+        txn(&mut history, 2, vec![r(1, 10), w(1, 11)], Outcome::Ok(()));
+        txn(&mut history, 4, vec![r(1, 11), r(2, 20)], Outcome::Ok(()));
+
+        let report = check_txn(&history);
+        assert_eq!(report.committed, 3);
+        assert!(report.anomalies.is_empty(), "{:?}", report.anomalies);
+    }
+
+    #[test]
+    fn reports_reads_of_aborted_and_intermediate_writes() {
+        let mut history = History::new();
+        txn(&mut history, 0, vec![w(1, 10)], Outcome::Fail);
+        txn(&mut history, 2, vec![w(2, 20), w(2, 21)], Outcome::Ok(()));
+        txn(&mut history, 4, vec![r(1, 10), r(2, 20)], Outcome::Ok(()));
+
+        let report = check_txn(&history);
+        assert_eq!(
+            report.anomalies,
+            vec![
+                TxnAnomaly::G1a {
+                    reader: 2,
+                    writer: 0,
+                    key: 1,
+                    value: 10,
+                },
+                TxnAnomaly::G1b {
+                    reader: 2,
+                    writer: 1,
+                    key: 2,
+                    value: 20,
+                },
+            ]
+        );
+        assert!(!report.is_valid(ConsistencyModel::ReadCommitted));
+    }
+
+    #[test]
+    fn reports_write_cycles_as_g0() {
+        let mut history = History::new();
+        txn(
+            &mut history,
+            0,
+            vec![r(2, 20), w(2, 21), w(1, 10)],
+            Outcome::Ok(()),
+        );
+        txn(
+            &mut history,
+            0,
+            vec![r(1, 10), w(1, 11), w(2, 20)],
+            Outcome::Ok(()),
+        );
+
+        let report = check_txn(&history);
+        assert!(
+            report
+                .anomalies
+                .contains(&TxnAnomaly::G0 { cycle: vec![1, 0] })
+        );
+        assert!(!report.is_valid(ConsistencyModel::ReadUncommitted));
+    }
+
+    #[test]
+    fn read_cycles_are_g1c_but_allowed_by_read_uncommitted() {
+        let mut history = History::new();
+        txn(&mut history, 0, vec![w(1, 10), r(2, 20)], Outcome::Ok(()));
+        txn(&mut history, 0, vec![w(2, 20), r(1, 10)], Outcome::Ok(()));
+
+        let report = check_txn(&history);
+        assert_eq!(
+            report.anomalies,
+            vec![TxnAnomaly::G1c { cycle: vec![1, 0] }]
+        );
+        assert!(report.is_valid(ConsistencyModel::ReadUncommitted));
+        assert!(!report.is_valid(ConsistencyModel::ReadCommitted));
+    }
+}
@@ -0,0 +1,56 @@
+//! Node settings read at startup.
+//!
+//! A setting `name` is taken from the `--name <value>` (or `--name=<value>`) command line
+//! flag, else from the `RUSTORM_NAME` environment variable, with dashes replaced by
+//! underscores. Maelstrom starts nodes without arguments, so the environment variable is
+//! the way to set them under `maelstrom test`.
+
+use anyhow::Context;
+use std::fmt::Display;
+use std::str::FromStr;
+use std::time::Duration;
+
+pub fn value(name: &str) -> Option<String> {
+    let flag = format!("--{}", name);
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == flag {
+            return args.next();
+        }
+        if let Some(value) = arg
+            .strip_prefix(&flag)
+            .and_then(|rest| rest.strip_prefix('='))
+        {
+            return Some(value.to_string());
+        }
+    }
+    std::env::var(env_var(name)).ok()
+}
+
+pub fn parse<T>(name: &str) -> anyhow::Result<Option<T>>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    value(name)
+        .map(|value| {
+            value
+                .parse::<T>()
+                .map_err(|err| anyhow::anyhow!("{}", err))
+                .with_context(|| format!("invalid --{} / {}: {}", name, env_var(name), value))
+        })
+        .transpose()
+}
+
+/// A duration given in milliseconds, e.g. `--gossip-interval-ms 500`.
+pub fn millis(name: &str) -> anyhow::Result<Option<Duration>> {
+    Ok(parse::<u64>(name)?.map(Duration::from_millis))
+}
+
+pub fn millis_or(name: &str, default: Duration) -> anyhow::Result<Duration> {
+    Ok(millis(name)?.unwrap_or(default))
+}
+
+fn env_var(name: &str) -> String {
+    format!("RUSTORM_{}", name.to_uppercase().replace('-', "_"))
+}
@@ -0,0 +1,139 @@
+pub mod anti_entropy;
+pub mod gset;
+pub mod intervals;
+pub mod lww;
+pub mod orset;
+pub mod pncounter;
+
+pub use anti_entropy::AntiEntropy;
+pub use gset::GSet;
+pub use intervals::IntervalSet;
+pub use lww::LwwRegister;
+pub use orset::OrSet;
+pub use pncounter::{GCounter, PnCounter};
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+
+/// State-based CRDT: replicas converge by merging each other's states.
+///
+/// `merge` must be commutative, associative and idempotent.
+pub trait Crdt:
+    Debug + Clone + Default + PartialEq + Send + Serialize + DeserializeOwned + 'static
+{
+    fn merge(&mut self, other: Self);
+
+    /// The part of this state `known` is missing: merging it into `known`
+    /// gives the same result as merging the whole state.
+    fn delta(&self, known: &Self) -> Self;
+}
+
+/// How a CRDT is exposed to Maelstrom clients through `add` and `read` requests.
+pub trait CrdtWorkload: Crdt {
+    /// Fields of an `add` request, e.g. `{"delta": 1}` or `{"element": 1}`.
+    type Add: Debug + Clone + Send + Serialize + DeserializeOwned + 'static;
+    /// `value` of a `read_ok` reply.
+    type Value: Debug + Clone + Send + Serialize + DeserializeOwned + 'static;
+
+    /// Applies an `add` request and returns its delta.
+    fn add(&mut self, node_id: &str, add: Self::Add) -> Self;
+    fn value(&self) -> Self::Value;
+}
+
+/// Count of events seen from each node.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VersionVector {
+    counters: BTreeMap<String, u64>,
+}
+
+impl VersionVector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, node_id: &str) -> u64 {
+        self.counters.get(node_id).copied().unwrap_or(0)
+    }
+
+    /// Records a new event of `node_id` and returns its counter.
+    pub fn increment(&mut self, node_id: &str) -> u64 {
+        let counter = self.counters.entry(node_id.to_string()).or_default();
+        *counter += 1;
+        *counter
+    }
+
+    /// Whether every event seen by `other` was seen by `self` too.
+    pub fn dominates(&self, other: &VersionVector) -> bool {
+        other
+            .counters
+            .iter()
+            .all(|(node_id, counter)| self.get(node_id) >= *counter)
+    }
+
+    pub fn concurrent(&self, other: &VersionVector) -> bool {
+        !self.dominates(other) && !other.dominates(self)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &u64)> {
+        self.counters.iter()
+    }
+}
+
+impl Crdt for VersionVector {
+    fn merge(&mut self, other: Self) {
+        for (node_id, counter) in other.counters {
+            let local = self.counters.entry(node_id).or_default();
+            *local = (*local).max(counter);
+        }
+    }
+
+    fn delta(&self, known: &Self) -> Self {
+        Self {
+            counters: self
+                .counters
+                .iter()
+                .filter(|(node_id, counter)| known.get(node_id) < **counter)
+                .map(|(node_id, counter)| (node_id.clone(), *counter))
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vector(counters: &[(&str, u64)]) -> VersionVector {
+        VersionVector {
+            counters: counters
+                .iter()
+                .map(|(node_id, counter)| (node_id.to_string(), *counter))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn version_vectors_are_ordered_by_dominance() {
+        let a = vector(&[("n0", 2), ("n1", 1)]);
+        let b = vector(&[("n0", 1)]);
+        let c = vector(&[("n1", 2)]);
+
+        assert!(a.dominates(&b) && !b.dominates(&a));
+        assert!(a.concurrent(&c));
+        assert!(VersionVector::new().dominates(&VersionVector::new()));
+    }
+
+    #[test]
+    fn merging_version_vectors_takes_the_max_of_each_counter() {
+        let mut a = vector(&[("n0", 2), ("n1", 1)]);
+        let b = vector(&[("n1", 3), ("n2", 1)]);
+
+        let delta = b.delta(&a);
+        assert_eq!(delta, vector(&[("n1", 3), ("n2", 1)]));
+        a.merge(delta);
+        assert_eq!(a, vector(&[("n0", 2), ("n1", 3), ("n2", 1)]));
+        assert_eq!(a.delta(&a), VersionVector::new());
+    }
+}
@@ -0,0 +1,265 @@
+use crate::crdt::Crdt;
+use std::collections::{BTreeMap, HashMap};
+
+/// Rounds without an acknowledgement after which a peer stops holding back garbage
+/// collection. It will be sent the full state once it answers again.
+const MAX_UNACKED_ROUNDS: usize = 5;
+
+/// Delta-state anti-entropy between a replica and its peers.
+///
+/// Local updates and the new part of received deltas are buffered under increasing
+/// sequence numbers. Each gossip round sends a peer the join of the deltas after the
+/// last sequence number it acknowledged; deltas acknowledged by every responsive peer
+/// are dropped. A peer that missed dropped deltas, e.g. after a partition heals,
+/// is sent the full state instead.
+#[derive(Debug, Clone)]
+pub struct AntiEntropy<C>
+where
+    C: Crdt,
+{
+    seq: u64,
+    /// Highest sequence number whose delta was dropped.
+    collected: u64,
+    deltas: BTreeMap<u64, BufferedDelta<C>>,
+    peers: HashMap<String, PeerProgress>,
+}
+
+#[derive(Debug, Clone)]
+struct BufferedDelta<C> {
+    /// Peer the delta came from, that does not need it back.
+    origin: Option<String>,
+    delta: C,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct PeerProgress {
+    acked: u64,
+    unacked_rounds: usize,
+}
+
+impl<C> AntiEntropy<C>
+where
+    C: Crdt,
+{
+    pub fn new(peers: impl IntoIterator<Item = String>) -> Self {
+        Self {
+            seq: 0,
+            collected: 0,
+            deltas: BTreeMap::new(),
+            peers: peers
+                .into_iter()
+                .map(|peer| (peer, PeerProgress::default()))
+                .collect(),
+        }
+    }
+
+    /// Replaces the peers to gossip with, keeping the progress of those still present.
+    pub fn set_peers(&mut self, peers: impl IntoIterator<Item = String>) {
+        let mut progress_by_peer = std::mem::take(&mut self.peers);
+        self.peers = peers
+            .into_iter()
+            .map(|peer| {
+                let progress = progress_by_peer.remove(&peer).unwrap_or_default();
+                (peer, progress)
+            })
+            .collect();
+        self.collect_garbage();
+    }
+
+    pub fn peers(&self) -> impl Iterator<Item = &String> {
+        self.peers.keys()
+    }
+
+    /// Peers that stopped acknowledging gossip.
+    pub fn lagging(&self) -> impl Iterator<Item = &String> {
+        self.peers
+            .iter()
+            .filter(|(_, progress)| progress.unacked_rounds > MAX_UNACKED_ROUNDS)
+            .map(|(peer, _)| peer)
+    }
+
+    /// Buffers the delta of a local update, already applied to the state.
+    pub fn record(&mut self, delta: C) {
+        self.buffer(None, delta);
+    }
+
+    /// Merges a delta received from `peer` into `state`.
+    pub fn receive(&mut self, state: &mut C, peer: &str, delta: C) {
+        let new = delta.delta(state);
+        if new == C::default() {
+            return;
+        }
+        state.merge(new.clone());
+        self.buffer(Some(peer.to_string()), new);
+    }
+
+    /// The gossip to send this round: each peer missing updates with the delta it lacks,
+    /// tagged with the sequence number to acknowledge.
+    pub fn outgoing(&mut self, state: &C) -> Vec<(String, u64, C)> {
+        self.outgoing_to(state, |_| true)
+    }
+
+    /// Like [`AntiEntropy::outgoing`], for the peers matching `filter` only.
+    pub fn outgoing_to(
+        &mut self,
+        state: &C,
+        filter: impl Fn(&str) -> bool,
+    ) -> Vec<(String, u64, C)> {
+        let mut outgoing = Vec::new();
+        for (peer, progress) in &mut self.peers {
+            if progress.acked >= self.seq || !filter(peer) {
+                continue;
+            }
+            if progress.acked < self.collected {
+                progress.unacked_rounds += 1;
+                outgoing.push((peer.clone(), self.seq, state.clone()));
+                continue;
+            }
+
+            let mut join = C::default();
+            for buffered in self.deltas.range(progress.acked + 1..).map(|(_, d)| d) {
+                if buffered.origin.as_ref() != Some(peer) {
+                    join.merge(buffered.delta.clone());
+                }
+            }
+            if join == C::default() {
+                progress.acked = self.seq;
+                continue;
+            }
+            progress.unacked_rounds += 1;
+            outgoing.push((peer.clone(), self.seq, join));
+        }
+        self.collect_garbage();
+        outgoing
+    }
+
+    pub fn ack(&mut self, peer: &str, upto: u64) {
+        let Some(progress) = self.peers.get_mut(peer) else {
+            return;
+        };
+        progress.acked = progress.acked.max(upto);
+        progress.unacked_rounds = 0;
+        self.collect_garbage();
+    }
+
+    /// Number of deltas waiting for an acknowledgement.
+    pub fn buffered(&self) -> usize {
+        self.deltas.len()
+    }
+
+    fn buffer(&mut self, origin: Option<String>, delta: C) {
+        self.seq += 1;
+        self.deltas
+            .insert(self.seq, BufferedDelta { origin, delta });
+        self.collect_garbage();
+    }
+
+    fn collect_garbage(&mut self) {
+        let acked_by_all = self
+            .peers
+            .values()
+            .filter(|progress| progress.unacked_rounds <= MAX_UNACKED_ROUNDS)
+            .map(|progress| progress.acked)
+            .min()
+            .unwrap_or(self.seq);
+        if acked_by_all <= self.collected {
+            return;
+        }
+        self.deltas = self.deltas.split_off(&(acked_by_all + 1));
+        self.collected = acked_by_all;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crdt::GSet;
+
+    fn anti_entropy(peers: &[&str]) -> AntiEntropy<GSet<usize>> {
+        AntiEntropy::new(peers.iter().map(|peer| peer.to_string()))
+    }
+
+    #[test]
+    fn sends_each_peer_the_deltas_it_did_not_acknowledge() {
+        let mut anti_entropy = anti_entropy(&["n1", "n2"]);
+        let mut state = GSet::new();
+        for element in [1, 2] {
+            state.insert(element);
+            anti_entropy.record(GSet::from_iter([element]));
+        }
+
+        let mut outgoing = anti_entropy.outgoing(&state);
+        outgoing.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(
+            outgoing,
+            vec![
+                ("n1".to_string(), 2, GSet::from_iter([1, 2])),
+                ("n2".to_string(), 2, GSet::from_iter([1, 2])),
+            ]
+        );
+
+        anti_entropy.ack("n1", 2);
+        state.insert(3);
+        anti_entropy.record(GSet::from_iter([3]));
+        assert_eq!(anti_entropy.buffered(), 3);
+        let mut outgoing = anti_entropy.outgoing(&state);
+        outgoing.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(
+            outgoing,
+            vec![
+                ("n1".to_string(), 3, GSet::from_iter([3])),
+                ("n2".to_string(), 3, GSet::from_iter([1, 2, 3])),
+            ]
+        );
+
+        anti_entropy.ack("n1", 3);
+        anti_entropy.ack("n2", 3);
+        assert_eq!(anti_entropy.buffered(), 0);
+        assert!(anti_entropy.outgoing(&state).is_empty());
+    }
+
+    #[test]
+    fn received_deltas_are_not_sent_back_to_their_origin() {
+        let mut anti_entropy = anti_entropy(&["n1", "n2"]);
+        let mut state = GSet::from_iter([1]);
+
+        anti_entropy.receive(&mut state, "n1", GSet::from_iter([1, 2]));
+        assert_eq!(state, GSet::from_iter([1, 2]));
+        anti_entropy.receive(&mut state, "n2", GSet::from_iter([2]));
+        assert_eq!(anti_entropy.buffered(), 1);
+
+        assert_eq!(
+            anti_entropy.outgoing(&state),
+            vec![("n2".to_string(), 1, GSet::from_iter([2]))]
+        );
+    }
+
+    #[test]
+    fn a_lagging_peer_is_sent_the_full_state() {
+        let mut anti_entropy = anti_entropy(&["n1", "n2"]);
+        let mut state = GSet::new();
+        for element in 0..=MAX_UNACKED_ROUNDS {
+            state.insert(element);
+            anti_entropy.record(GSet::from_iter([element]));
+            for (peer, upto, _) in anti_entropy.outgoing(&state) {
+                if peer == "n1" {
+                    anti_entropy.ack(&peer, upto);
+                }
+            }
+        }
+        assert_eq!(anti_entropy.lagging().collect::<Vec<_>>(), vec!["n2"]);
+        assert_eq!(anti_entropy.buffered(), 0);
+
+        state.insert(100);
+        anti_entropy.record(GSet::from_iter([100]));
+        let mut outgoing = anti_entropy.outgoing(&state);
+        outgoing.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(
+            outgoing,
+            vec![
+                ("n1".to_string(), 7, GSet::from_iter([100])),
+                ("n2".to_string(), 7, state.clone()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,133 @@
+use crate::crdt::{Crdt, CrdtWorkload};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fmt::Debug;
+
+/// Grow-only set.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(bound(deserialize = "T: DeserializeOwned + Ord"))]
+pub struct GSet<T>
+where
+    T: Ord,
+{
+    elements: BTreeSet<T>,
+}
+
+impl<T> Default for GSet<T>
+where
+    T: Ord,
+{
+    fn default() -> Self {
+        Self {
+            elements: BTreeSet::new(),
+        }
+    }
+}
+
+impl<T> GSet<T>
+where
+    T: Ord,
+{
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns whether `element` was not in the set yet.
+    pub fn insert(&mut self, element: T) -> bool {
+        self.elements.insert(element)
+    }
+
+    pub fn contains(&self, element: &T) -> bool {
+        self.elements.contains(element)
+    }
+
+    pub fn len(&self) -> usize {
+        self.elements.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.elements.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.elements.iter()
+    }
+}
+
+impl<T> FromIterator<T> for GSet<T>
+where
+    T: Ord,
+{
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        Self {
+            elements: iter.into_iter().collect(),
+        }
+    }
+}
+
+impl<T> IntoIterator for GSet<T>
+where
+    T: Ord,
+{
+    type Item = T;
+    type IntoIter = std::collections::btree_set::IntoIter<T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.elements.into_iter()
+    }
+}
+
+impl<T> Crdt for GSet<T>
+where
+    T: Debug + Clone + Ord + Send + Serialize + DeserializeOwned + 'static,
+{
+    fn merge(&mut self, other: Self) {
+        self.elements.extend(other.elements);
+    }
+
+    fn delta(&self, known: &Self) -> Self {
+        Self {
+            elements: self.elements.difference(&known.elements).cloned().collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AddElement<T> {
+    pub element: T,
+}
+
+impl<T> CrdtWorkload for GSet<T>
+where
+    T: Debug + Clone + Ord + Send + Serialize + DeserializeOwned + 'static,
+{
+    type Add = AddElement<T>;
+    type Value = Vec<T>;
+
+    fn add(&mut self, _node_id: &str, add: Self::Add) -> Self {
+        self.insert(add.element.clone());
+        Self::from_iter([add.element])
+    }
+
+    fn value(&self) -> Self::Value {
+        self.elements.iter().cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merge_is_a_union_and_delta_the_missing_elements() {
+        let mut a = GSet::from_iter([1, 2]);
+        let b = GSet::from_iter([2, 3]);
+
+        assert_eq!(b.delta(&a), GSet::from_iter([3]));
+        a.merge(b.clone());
+        assert_eq!(a, GSet::from_iter([1, 2, 3]));
+        a.merge(b);
+        assert_eq!(a.len(), 3);
+    }
+}
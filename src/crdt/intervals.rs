@@ -0,0 +1,249 @@
+use crate::crdt::Crdt;
+use serde::de::Deserializer;
+use serde::ser::{SerializeSeq, Serializer};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Grow-only set of integers stored as sorted, disjoint runs of consecutive values.
+///
+/// Sets of mostly consecutive values, such as the unique ids broadcast by Maelstrom,
+/// take a few runs instead of one entry per value. The set serializes as its runs,
+/// e.g. `[[0, 41], [43, 99]]`; [`as_elements`] serializes it as a plain array instead.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IntervalSet {
+    /// Inclusive end of each run, by start.
+    runs: BTreeMap<usize, usize>,
+    len: usize,
+}
+
+impl IntervalSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns whether `element` was not in the set yet.
+    pub fn insert(&mut self, element: usize) -> bool {
+        self.insert_run(element, element)
+    }
+
+    /// Inserts `start..=end`. Returns whether any of its values was not in the set yet.
+    pub fn insert_run(&mut self, start: usize, end: usize) -> bool {
+        if start > end {
+            return false;
+        }
+        let len = self.len;
+        let (mut new_start, mut new_end) = (start, end);
+        let touching = self
+            .runs
+            .range(..=end.saturating_add(1))
+            .rev()
+            .take_while(|(_, run_end)| run_end.saturating_add(1) >= start)
+            .map(|(run_start, run_end)| (*run_start, *run_end))
+            .collect::<Vec<_>>();
+        for (run_start, run_end) in touching {
+            self.runs.remove(&run_start);
+            self.len -= run_end - run_start + 1;
+            new_start = new_start.min(run_start);
+            new_end = new_end.max(run_end);
+        }
+        self.runs.insert(new_start, new_end);
+        self.len += new_end - new_start + 1;
+        self.len != len
+    }
+
+    pub fn contains(&self, element: &usize) -> bool {
+        self.runs
+            .range(..=element)
+            .next_back()
+            .is_some_and(|(_, run_end)| run_end >= element)
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.runs.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        self.runs().flat_map(|(start, end)| start..=end)
+    }
+
+    /// Inclusive bounds of each run, in increasing order.
+    pub fn runs(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        self.runs.iter().map(|(start, end)| (*start, *end))
+    }
+
+    /// The values of this set that are not in `other`.
+    pub fn difference(&self, other: &Self) -> Self {
+        let mut difference = Self::new();
+        for (start, end) in self.runs() {
+            let mut next = Some(start);
+            let first_overlapping = other
+                .runs
+                .range(..=start)
+                .next_back()
+                .map_or(start, |(other_start, _)| *other_start);
+            for (other_start, other_end) in other.runs.range(first_overlapping..=end) {
+                let Some(from) = next else {
+                    break;
+                };
+                if *other_end < from {
+                    continue;
+                }
+                if *other_start > from {
+                    difference.insert_run(from, other_start - 1);
+                }
+                next = other_end.checked_add(1);
+            }
+            if let Some(from) = next
+                && from <= end
+            {
+                difference.insert_run(from, end);
+            }
+        }
+        difference
+    }
+}
+
+impl Crdt for IntervalSet {
+    fn merge(&mut self, other: Self) {
+        for (start, end) in other.runs {
+            self.insert_run(start, end);
+        }
+    }
+
+    fn delta(&self, known: &Self) -> Self {
+        self.difference(known)
+    }
+}
+
+impl FromIterator<usize> for IntervalSet {
+    fn from_iter<I: IntoIterator<Item = usize>>(iter: I) -> Self {
+        let mut set = Self::new();
+        set.extend(iter);
+        set
+    }
+}
+
+impl Extend<usize> for IntervalSet {
+    fn extend<I: IntoIterator<Item = usize>>(&mut self, iter: I) {
+        for element in iter {
+            self.insert(element);
+        }
+    }
+}
+
+impl Serialize for IntervalSet {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut seq = serializer.serialize_seq(Some(self.runs.len()))?;
+        for run in self.runs() {
+            seq.serialize_element(&run)?;
+        }
+        seq.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for IntervalSet {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let runs = Vec::<(usize, usize)>::deserialize(deserializer)?;
+        let mut set = Self::new();
+        for (start, end) in runs {
+            if start > end {
+                return Err(serde::de::Error::custom(format!(
+                    "run start {} is after its end {}",
+                    start, end
+                )));
+            }
+            set.insert_run(start, end);
+        }
+        Ok(set)
+    }
+}
+
+/// Serializes an [`IntervalSet`] as a plain array of its values, for use with
+/// `#[serde(with = "as_elements")]` where clients expect one.
+pub mod as_elements {
+    use super::IntervalSet;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S>(set: &IntervalSet, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_seq(set.iter())
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<IntervalSet, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Ok(Vec::<usize>::deserialize(deserializer)?
+            .into_iter()
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn inserts_merge_adjacent_and_overlapping_runs() {
+        let mut set = IntervalSet::new();
+        assert!(set.insert_run(0, 3));
+        assert!(set.insert_run(8, 9));
+        assert!(set.insert(4));
+        assert!(!set.insert(2));
+        assert!(set.insert_run(6, 7));
+        assert_eq!(set.runs().collect::<Vec<_>>(), vec![(0, 4), (6, 9)]);
+        assert_eq!(set.len(), 9);
+
+        assert!(set.insert_run(3, 6));
+        assert_eq!(set.runs().collect::<Vec<_>>(), vec![(0, 9)]);
+        assert_eq!(set.len(), 10);
+        assert!(!set.contains(&10));
+    }
+
+    #[test]
+    fn difference_splits_runs_around_the_other_set() {
+        let set = IntervalSet::from_iter(0..20);
+        let other = [(2, 4), (10, 11), (18, 30)]
+            .into_iter()
+            .flat_map(|(start, end)| start..=end)
+            .collect::<IntervalSet>();
+
+        let difference = set.difference(&other);
+        assert_eq!(
+            difference.runs().collect::<Vec<_>>(),
+            vec![(0, 1), (5, 9), (12, 17)]
+        );
+        assert!(other.difference(&other).is_empty());
+
+        let mut merged = other.clone();
+        merged.merge(set.delta(&other));
+        assert!(set.iter().all(|value| merged.contains(&value)));
+    }
+
+    #[test]
+    fn serializes_as_runs_or_elements() {
+        let set = IntervalSet::from_iter([0, 1, 2, 5]);
+        assert_eq!(serde_json::to_string(&set).unwrap(), "[[0,2],[5,5]]");
+        assert_eq!(
+            serde_json::from_str::<IntervalSet>("[[5,5],[0,2],[3,3]]").unwrap(),
+            IntervalSet::from_iter([0, 1, 2, 3, 5])
+        );
+        assert!(serde_json::from_str::<IntervalSet>("[[2,1]]").is_err());
+
+        let mut json = serde_json::Serializer::new(Vec::new());
+        as_elements::serialize(&set, &mut json).unwrap();
+        assert_eq!(json.into_inner(), b"[0,1,2,5]");
+    }
+}
@@ -0,0 +1,104 @@
+use crate::crdt::Crdt;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::fmt::Debug;
+
+/// Last-writer-wins register. Concurrent writes with the same timestamp are
+/// ordered by node id.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LwwRegister<T> {
+    value: Option<T>,
+    timestamp: u64,
+    node_id: String,
+}
+
+impl<T> Default for LwwRegister<T> {
+    fn default() -> Self {
+        Self {
+            value: None,
+            timestamp: 0,
+            node_id: String::new(),
+        }
+    }
+}
+
+impl<T> LwwRegister<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Writes `value` if `timestamp` is newer than the current write.
+    pub fn set(&mut self, node_id: &str, timestamp: u64, value: T) {
+        if (timestamp, node_id) > (self.timestamp, self.node_id.as_str()) {
+            self.value = Some(value);
+            self.timestamp = timestamp;
+            self.node_id = node_id.to_string();
+        }
+    }
+
+    pub fn get(&self) -> Option<&T> {
+        self.value.as_ref()
+    }
+
+    pub fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+
+    fn is_newer_than(&self, other: &Self) -> bool {
+        (self.timestamp, &self.node_id) > (other.timestamp, &other.node_id)
+    }
+}
+
+impl<T> Crdt for LwwRegister<T>
+where
+    T: Debug + Clone + PartialEq + Send + Serialize + DeserializeOwned + 'static,
+{
+    fn merge(&mut self, other: Self) {
+        if other.is_newer_than(self) {
+            *self = other;
+        }
+    }
+
+    fn delta(&self, known: &Self) -> Self {
+        if self.is_newer_than(known) {
+            self.clone()
+        } else {
+            Self::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_latest_write_wins_and_ties_go_to_the_highest_node() {
+        let mut a = LwwRegister::new();
+        a.set("n0", 2, 'a');
+        let mut b = LwwRegister::new();
+        b.set("n1", 2, 'b');
+        let mut c = LwwRegister::new();
+        c.set("n2", 1, 'c');
+
+        let mut abc = a.clone();
+        abc.merge(b.clone());
+        abc.merge(c.clone());
+        let mut cba = c;
+        cba.merge(b);
+        cba.merge(a);
+        assert_eq!(abc, cba);
+        assert_eq!(abc.get(), Some(&'b'));
+    }
+
+    #[test]
+    fn delta_is_empty_unless_newer() {
+        let mut old = LwwRegister::new();
+        old.set("n0", 1, 1);
+        let mut new = old.clone();
+        new.set("n0", 2, 2);
+
+        assert_eq!(old.delta(&new), LwwRegister::default());
+        assert_eq!(new.delta(&old), new);
+    }
+}
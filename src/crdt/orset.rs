@@ -0,0 +1,142 @@
+use crate::crdt::{Crdt, VersionVector};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fmt::Debug;
+
+/// Unique tag of an add: the node that made it and its counter.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct Dot {
+    pub node_id: String,
+    pub counter: u64,
+}
+
+/// Observed-remove set: a remove only cancels the adds it has seen,
+/// so an add concurrent with a remove wins.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(bound(deserialize = "T: DeserializeOwned + Ord"))]
+pub struct OrSet<T>
+where
+    T: Ord,
+{
+    entries: BTreeSet<(T, Dot)>,
+    removed: BTreeSet<Dot>,
+    clock: VersionVector,
+}
+
+impl<T> Default for OrSet<T>
+where
+    T: Ord,
+{
+    fn default() -> Self {
+        Self {
+            entries: BTreeSet::new(),
+            removed: BTreeSet::new(),
+            clock: VersionVector::new(),
+        }
+    }
+}
+
+impl<T> OrSet<T>
+where
+    T: Ord + Clone,
+{
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, node_id: &str, element: T) {
+        let counter = self.clock.increment(node_id);
+        self.entries.insert((
+            element,
+            Dot {
+                node_id: node_id.to_string(),
+                counter,
+            },
+        ));
+    }
+
+    /// Removes `element` as observed by this replica.
+    pub fn remove(&mut self, element: &T) {
+        let observed = self
+            .entries
+            .iter()
+            .filter(|(entry, _)| entry == element)
+            .cloned()
+            .collect::<Vec<_>>();
+        for entry in observed {
+            self.entries.remove(&entry);
+            self.removed.insert(entry.1);
+        }
+    }
+
+    pub fn contains(&self, element: &T) -> bool {
+        self.entries.iter().any(|(entry, _)| entry == element)
+    }
+
+    pub fn elements(&self) -> BTreeSet<&T> {
+        self.entries.iter().map(|(element, _)| element).collect()
+    }
+}
+
+impl<T> Crdt for OrSet<T>
+where
+    T: Debug + Clone + Ord + Send + Serialize + DeserializeOwned + 'static,
+{
+    fn merge(&mut self, other: Self) {
+        self.removed.extend(other.removed);
+        self.entries.extend(other.entries);
+        let removed = &self.removed;
+        self.entries.retain(|(_, dot)| !removed.contains(dot));
+        self.clock.merge(other.clock);
+    }
+
+    fn delta(&self, known: &Self) -> Self {
+        Self {
+            entries: self
+                .entries
+                .iter()
+                .filter(|(_, dot)| known.clock.get(&dot.node_id) < dot.counter)
+                .cloned()
+                .collect(),
+            removed: self.removed.difference(&known.removed).cloned().collect(),
+            clock: self.clock.delta(&known.clock),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_remove_only_cancels_the_adds_it_observed() {
+        let mut a = OrSet::new();
+        a.insert("n0", 1);
+        let mut b = a.clone();
+        b.remove(&1);
+        a.insert("n0", 1);
+
+        let mut ab = a.clone();
+        ab.merge(b.clone());
+        let mut ba = b;
+        ba.merge(a);
+        assert_eq!(ab, ba);
+        assert!(ab.contains(&1));
+    }
+
+    #[test]
+    fn removes_propagate_through_deltas() {
+        let mut a = OrSet::new();
+        a.insert("n0", 1);
+        a.insert("n0", 2);
+        let mut b = OrSet::new();
+        b.merge(a.delta(&b));
+        assert_eq!(b, a);
+
+        a.remove(&1);
+        let delta = a.delta(&b);
+        b.merge(delta);
+        assert_eq!(b.elements(), BTreeSet::from([&2]));
+    }
+}
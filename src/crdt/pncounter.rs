@@ -0,0 +1,184 @@
+use crate::crdt::{Crdt, CrdtWorkload};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Grow-only counter: each node only increments its own entry, merges take the max.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GCounter {
+    counts: BTreeMap<String, u64>,
+}
+
+impl GCounter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn increment(&mut self, node_id: &str, delta: u64) {
+        *self.counts.entry(node_id.to_string()).or_default() += delta;
+    }
+
+    pub fn value(&self) -> u64 {
+        self.counts.values().sum()
+    }
+
+    /// The counter with only the entry of `node_id`.
+    pub fn own(&self, node_id: &str) -> Self {
+        Self {
+            counts: self
+                .counts
+                .get_key_value(node_id)
+                .map(|(node_id, count)| (node_id.clone(), *count))
+                .into_iter()
+                .collect(),
+        }
+    }
+}
+
+impl Crdt for GCounter {
+    fn merge(&mut self, other: Self) {
+        for (node_id, count) in other.counts {
+            let local = self.counts.entry(node_id).or_default();
+            *local = (*local).max(count);
+        }
+    }
+
+    fn delta(&self, known: &Self) -> Self {
+        Self {
+            counts: self
+                .counts
+                .iter()
+                .filter(|(node_id, count)| known.counts.get(*node_id) < Some(*count))
+                .map(|(node_id, count)| (node_id.clone(), *count))
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AddDelta<T> {
+    pub delta: T,
+}
+
+impl CrdtWorkload for GCounter {
+    type Add = AddDelta<u64>;
+    type Value = u64;
+
+    fn add(&mut self, node_id: &str, add: Self::Add) -> Self {
+        self.increment(node_id, add.delta);
+        self.own(node_id)
+    }
+
+    fn value(&self) -> Self::Value {
+        GCounter::value(self)
+    }
+}
+
+/// Counter supporting decrements, as a pair of grow-only counters.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PnCounter {
+    increments: GCounter,
+    decrements: GCounter,
+}
+
+impl PnCounter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, node_id: &str, delta: i64) {
+        if delta >= 0 {
+            self.increments.increment(node_id, delta.unsigned_abs());
+        } else {
+            self.decrements.increment(node_id, delta.unsigned_abs());
+        }
+    }
+
+    pub fn value(&self) -> i64 {
+        self.increments.value() as i64 - self.decrements.value() as i64
+    }
+}
+
+impl Crdt for PnCounter {
+    fn merge(&mut self, other: Self) {
+        self.increments.merge(other.increments);
+        self.decrements.merge(other.decrements);
+    }
+
+    fn delta(&self, known: &Self) -> Self {
+        Self {
+            increments: self.increments.delta(&known.increments),
+            decrements: self.decrements.delta(&known.decrements),
+        }
+    }
+}
+
+impl CrdtWorkload for PnCounter {
+    type Add = AddDelta<i64>;
+    type Value = i64;
+
+    fn add(&mut self, node_id: &str, add: Self::Add) -> Self {
+        PnCounter::add(self, node_id, add.delta);
+        Self {
+            increments: self.increments.own(node_id),
+            decrements: self.decrements.own(node_id),
+        }
+    }
+
+    fn value(&self) -> Self::Value {
+        PnCounter::value(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn g_counter_merges_commute_and_are_idempotent() {
+        let mut a = GCounter::new();
+        a.increment("n0", 3);
+        let mut b = GCounter::new();
+        b.increment("n0", 1);
+        b.increment("n1", 2);
+
+        let mut ab = a.clone();
+        ab.merge(b.clone());
+        let mut ba = b.clone();
+        ba.merge(a.clone());
+        assert_eq!(ab, ba);
+        assert_eq!(ab.value(), 5);
+
+        ab.merge(b);
+        assert_eq!(ab, ba);
+    }
+
+    #[test]
+    fn g_counter_delta_holds_only_the_newer_entries() {
+        let mut known = GCounter::new();
+        known.increment("n0", 3);
+        let mut state = known.clone();
+        state.increment("n1", 2);
+
+        let delta = state.delta(&known);
+        assert_eq!(delta, state.own("n1"));
+        known.merge(delta);
+        assert_eq!(known, state);
+    }
+
+    #[test]
+    fn pn_counter_counts_decrements_separately() {
+        let mut a = PnCounter::new();
+        a.add("n0", 5);
+        a.add("n0", -2);
+        let mut b = PnCounter::new();
+        b.add("n1", -4);
+
+        let delta = CrdtWorkload::add(&mut b, "n1", AddDelta { delta: 1 });
+        a.merge(b.delta(&PnCounter::new()));
+        assert_eq!(a.value(), 0);
+        assert_eq!(delta.value(), -3);
+
+        a.merge(b);
+        assert_eq!(a.value(), 0);
+    }
+}
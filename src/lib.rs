@@ -1,8 +1,15 @@
+pub mod checker;
+pub mod config;
+pub mod crdt;
 pub mod mloop;
 pub mod node;
 pub mod payloads;
+pub mod rng;
+pub mod rpc;
+pub mod sim;
 pub mod stdout_json;
 pub mod stdout_json_async;
+pub mod timer;
 
 use serde::{Deserialize, Serialize};
 use std::fmt::Debug;
@@ -20,41 +27,90 @@ where
     pub body: Body<T>,
 }
 
-macro_rules! impl_message_deserialize {
-    ($($payload:ty),* $(,)?) => {
-        $(
-            impl<'de> serde::Deserialize<'de> for Message<$payload> {
-                fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
-                where
-                    D: serde::Deserializer<'de>,
-                {
-                    #[derive(Deserialize)]
-                    struct MessageHelper<T> {
-                        src: String,
-                        #[serde(rename = "dest")]
-                        dst: String,
-                        body: Body<T>,
-                    }
-                    let helper = MessageHelper::<$payload>::deserialize(deserializer)?;
-                    Ok(Message {
-                        src: helper.src,
-                        dst: helper.dst,
-                        body: helper.body,
-                    })
-                }
-            }
-        )*
-    };
+/// Payloads a [`Message`] can be decoded into.
+///
+/// Every deserializable payload decodes its body on its own; combinators such as
+/// [`payloads::OrService`] also look at who sent the message.
+pub trait FromBody: Sized {
+    fn from_body(src: &str, body: serde_json::Value) -> serde_json::Result<Body<Self>>;
+
+    /// Whether a body of type `ty` sent by `src` is one of the messages of this payload,
+    /// whether or not the rest of the body is valid.
+    fn knows_type(src: &str, ty: &str) -> bool;
+}
+
+impl<T> FromBody for T
+where
+    T: serde::de::DeserializeOwned,
+{
+    fn from_body(_src: &str, body: serde_json::Value) -> serde_json::Result<Body<Self>> {
+        serde_json::from_value(body)
+    }
+
+    fn knows_type(_src: &str, ty: &str) -> bool {
+        let probe = serde::de::value::MapDeserializer::<_, TypeProbeError>::new(std::iter::once((
+            "type", ty,
+        )));
+        !matches!(T::deserialize(probe), Err(TypeProbeError::UnknownType))
+    }
+}
+
+/// Outcome of decoding a body made only of its `type`: tags serde does not know are
+/// reported through [`serde::de::Error::unknown_variant`], anything else is irrelevant.
+#[derive(Debug)]
+enum TypeProbeError {
+    UnknownType,
+    Other,
+}
+
+impl std::fmt::Display for TypeProbeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TypeProbeError::UnknownType => write!(f, "unknown type"),
+            TypeProbeError::Other => write!(f, "invalid body"),
+        }
+    }
+}
+
+impl std::error::Error for TypeProbeError {}
+
+impl serde::de::Error for TypeProbeError {
+    fn custom<T>(_msg: T) -> Self
+    where
+        T: std::fmt::Display,
+    {
+        TypeProbeError::Other
+    }
+
+    fn unknown_variant(_variant: &str, _expected: &'static [&'static str]) -> Self {
+        TypeProbeError::UnknownType
+    }
 }
 
-impl_message_deserialize!(
-    payloads::EchoPayload,
-    payloads::GeneratePayload,
-    payloads::BroadcastPayload,
-    payloads::InitPayload,
-    payloads::KafkaLogPayload,
-    payloads::TxnPayload
-);
+impl<'de, T> Deserialize<'de> for Message<T>
+where
+    T: Debug + FromBody,
+{
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        struct MessageHelper {
+            src: String,
+            #[serde(rename = "dest")]
+            dst: String,
+            body: serde_json::Value,
+        }
+        let helper = MessageHelper::deserialize(deserializer)?;
+        let body = T::from_body(&helper.src, helper.body).map_err(serde::de::Error::custom)?;
+        Ok(Message {
+            src: helper.src,
+            dst: helper.dst,
+            body,
+        })
+    }
+}
 
 impl<T> Message<T>
 where
@@ -75,6 +131,50 @@ where
             },
         }
     }
+
+    /// Builds the error reply to this message, consuming its payload.
+    pub fn into_error_reply(
+        self,
+        id: Option<&mut usize>,
+        code: payloads::ErrorCode,
+        text: impl Into<String>,
+    ) -> Message<payloads::ErrorPayload> {
+        self.into_reply(id)
+            .map_payload(|_| payloads::ErrorPayload::new(code, text))
+    }
+
+    pub fn map_payload<U, F>(self, f: F) -> Message<U>
+    where
+        U: Debug,
+        F: FnOnce(T) -> U,
+    {
+        Message {
+            src: self.src,
+            dst: self.dst,
+            body: self.body.map(f),
+        }
+    }
+}
+
+impl Message<payloads::ErrorPayload> {
+    /// Builds an error reply from `src` to `dst` when the request itself is not at hand.
+    pub fn error_reply(
+        src: String,
+        dst: String,
+        in_reply_to: Option<usize>,
+        code: payloads::ErrorCode,
+        text: impl Into<String>,
+    ) -> Self {
+        Message {
+            src,
+            dst,
+            body: Body {
+                msg_id: None,
+                in_reply_to,
+                payload: payloads::ErrorPayload::new(code, text),
+            },
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -89,3 +189,16 @@ pub struct Body<T> {
     #[serde(flatten)]
     pub payload: T,
 }
+
+impl<T> Body<T> {
+    pub fn map<U, F>(self, f: F) -> Body<U>
+    where
+        F: FnOnce(T) -> U,
+    {
+        Body {
+            msg_id: self.msg_id,
+            in_reply_to: self.in_reply_to,
+            payload: f(self.payload),
+        }
+    }
+}
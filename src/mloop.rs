@@ -1,3 +1,5 @@
+mod decode;
+#[allow(clippy::module_inception)]
 mod mloop;
 mod mloop_async;
 
@@ -5,9 +7,14 @@ use crate::payloads::{InitOkPayload, InitPayload};
 use crate::stdout_json::StdoutJson;
 use crate::{Body, Message};
 use anyhow::Context;
+pub use decode::rejected_messages;
+pub(crate) use decode::{Decoded, decode};
 pub use mloop::main_loop;
 pub use mloop_async::main_loop_async;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
 use std::io::BufRead;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 pub fn init() -> anyhow::Result<Message<InitPayload>> {
     let stdin = std::io::stdin().lock();
@@ -15,8 +22,7 @@ pub fn init() -> anyhow::Result<Message<InitPayload>> {
     let init_msg = stdin_lines
         .next()
         .context("first message should be init")??;
-    Ok(serde_json::from_str::<Message<InitPayload>>(&init_msg)
-        .context("first message should be init")?)
+    serde_json::from_str::<Message<InitPayload>>(&init_msg).context("first message should be init")
 }
 
 pub fn receive_init_then_send_init_ok() -> anyhow::Result<String> {
@@ -27,7 +33,11 @@ pub fn receive_init_then_send_init_ok() -> anyhow::Result<String> {
             Body {
                 msg_id,
                 in_reply_to: _,
-                payload: InitPayload { node_id, node_ids: _ },
+                payload:
+                    InitPayload {
+                        node_id,
+                        node_ids: _,
+                    },
             },
     } = init()?;
 
@@ -45,3 +55,15 @@ pub fn receive_init_then_send_init_ok() -> anyhow::Result<String> {
 
     Ok(node_id)
 }
+
+/// Differs between nodes and runs, so that jittered timers do not line up.
+fn timers_seed(node_id: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    node_id.hash(&mut hasher);
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos()
+        .hash(&mut hasher);
+    hasher.finish()
+}
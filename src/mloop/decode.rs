@@ -0,0 +1,146 @@
+use crate::payloads::{ErrorCode, ErrorPayload};
+use crate::{Body, FromBody, Message};
+use std::fmt::Debug;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static REJECTED_MESSAGES: AtomicUsize = AtomicUsize::new(0);
+
+/// Number of incoming messages rejected because they could not be decoded.
+pub fn rejected_messages() -> usize {
+    REJECTED_MESSAGES.load(Ordering::Relaxed)
+}
+
+#[derive(Debug)]
+pub(crate) enum Decoded<P>
+where
+    P: Debug,
+{
+    Message(Message<P>),
+    /// The message was logged and dropped. `reply` is the error to send back,
+    /// if the sender and msg_id could be salvaged.
+    Rejected {
+        reply: Option<Message<ErrorPayload>>,
+    },
+}
+
+pub(crate) fn decode_line<P>(line: &str) -> Decoded<P>
+where
+    P: Debug,
+    P: FromBody,
+{
+    match serde_json::from_str::<serde_json::Value>(line) {
+        Ok(json) => decode(json),
+        Err(e) => {
+            reject(line, &e);
+            Decoded::Rejected { reply: None }
+        }
+    }
+}
+
+/// Decodes a message addressed to a node. On failure the message is logged to stderr,
+/// counted, and answered with `not-supported` if its type is unknown
+/// or `malformed-request` otherwise.
+pub(crate) fn decode<P>(json: serde_json::Value) -> Decoded<P>
+where
+    P: Debug,
+    P: FromBody,
+{
+    let e = match serde_json::from_value::<Message<P>>(json.clone()) {
+        Ok(msg) => return Decoded::Message(msg),
+        Err(e) => e,
+    };
+    reject(&json, &e);
+
+    let (Some(src), Some(dest), Some(msg_id)) = (
+        json["src"].as_str(),
+        json["dest"].as_str(),
+        json["body"]["msg_id"].as_u64(),
+    ) else {
+        return Decoded::Rejected { reply: None };
+    };
+    let code = match json["body"]["type"].as_str() {
+        Some(ty) if !P::knows_type(src, ty) => ErrorCode::NotSupported,
+        _ => ErrorCode::MalformedRequest,
+    };
+    Decoded::Rejected {
+        reply: Some(Message {
+            src: dest.to_string(),
+            dst: src.to_string(),
+            body: Body {
+                msg_id: None,
+                in_reply_to: Some(msg_id as usize),
+                payload: ErrorPayload::new(code, e.to_string()),
+            },
+        }),
+    }
+}
+
+fn reject(input: impl std::fmt::Display, e: &serde_json::Error) {
+    REJECTED_MESSAGES.fetch_add(1, Ordering::Relaxed);
+    eprintln!("rejected message {}: {}", input, e);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::payloads::EchoPayload;
+    use serde_json::json;
+
+    fn rejection(json: serde_json::Value) -> Option<Message<ErrorPayload>> {
+        match decode::<EchoPayload>(json) {
+            Decoded::Message(msg) => panic!("decoded {:?}", msg),
+            Decoded::Rejected { reply } => reply,
+        }
+    }
+
+    #[test]
+    fn decodes_known_messages() {
+        let json = json!({
+            "src": "c1",
+            "dest": "n0",
+            "body": {"type": "echo", "msg_id": 1, "echo": "hi"},
+        });
+        let Decoded::Message(msg) = decode::<EchoPayload>(json) else {
+            panic!("echo was rejected");
+        };
+        assert!(matches!(msg.body.payload, EchoPayload::Echo { echo } if echo == "hi"));
+    }
+
+    #[test]
+    fn answers_unknown_types_with_not_supported() {
+        let reply = rejection(json!({
+            "src": "c1",
+            "dest": "n0",
+            "body": {"type": "frobnicate", "msg_id": 7},
+        }))
+        .unwrap();
+        assert_eq!((reply.src.as_str(), reply.dst.as_str()), ("n0", "c1"));
+        assert_eq!(reply.body.in_reply_to, Some(7));
+        assert_eq!(reply.body.payload.code.code(), 10);
+    }
+
+    #[test]
+    fn answers_known_types_that_do_not_decode_with_malformed_request() {
+        let reply = rejection(json!({
+            "src": "c1",
+            "dest": "n0",
+            "body": {"type": "echo", "msg_id": 8},
+        }))
+        .unwrap();
+        assert_eq!(reply.body.in_reply_to, Some(8));
+        assert_eq!(reply.body.payload.code.code(), 12);
+    }
+
+    #[test]
+    fn drops_messages_that_cannot_be_answered() {
+        let before = rejected_messages();
+        assert!(
+            rejection(json!({"src": "c1", "dest": "n0", "body": {"type": "frobnicate"}})).is_none()
+        );
+        assert!(matches!(
+            decode_line::<EchoPayload>("{not json"),
+            Decoded::Rejected { reply: None }
+        ));
+        assert!(rejected_messages() >= before + 2);
+    }
+}
@@ -1,42 +1,81 @@
-use crate::Message;
-use crate::mloop::init;
+use crate::mloop::decode::{Decoded, decode_line};
+use crate::mloop::{init, timers_seed};
 use crate::node::Node;
-use crate::payloads::Event;
+use crate::payloads::{ErrorPayload, Event};
 use crate::stdout_json::StdoutJson;
+use crate::timer::Timers;
+use crate::{FromBody, Message};
 use anyhow::Context;
-use serde::de::DeserializeOwned;
 use std::fmt::Debug;
 use std::io::BufRead;
+use std::sync::mpsc::RecvTimeoutError;
+use std::time::Instant;
+
+enum Input<P, IP>
+where
+    P: Debug,
+    IP: Debug,
+{
+    Event(Event<P, IP>),
+    Rejected(Message<ErrorPayload>),
+}
 
 pub fn main_loop<N, P, IP>() -> anyhow::Result<()>
 where
     N: Node<P, IP>,
     P: Debug + Send + 'static,
-    IP: Debug + Send + 'static,
-    Message<P>: DeserializeOwned,
+    IP: Debug + Clone + Send + 'static,
+    P: FromBody,
 {
     let init_msg = init()?;
-    let (tx, rx) = std::sync::mpsc::channel::<Event<P, IP>>();
-    let tx_stdin = tx.clone();
+    let (input_tx, input_rx) = std::sync::mpsc::channel::<Input<P, IP>>();
+
     std::thread::spawn::<_, anyhow::Result<()>>(move || {
-        let tx = tx_stdin.clone();
         let stdin = std::io::stdin().lock();
         for stdin_line in stdin.lines() {
             let stdin_line = stdin_line.context("failed to read from stdin")?;
-            let msg = serde_json::from_str::<Message<P>>(&stdin_line)
-                .context("msg deserialization failed")?;
-            if let Err(_) = tx.send(Event::Message(msg)) {
+            let input = match decode_line::<P>(&stdin_line) {
+                Decoded::Message(msg) => Input::Event(Event::Message(msg)),
+                Decoded::Rejected { reply: Some(reply) } => Input::Rejected(reply),
+                Decoded::Rejected { reply: None } => continue,
+            };
+            if input_tx.send(input).is_err() {
                 return Ok(());
             };
         }
         Ok(())
     });
 
+    let start = Instant::now();
+    let timers = Timers::new(timers_seed(&init_msg.body.payload.node_id));
     let mut stdout_json = StdoutJson::new();
-    let mut node = N::init(init_msg, &mut stdout_json, tx)?;
-    for event in rx {
-        node.step(event, &mut stdout_json)
-            .context("node step function failed")?;
+    let mut node = N::init(init_msg, &mut stdout_json, &timers)?;
+    loop {
+        timers.advance(start.elapsed());
+        while let Some(payload) = timers.pop_due() {
+            node.step(Event::InjectedPayload(payload), &mut stdout_json)
+                .context("node step function failed")?;
+        }
+
+        let input = match timers.next_deadline() {
+            Some(deadline) => match input_rx.recv_timeout(deadline.saturating_sub(start.elapsed()))
+            {
+                Ok(input) => input,
+                Err(RecvTimeoutError::Timeout) => continue,
+                Err(RecvTimeoutError::Disconnected) => break,
+            },
+            None => match input_rx.recv() {
+                Ok(input) => input,
+                Err(_) => break,
+            },
+        };
+        timers.advance(start.elapsed());
+        match input {
+            Input::Event(event) => node
+                .step(event, &mut stdout_json)
+                .context("node step function failed")?,
+            Input::Rejected(reply) => stdout_json.write(&reply)?,
+        }
     }
 
     Ok(())
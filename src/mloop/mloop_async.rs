@@ -1,51 +1,100 @@
-use crate::mloop::receive_init_then_send_init_ok;
-use crate::node::multikafkalog::MultiKafkaLogNode;
-use crate::payloads::KafkaLogOrKvPayload;
-use crate::stdout_json::StdoutJson;
-use crate::Message;
+use crate::mloop::decode::{Decoded, decode_line};
+use crate::mloop::{init, timers_seed};
+use crate::node::{AsyncNode, init_ok_msg};
+use crate::payloads::{ErrorPayload, Event};
+use crate::stdout_json_async::AsyncStdoutJson;
+use crate::timer::Timers;
+use crate::{FromBody, Message};
+use anyhow::Context;
+use serde::Serialize;
+use std::fmt::Debug;
 use tokio::io::{AsyncBufReadExt, BufReader};
 
-pub async fn main_loop_async() -> anyhow::Result<()> {
-    let node_id = receive_init_then_send_init_ok()?;
+pub async fn main_loop_async<N, P, IP>() -> anyhow::Result<()>
+where
+    N: AsyncNode<P, IP>,
+    P: Debug + Serialize + Send + Sync + 'static,
+    IP: Debug + Clone + Send + 'static,
+    P: FromBody,
+{
+    let init_msg = init()?;
+    let mut async_stdout = AsyncStdoutJson::new();
+    async_stdout.write(&init_ok_msg(&init_msg)).await?;
 
-    let (stdout_tx, mut stdout_rx) =
-        tokio::sync::mpsc::unbounded_channel::<Message<KafkaLogOrKvPayload>>();
-    let (stdin_tx, stdin_rx) =
-        tokio::sync::mpsc::unbounded_channel::<Message<KafkaLogOrKvPayload>>();
+    let (stdout_tx, mut stdout_rx) = tokio::sync::mpsc::unbounded_channel::<Message<P>>();
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel::<Event<P, IP>>();
 
-    tokio::spawn(async move {
-        let async_stdin = tokio::io::stdin();
-        let mut reader = BufReader::new(async_stdin).lines();
-        let mut stdout = StdoutJson::new();
+    let (rejected_tx, mut rejected_rx) =
+        tokio::sync::mpsc::unbounded_channel::<Message<ErrorPayload>>();
 
+    let stdout_task = tokio::spawn(async move {
         loop {
-            tokio::select! {
-                Some(msg) = stdout_rx.recv() => {
-                    stdout.write(&msg).expect("failed to write to stdout");
-                },
-                stdin_msg = reader.next_line() => {
-                    match stdin_msg {
-                        Ok(Some(msg)) => {
-                            let msg = serde_json::from_str::<Message<KafkaLogOrKvPayload>>(&msg)
-                                .expect("msg deserialization failed");
-                            stdin_tx.send(msg).expect("failed to send msg to stdout channel");
-                        },
-                        Err(e) => {
-                            eprintln!("stdin read error: {}", e);
-                            break;
-                        }
-                        _ => {
-                            eprintln!("error stdin! {:?}", stdin_msg);
-                        }
+            let written = tokio::select! {
+                Some(msg) = stdout_rx.recv() => async_stdout.write(&msg).await,
+                Some(reply) = rejected_rx.recv() => async_stdout.write(&reply).await,
+                else => break,
+            };
+            if let Err(e) = written {
+                eprintln!("stdout write error: {:#}", e);
+                break;
+            }
+        }
+    });
+
+    let tx_stdin = tx.clone();
+    let mut stdin_task = tokio::spawn(async move {
+        let mut reader = BufReader::new(tokio::io::stdin()).lines();
+        while let Some(stdin_line) = reader
+            .next_line()
+            .await
+            .context("failed to read from stdin")?
+        {
+            let msg = match decode_line::<P>(&stdin_line) {
+                Decoded::Message(msg) => msg,
+                Decoded::Rejected { reply } => {
+                    if let Some(reply) = reply {
+                        let _ = rejected_tx.send(reply);
                     }
-                },
+                    continue;
+                }
+            };
+            if tx_stdin.send(Event::Message(msg)).is_err() {
+                break;
             }
         }
+        anyhow::Ok(())
     });
 
-    let mut node = MultiKafkaLogNode::new(node_id, 5, stdin_rx, stdout_tx);
+    let start = tokio::time::Instant::now();
+    let timers = Timers::new(timers_seed(&init_msg.body.payload.node_id));
+    let mut node = N::init(init_msg, stdout_tx, tx.clone(), &timers)?;
+    let stdin_result = loop {
+        timers.advance(start.elapsed());
+        while let Some(payload) = timers.pop_due() {
+            node.step(Event::InjectedPayload(payload))
+                .await
+                .context("node step function failed")?;
+        }
+
+        let next_timer = timers.next_deadline().map(|deadline| start + deadline);
+        tokio::select! {
+            biased;
+            Some(event) = rx.recv() => {
+                timers.advance(start.elapsed());
+                node.step(event).await.context("node step function failed")?;
+            }
+            stdin_result = &mut stdin_task => break stdin_result,
+            _ = tokio::time::sleep_until(next_timer.unwrap_or(start)), if next_timer.is_some() => {}
+        }
+    };
 
-    node.run().await;
+    while let Ok(event) = rx.try_recv() {
+        node.step(event)
+            .await
+            .context("node step function failed")?;
+    }
+    drop(node);
+    stdout_task.await.context("stdout task failed")?;
 
-    Ok(())
+    stdin_result.context("stdin task failed")?
 }
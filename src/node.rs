@@ -1,15 +1,19 @@
 pub mod broadcast;
+pub mod crdt;
 pub mod echo;
 pub mod generate;
 pub mod gocounter;
-pub mod multibroadcast;
 pub mod kafkalog;
+pub mod multibroadcast;
 pub mod multikafkalog;
-pub mod singletxn;
 pub mod multitxn;
+pub mod raft;
+pub mod replicatedkafkalog;
+pub mod singletxn;
 
 use crate::payloads::{Event, InitOkPayload, InitPayload};
 use crate::stdout_json::StdoutJson;
+use crate::timer::Timers;
 use crate::{Body, Message};
 use std::fmt::Debug;
 
@@ -21,29 +25,49 @@ where
     fn init(
         init_msg: Message<InitPayload>,
         output: &mut StdoutJson,
-        tx_channel: std::sync::mpsc::Sender<Event<P, IP>>,
+        timers: &Timers<IP>,
     ) -> anyhow::Result<Self>
     where
         Self: Sized;
     fn step(&mut self, event: Event<P, IP>, output: &mut StdoutJson) -> anyhow::Result<()>;
 }
 
+pub trait AsyncNode<P, IP = ()>
+where
+    P: Debug,
+    IP: Debug,
+{
+    fn init(
+        init_msg: Message<InitPayload>,
+        output: tokio::sync::mpsc::UnboundedSender<Message<P>>,
+        tx_channel: tokio::sync::mpsc::UnboundedSender<Event<P, IP>>,
+        timers: &Timers<IP>,
+    ) -> anyhow::Result<Self>
+    where
+        Self: Sized;
+
+    fn step(&mut self, event: Event<P, IP>) -> impl Future<Output = anyhow::Result<()>>;
+}
+
 fn common_init_node(
     init_msg: Message<InitPayload>,
     output: &mut StdoutJson,
 ) -> anyhow::Result<(String, Vec<String>)> {
-    let node_id = init_msg.body.payload.node_id;
+    output.write(&init_ok_msg(&init_msg))?;
+    Ok((
+        init_msg.body.payload.node_id,
+        init_msg.body.payload.node_ids,
+    ))
+}
 
-    let init_ok = Message {
-        src: node_id.clone(),
+pub(crate) fn init_ok_msg(init_msg: &Message<InitPayload>) -> Message<InitOkPayload> {
+    Message {
+        src: init_msg.body.payload.node_id.clone(),
         dst: init_msg.src.clone(),
         body: Body {
             msg_id: None,
             in_reply_to: init_msg.body.msg_id,
             payload: InitOkPayload::new(),
         },
-    };
-
-    output.write(&init_ok)?;
-    Ok((node_id, init_msg.body.payload.node_ids))
+    }
 }
@@ -1,22 +1,23 @@
-use crate::node::{common_init_node, Node};
+use crate::Message;
+use crate::crdt::IntervalSet;
+use crate::node::{Node, common_init_node};
 use crate::payloads::{BroadcastPayload, Event, InitPayload};
 use crate::stdout_json::StdoutJson;
-use crate::Message;
-use std::collections::HashSet;
+use crate::timer::Timers;
 
 #[derive(Debug, Clone)]
 pub struct BroadcastNode {
     pub id: String,
     pub msg_id: usize,
     pub node_ids: Vec<String>,
-    pub broadcast_messages: HashSet<usize>,
+    pub broadcast_messages: IntervalSet,
 }
 
 impl Node<BroadcastPayload> for BroadcastNode {
     fn init(
         init_msg: Message<InitPayload>,
         output: &mut StdoutJson,
-        _tx_channel: std::sync::mpsc::Sender<Event<BroadcastPayload, ()>>,
+        _timers: &Timers<()>,
     ) -> anyhow::Result<Self>
     where
         Self: Sized,
@@ -26,7 +27,7 @@ impl Node<BroadcastPayload> for BroadcastNode {
             id: node_id,
             msg_id: 0,
             node_ids,
-            broadcast_messages: HashSet::new(),
+            broadcast_messages: IntervalSet::new(),
         })
     }
 
@@ -55,11 +56,13 @@ impl Node<BroadcastPayload> for BroadcastNode {
                 reply.body.payload = BroadcastPayload::TopologyOk;
                 output.write(&reply)?;
             }
-            BroadcastPayload::TopologyOk { .. }
+            BroadcastPayload::TopologyOk
             | BroadcastPayload::ReadOk { .. }
             | BroadcastPayload::BroadcastOk
             | BroadcastPayload::Gossip { .. }
-            | BroadcastPayload::GossipOk { .. } => {}
+            | BroadcastPayload::GossipOk { .. }
+            | BroadcastPayload::Forward { .. }
+            | BroadcastPayload::ForwardOk => {}
         };
         Ok(())
     }
@@ -0,0 +1,99 @@
+use crate::config;
+use crate::crdt::{AntiEntropy, CrdtWorkload};
+use crate::node::{Node, common_init_node};
+use crate::payloads::{CrdtPayload, CrdtTimer, Event, InitPayload};
+use crate::stdout_json::StdoutJson;
+use crate::timer::Timers;
+use crate::{Body, Message};
+use std::time::Duration;
+
+/// Serves a CRDT through Maelstrom's `add`/`read` workloads (`g-set`, `g-counter`,
+/// `pn-counter`), periodically gossiping to every peer the deltas it has not acknowledged.
+///
+/// Reads are answered from the local replica, so the node stays available under partitions.
+#[derive(Debug, Clone)]
+pub struct CrdtNode<C>
+where
+    C: CrdtWorkload,
+{
+    pub id: String,
+    pub msg_id: usize,
+    pub state: C,
+    pub anti_entropy: AntiEntropy<C>,
+}
+
+impl<C> Node<CrdtPayload<C>, CrdtTimer> for CrdtNode<C>
+where
+    C: CrdtWorkload,
+{
+    fn init(
+        init_msg: Message<InitPayload>,
+        output: &mut StdoutJson,
+        timers: &Timers<CrdtTimer>,
+    ) -> anyhow::Result<Self>
+    where
+        Self: Sized,
+    {
+        let (node_id, node_ids) = common_init_node(init_msg, output)?;
+        let crdt_node = Self {
+            msg_id: 0,
+            state: C::default(),
+            anti_entropy: AntiEntropy::new(node_ids.into_iter().filter(|peer| *peer != node_id)),
+            id: node_id,
+        };
+        let interval = config::millis_or("gossip-interval-ms", Duration::from_millis(500))?;
+        timers.every_with_jitter("gossip", interval, interval / 10, CrdtTimer::Gossip)?;
+        Ok(crdt_node)
+    }
+
+    fn step(
+        &mut self,
+        event: Event<CrdtPayload<C>, CrdtTimer>,
+        output: &mut StdoutJson,
+    ) -> anyhow::Result<()> {
+        match event {
+            Event::Message(message) => {
+                let src = message.src.clone();
+                let mut reply = message.into_reply(Some(&mut self.msg_id));
+                match reply.body.payload {
+                    CrdtPayload::Add(add) => {
+                        let delta = self.state.add(&self.id, add);
+                        self.anti_entropy.record(delta);
+                        reply.body.payload = CrdtPayload::AddOk;
+                        output.write(&reply)?;
+                    }
+                    CrdtPayload::Read => {
+                        reply.body.payload = CrdtPayload::ReadOk {
+                            value: self.state.value(),
+                        };
+                        output.write(&reply)?;
+                    }
+                    CrdtPayload::Gossip { delta, upto } => {
+                        self.anti_entropy.receive(&mut self.state, &src, delta);
+                        reply.body.payload = CrdtPayload::GossipOk { upto };
+                        output.write(&reply)?;
+                    }
+                    CrdtPayload::GossipOk { upto } => self.anti_entropy.ack(&src, upto),
+                    CrdtPayload::AddOk | CrdtPayload::ReadOk { .. } => {}
+                }
+            }
+            Event::InjectedPayload(CrdtTimer::Gossip) => {
+                for (peer, upto, delta) in self.anti_entropy.outgoing(&self.state) {
+                    let gossip = Message {
+                        src: self.id.clone(),
+                        dst: peer,
+                        body: Body {
+                            msg_id: Some(self.msg_id),
+                            in_reply_to: None,
+                            payload: CrdtPayload::Gossip { delta, upto },
+                        },
+                    };
+                    self.msg_id += 1;
+                    output.write(&gossip)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
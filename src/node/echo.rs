@@ -2,6 +2,7 @@ use crate::Message;
 use crate::node::{Node, common_init_node};
 use crate::payloads::{EchoPayload, Event, InitPayload};
 use crate::stdout_json::StdoutJson;
+use crate::timer::Timers;
 
 #[derive(Debug, Clone)]
 pub struct EchoNode {
@@ -13,7 +14,7 @@ impl Node<EchoPayload> for EchoNode {
     fn init(
         init_msg: Message<InitPayload>,
         output: &mut StdoutJson,
-        _tx_channel: std::sync::mpsc::Sender<Event<EchoPayload, ()>>,
+        _timers: &Timers<()>,
     ) -> anyhow::Result<Self>
     where
         Self: Sized,
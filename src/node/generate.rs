@@ -3,6 +3,7 @@ use crate::node::{Node, common_init_node};
 use crate::payloads::GeneratePayload::GenerateOk;
 use crate::payloads::{Event, GeneratePayload, InitPayload};
 use crate::stdout_json::StdoutJson;
+use crate::timer::Timers;
 use anyhow::Context;
 
 #[derive(Debug, Clone)]
@@ -15,7 +16,7 @@ impl Node<GeneratePayload> for GenerateNode {
     fn init(
         init_msg: Message<InitPayload>,
         output: &mut StdoutJson,
-        _tx_channel: std::sync::mpsc::Sender<Event<GeneratePayload, ()>>,
+        _timers: &Timers<()>,
     ) -> anyhow::Result<Self>
     where
         Self: Sized,
@@ -38,7 +39,7 @@ impl Node<GeneratePayload> for GenerateNode {
         };
         let mut reply = input.into_reply(Some(&mut self.msg_id));
         match reply.body.payload {
-            GeneratePayload::Generate { .. } => {
+            GeneratePayload::Generate => {
                 reply.body.payload = GenerateOk {
                     guid: self.generate_guid_using_node_id(),
                 };
@@ -1,27 +1,32 @@
+use crate::config;
 use crate::node::{Node, common_init_node};
 use crate::payloads::KvPayload::Write;
 use crate::payloads::{
     Event, GoCounterOrSeqKvPayload, GoCounterPayload, InitPayload, KvPayload, SyncCounter,
 };
+use crate::rpc::{RpcCallbacks, RpcError, RpcOptions};
 use crate::stdout_json::StdoutJson;
+use crate::timer::Timers;
 use crate::{Body, Message};
 use std::collections::HashMap;
+use std::time::Duration;
 
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub struct GrowOnlyCounterNode {
     pub id: String,
     pub msg_id: usize,
     pub counter: usize,
     pub node_ids: Vec<String>,
     pub value_by_node_id: HashMap<String, usize>,
-    pub node_id_by_msg_id: HashMap<usize, String>,
+    pub rpc: RpcCallbacks<Self, GoCounterOrSeqKvPayload>,
+    pub timers: Timers<SyncCounter>,
 }
 
 impl Node<GoCounterOrSeqKvPayload, SyncCounter> for GrowOnlyCounterNode {
     fn init(
         init_msg: Message<InitPayload>,
         output: &mut StdoutJson,
-        tx_channel: std::sync::mpsc::Sender<Event<GoCounterOrSeqKvPayload, SyncCounter>>,
+        timers: &Timers<SyncCounter>,
     ) -> anyhow::Result<Self>
     where
         Self: Sized,
@@ -32,6 +37,11 @@ impl Node<GoCounterOrSeqKvPayload, SyncCounter> for GrowOnlyCounterNode {
             .filter(|node_id| *node_id != this_node_id)
             .collect::<Vec<_>>();
         let multi_node_broadcast = Self {
+            timers: timers.clone(),
+            rpc: RpcCallbacks::new(
+                this_node_id.clone(),
+                RpcOptions::new(Duration::from_millis(1000)),
+            ),
             id: this_node_id,
             msg_id: 0,
             counter: 0,
@@ -41,9 +51,12 @@ impl Node<GoCounterOrSeqKvPayload, SyncCounter> for GrowOnlyCounterNode {
                 .map(|node_id| (node_id, 0))
                 .collect(),
             node_ids,
-            node_id_by_msg_id: HashMap::new(),
         };
-        Self::spawn_sync_counter_thread(tx_channel);
+        timers.every(
+            "sync",
+            config::millis_or("sync-interval-ms", Duration::from_millis(1000))?,
+            SyncCounter::Sync,
+        )?;
         Ok(multi_node_broadcast)
     }
 
@@ -54,87 +67,79 @@ impl Node<GoCounterOrSeqKvPayload, SyncCounter> for GrowOnlyCounterNode {
     ) -> anyhow::Result<()> {
         match event {
             Event::Message(message) => {
-                let in_reply_to = message.body.in_reply_to;
+                if let Some(callback) = self.rpc.take_callback(&message) {
+                    return callback(self, Ok(message), output);
+                }
                 let mut reply = message.into_reply(Some(&mut self.msg_id));
                 match reply.body.payload {
-                    GoCounterOrSeqKvPayload::GoCounter(go_counter_payload) => {
-                        match go_counter_payload {
-                            GoCounterPayload::Read => {
-                                let global_value: usize =
-                                    self.counter + self.value_by_node_id.values().sum::<usize>();
-                                reply.body.payload =
-                                    GoCounterOrSeqKvPayload::GoCounter(GoCounterPayload::ReadOk {
-                                        value: global_value,
-                                    });
-                                output.write(&reply)?;
-                            }
-                            GoCounterPayload::Add { delta } => {
-                                self.counter += delta;
-                                reply.body.payload =
-                                    GoCounterOrSeqKvPayload::GoCounter(GoCounterPayload::AddOk);
-                                output.write(&reply)?;
-
-                                let msg_id = self.msg_id;
-                                self.msg_id += 1;
-                                let write = Message {
-                                    src: self.id.clone(),
-                                    dst: "seq-kv".to_string(),
-                                    body: Body {
-                                        msg_id: Some(msg_id),
-                                        in_reply_to: None,
-                                        payload: GoCounterOrSeqKvPayload::SeqKv(Write {
-                                            key: self.id.clone(),
-                                            value: self.counter,
-                                        }),
-                                    },
-                                };
-                                output.write(&write)?;
-                            }
-                            GoCounterPayload::ReadOk { .. } | GoCounterPayload::AddOk { .. } => {}
+                    GoCounterOrSeqKvPayload::Node(go_counter_payload) => match go_counter_payload {
+                        GoCounterPayload::Read => {
+                            let global_value: usize =
+                                self.counter + self.value_by_node_id.values().sum::<usize>();
+                            reply.body.payload =
+                                GoCounterOrSeqKvPayload::Node(GoCounterPayload::ReadOk {
+                                    value: global_value,
+                                });
+                            output.write(&reply)?;
                         }
-                    }
-                    GoCounterOrSeqKvPayload::SeqKv(seq_kv_payload) => match seq_kv_payload {
-                        KvPayload::ReadOk { value } => {
-                            let Some(in_reply_to) = in_reply_to else {
-                                return Ok(());
-                            };
-                            let Some(node_id) = self.node_id_by_msg_id.remove(&in_reply_to) else {
-                                return Ok(());
+                        GoCounterPayload::Add { delta } => {
+                            self.counter += delta;
+                            reply.body.payload =
+                                GoCounterOrSeqKvPayload::Node(GoCounterPayload::AddOk);
+                            output.write(&reply)?;
+
+                            let msg_id = self.msg_id;
+                            self.msg_id += 1;
+                            let write = Message {
+                                src: self.id.clone(),
+                                dst: "seq-kv".to_string(),
+                                body: Body {
+                                    msg_id: Some(msg_id),
+                                    in_reply_to: None,
+                                    payload: GoCounterOrSeqKvPayload::Service(Write {
+                                        key: self.id.clone(),
+                                        value: self.counter,
+                                    }),
+                                },
                             };
-                            self.value_by_node_id
-                                .entry(node_id)
-                                .and_modify(|v| *v = (*v).max(value))
-                                .or_insert(value);
+                            output.write(&write)?;
                         }
-                        Write { .. }
-                        | KvPayload::CasOk
-                        | KvPayload::WriteOk
-                        | KvPayload::Cas { .. }
-                        | KvPayload::Error { .. }
-                        | KvPayload::Read { .. } => {}
+                        GoCounterPayload::ReadOk { .. } | GoCounterPayload::AddOk => {}
                     },
+                    GoCounterOrSeqKvPayload::Service(_) | GoCounterOrSeqKvPayload::Error(_) => {}
                 }
             }
             Event::InjectedPayload(_sync_msg) => {
-                for node_id in &self.node_ids {
-                    let msg_id = self.msg_id;
-                    self.msg_id += 1;
-
-                    self.node_id_by_msg_id.insert(msg_id, node_id.clone());
+                for callback in self
+                    .rpc
+                    .expire(&mut self.msg_id, self.timers.now(), output)?
+                {
+                    callback(self, Err(RpcError::Timeout), output)?;
+                }
 
-                    let seq_kv_read = Message {
-                        src: self.id.clone(),
-                        dst: "seq-kv".to_string(),
-                        body: Body {
-                            msg_id: Some(msg_id),
-                            in_reply_to: None,
-                            payload: GoCounterOrSeqKvPayload::SeqKv(KvPayload::Read {
-                                key: node_id.clone(),
-                            }),
+                for node_id in self.node_ids.clone() {
+                    let read = GoCounterOrSeqKvPayload::Service(KvPayload::Read {
+                        key: node_id.clone(),
+                    });
+                    self.rpc.call(
+                        &mut self.msg_id,
+                        "seq-kv",
+                        read,
+                        self.timers.now(),
+                        output,
+                        move |node: &mut Self, reply, _output| {
+                            if let Ok(GoCounterOrSeqKvPayload::Service(KvPayload::ReadOk {
+                                value,
+                            })) = reply.map(|msg| msg.body.payload)
+                            {
+                                node.value_by_node_id
+                                    .entry(node_id)
+                                    .and_modify(|v| *v = (*v).max(value))
+                                    .or_insert(value);
+                            }
+                            Ok(())
                         },
-                    };
-
-                    output.write(&seq_kv_read)?;
+                    )?;
                 }
             }
         }
@@ -142,18 +147,3 @@ impl Node<GoCounterOrSeqKvPayload, SyncCounter> for GrowOnlyCounterNode {
         Ok(())
     }
 }
-
-impl GrowOnlyCounterNode {
-    fn spawn_sync_counter_thread(
-        tx_channel: std::sync::mpsc::Sender<Event<GoCounterOrSeqKvPayload, SyncCounter>>,
-    ) {
-        std::thread::spawn(move || {
-            loop {
-                std::thread::sleep(std::time::Duration::from_millis(1000));
-                if let Err(_) = tx_channel.send(Event::InjectedPayload(SyncCounter::Sync)) {
-                    break;
-                }
-            }
-        });
-    }
-}
@@ -1,14 +1,26 @@
-use crate::Message;
 use crate::node::{Node, common_init_node};
-use crate::payloads::{Event, InitPayload, KafkaLogPayload};
+use crate::payloads::{Event, InitPayload, KafkaLogPayload, consumer_group};
 use crate::stdout_json::StdoutJson;
-use std::collections::HashMap;
-use std::sync::mpsc::Sender;
+use crate::timer::Timers;
+use crate::{Message, config};
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::fmt::{Display, Formatter};
+use std::time::Duration;
 
+/// Ticks of the retention timer, which fires `AGE_TICKS` times per `max_age`, after which a
+/// segment not appended to expires.
+const AGE_TICKS: u64 = 4;
+
+/// Single-node kafka log keeping the messages of each key in memory.
+///
+/// Old messages are dropped or compacted as allowed by the configured [`KafkaRetention`]; a
+/// poll below the first message kept of a key is answered with its low-water mark in
+/// `out_of_range`, and a poll reaching into compacted segments with their end in `compacted`.
 #[derive(Debug, Clone)]
 pub struct KafkaLogNode {
     _id: String,
     msg_id: usize,
+    retention: KafkaRetention,
     logs: HashMap<String, KafkaLog>,
 }
 
@@ -16,15 +28,21 @@ impl Node<KafkaLogPayload, ()> for KafkaLogNode {
     fn init(
         init_msg: Message<InitPayload>,
         output: &mut StdoutJson,
-        _tx_channel: Sender<Event<KafkaLogPayload, ()>>,
+        timers: &Timers<()>,
     ) -> anyhow::Result<Self>
     where
         Self: Sized,
     {
         let (node_id, _node_ids) = common_init_node(init_msg, output)?;
+        let retention = KafkaRetention::from_config()?;
+        if let Some(max_age) = retention.max_age {
+            let interval = (max_age / AGE_TICKS as u32).max(Duration::from_millis(1));
+            timers.every("retention", interval, ())?;
+        }
         Ok(Self {
             _id: node_id,
             msg_id: 0,
+            retention,
             logs: HashMap::new(),
         })
     }
@@ -39,10 +57,11 @@ impl Node<KafkaLogPayload, ()> for KafkaLogNode {
                 let mut reply = msg.into_reply(Some(&mut self.msg_id));
                 match reply.body.payload {
                     KafkaLogPayload::Send { key, msg } => {
+                        let retention = self.retention;
                         let kafka_log = self
                             .logs
                             .entry(key)
-                            .or_insert_with(|| KafkaLog::new(5, 100));
+                            .or_insert_with(|| KafkaLog::new(5, 100).with_retention(retention));
 
                         let offset = kafka_log.append(msg);
                         reply.body.payload = KafkaLogPayload::SendOk { offset };
@@ -52,27 +71,38 @@ impl Node<KafkaLogPayload, ()> for KafkaLogNode {
                     KafkaLogPayload::Poll { offsets } => {
                         let mut msgs: HashMap<String, Vec<(usize, usize)>> =
                             HashMap::with_capacity(offsets.len());
+                        let mut out_of_range = HashMap::new();
+                        let mut compacted = HashMap::new();
 
                         for (key, offset) in offsets {
                             if let Some(log) = self.logs.get(&key) {
-                                msgs.insert(
-                                    key,
-                                    log.poll(offset)
-                                        .iter()
-                                        .map(|(offset, msg)| (*offset, *msg))
-                                        .collect(),
-                                );
+                                match log.poll(offset) {
+                                    Ok(polled) => {
+                                        if let Some(compacted_end) = log.compacted_past(offset) {
+                                            compacted.insert(key.clone(), compacted_end);
+                                        }
+                                        msgs.insert(key, polled);
+                                    }
+                                    Err(error) => {
+                                        out_of_range.insert(key, error.low_water_mark);
+                                    }
+                                }
                             }
                         }
 
-                        reply.body.payload = KafkaLogPayload::PollOk { msgs };
+                        reply.body.payload = KafkaLogPayload::PollOk {
+                            msgs,
+                            out_of_range,
+                            compacted,
+                        };
                         output.write(&reply)?;
                     }
                     KafkaLogPayload::PollOk { .. } => {}
-                    KafkaLogPayload::CommitOffsets { offsets } => {
+                    KafkaLogPayload::CommitOffsets { offsets, group } => {
+                        let group = consumer_group(group);
                         for (key, offset) in offsets {
                             if let Some(log) = self.logs.get_mut(&key) {
-                                log.commit_offset(offset);
+                                log.commit_offset(&group, offset);
                             }
                         }
 
@@ -80,13 +110,14 @@ impl Node<KafkaLogPayload, ()> for KafkaLogNode {
                         output.write(&reply)?;
                     }
                     KafkaLogPayload::CommitOffsetsOk => {}
-                    KafkaLogPayload::ListCommittedOffsets { keys } => {
+                    KafkaLogPayload::ListCommittedOffsets { keys, group } => {
+                        let group = consumer_group(group);
                         let mut offsets: HashMap<String, usize> =
                             HashMap::with_capacity(keys.len());
 
                         for key in keys {
                             if let Some(log) = self.logs.get(&key) {
-                                offsets.insert(key, log.get_committed_offset());
+                                offsets.insert(key, log.get_committed_offset(&group));
                             }
                         }
 
@@ -94,59 +125,451 @@ impl Node<KafkaLogPayload, ()> for KafkaLogNode {
                         output.write(&reply)?;
                     }
                     KafkaLogPayload::ListCommittedOffsetsOk { .. } => {}
+                    KafkaLogPayload::ResetOffsets { offsets, group } => {
+                        let group = consumer_group(group);
+                        for (key, offset) in offsets {
+                            if let Some(log) = self.logs.get_mut(&key) {
+                                log.reset_offset(&group, offset);
+                            }
+                        }
+
+                        reply.body.payload = KafkaLogPayload::ResetOffsetsOk;
+                        output.write(&reply)?;
+                    }
+                    KafkaLogPayload::ResetOffsetsOk => {}
+                    KafkaLogPayload::ListGroups => {
+                        let groups = self
+                            .logs
+                            .values()
+                            .flat_map(KafkaLog::groups)
+                            .cloned()
+                            .collect::<BTreeSet<_>>();
+
+                        reply.body.payload = KafkaLogPayload::ListGroupsOk {
+                            groups: groups.into_iter().collect(),
+                        };
+                        output.write(&reply)?;
+                    }
+                    KafkaLogPayload::ListGroupsOk { .. } => {}
+                }
+            }
+            Event::InjectedPayload(()) => {
+                for log in self.logs.values_mut() {
+                    log.tick();
                 }
             }
-            Event::InjectedPayload(_) => {}
         };
 
         Ok(())
     }
 }
 
+/// How much of its logs a [`KafkaLogNode`] keeps, read at startup.
+///
+/// Messages are dropped a segment of `segment_size` offsets at a time, oldest first, as soon
+/// as any of the enabled rules allows it. The segment being appended to is always kept.
+/// Compaction then applies to the sealed segments left.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KafkaRetention {
+    pub segment_size: usize,
+    /// Messages kept at least, the segments before them being dropped.
+    pub max_messages: Option<usize>,
+    /// Drops the segments every consumer group committed past.
+    pub committed: bool,
+    /// Drops the segments not appended to for this long.
+    pub max_age: Option<Duration>,
+    /// Compacts the sealed segments every consumer group committed past, leaving only the
+    /// newest message of all compacted segments at its offset. Nothing is compacted on a log
+    /// no group committed on. With `committed` such a segment is dropped before it could be
+    /// compacted, so compaction only has an effect without it.
+    pub compaction: bool,
+}
+
+impl Default for KafkaRetention {
+    /// Keeps every message.
+    fn default() -> Self {
+        Self {
+            segment_size: 32,
+            max_messages: None,
+            committed: false,
+            max_age: None,
+            compaction: false,
+        }
+    }
+}
+
+impl KafkaRetention {
+    /// Reads `kafka-segment-size` (32 by default), `kafka-retention-messages`,
+    /// `kafka-retention-committed` (`false` by default), `kafka-retention-ms` and
+    /// `kafka-compaction` (`false` by default).
+    pub fn from_config() -> anyhow::Result<Self> {
+        let retention = Self {
+            segment_size: config::parse("kafka-segment-size")?.unwrap_or(32),
+            max_messages: config::parse("kafka-retention-messages")?,
+            committed: config::parse("kafka-retention-committed")?.unwrap_or(false),
+            max_age: config::millis("kafka-retention-ms")?,
+            compaction: config::parse("kafka-compaction")?.unwrap_or(false),
+        };
+        anyhow::ensure!(
+            retention.segment_size > 0,
+            "kafka-segment-size must be greater than 0"
+        );
+        Ok(retention)
+    }
+}
+
+/// A poll below the low-water mark of a log, whose messages were dropped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct OffsetOutOfRange {
+    pub(crate) low_water_mark: usize,
+}
+
+impl Display for OffsetOutOfRange {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "offsets below {} were dropped", self.low_water_mark)
+    }
+}
+
+impl std::error::Error for OffsetOutOfRange {}
+
 #[derive(Debug, Clone, Default)]
-struct KafkaLog {
+pub(crate) struct KafkaLog {
     max_poll: usize,
-    committed_up_to: usize,
-    messages: Vec<usize>,
+    retention: KafkaRetention,
+    /// Committed offset of each consumer group.
+    committed_up_to: HashMap<String, usize>,
+    /// Offset of the first retained message, the start of a segment.
+    low_water_mark: usize,
+    /// End of the compacted segments, which start at `low_water_mark`.
+    compacted_end: usize,
+    /// Offset and message of the newest message of the compacted segments, while retained.
+    kept: Option<(usize, usize)>,
+    /// Messages from `compacted_end`.
+    messages: VecDeque<usize>,
+    /// Retention ticks so far.
+    ticks: u64,
+    /// Tick each retained segment, compacted or not, was last appended to at.
+    appended_at: VecDeque<u64>,
 }
 
 impl KafkaLog {
-    fn new(max_poll: usize, start_capacity: usize) -> Self {
+    pub(crate) fn new(max_poll: usize, start_capacity: usize) -> Self {
         Self {
             max_poll,
-            committed_up_to: 0,
-            messages: Vec::with_capacity(start_capacity),
+            messages: VecDeque::with_capacity(start_capacity),
+            ..Self::default()
         }
     }
 
-    fn append(&mut self, msg: usize) -> usize {
-        let offset = self.messages.len();
-        self.messages.push(msg);
+    pub(crate) fn with_retention(mut self, retention: KafkaRetention) -> Self {
+        self.retention = retention;
+        self
+    }
+
+    pub(crate) fn append(&mut self, msg: usize) -> usize {
+        let offset = self.len();
+        let segment_size = self.retention.segment_size;
+        if offset.is_multiple_of(segment_size) || self.appended_at.is_empty() {
+            self.appended_at.push_back(self.ticks);
+        } else if let Some(appended_at) = self.appended_at.back_mut() {
+            *appended_at = self.ticks;
+        }
+
+        self.messages.push_back(msg);
+        self.apply_retention();
         offset
     }
 
-    fn poll(&self, start_offset: usize) -> Vec<(usize, usize)> {
-        if start_offset >= self.messages.len() {
-            return vec![];
+    /// Advances the age of the segments by a tick of the retention timer.
+    pub(crate) fn tick(&mut self) {
+        self.ticks += 1;
+        self.apply_retention();
+    }
+
+    /// Drops the oldest segments the retention rules allow to, then compacts the sealed
+    /// segments left if enabled.
+    pub(crate) fn apply_retention(&mut self) {
+        let segment_size = self.retention.segment_size;
+        let committed_by_all = self.committed_up_to.values().min().copied();
+        while self.len() - self.low_water_mark > segment_size {
+            let segment_end = self.low_water_mark + segment_size;
+            let retained = self.len() - self.low_water_mark;
+            let retention = &self.retention;
+            let beyond_count = retention
+                .max_messages
+                .is_some_and(|max_messages| retained - segment_size >= max_messages);
+            let committed = retention.committed
+                && committed_by_all.is_some_and(|committed| segment_end <= committed);
+            let expired =
+                retention.max_age.is_some() && self.ticks - self.appended_at[0] >= AGE_TICKS;
+            if !(beyond_count || committed || expired) {
+                break;
+            }
+
+            if self.compacted_end < segment_end {
+                self.messages.drain(..segment_end - self.compacted_end);
+                self.compacted_end = segment_end;
+            }
+            if self.kept.is_some_and(|(offset, _)| offset < segment_end) {
+                self.kept = None;
+            }
+            self.appended_at.pop_front();
+            self.low_water_mark = segment_end;
+        }
+
+        if self.retention.compaction {
+            self.compact(committed_by_all);
+        }
+    }
+
+    /// Compacts the sealed segments every consumer group committed past into `kept`.
+    fn compact(&mut self, committed_by_all: Option<usize>) {
+        let Some(committed) = committed_by_all else {
+            return;
+        };
+        let segment_size = self.retention.segment_size;
+        while self.messages.len() > segment_size {
+            let segment_end = self.compacted_end + segment_size;
+            if committed < segment_end {
+                break;
+            }
+
+            self.kept = Some((segment_end - 1, self.messages[segment_size - 1]));
+            self.messages.drain(..segment_size);
+            self.compacted_end = segment_end;
         }
+    }
 
-        let end = std::cmp::min(self.messages.len(), start_offset + self.max_poll);
-        self.messages[start_offset..end]
+    /// Returns up to `max_poll` messages from `start_offset`, of which only the newest is
+    /// left in the compacted segments.
+    pub(crate) fn poll(
+        &self,
+        start_offset: usize,
+    ) -> Result<Vec<(usize, usize)>, OffsetOutOfRange> {
+        if start_offset < self.low_water_mark {
+            return Err(OffsetOutOfRange {
+                low_water_mark: self.low_water_mark,
+            });
+        }
+        if start_offset >= self.len() {
+            return Ok(vec![]);
+        }
+
+        let kept = self.kept.filter(|(offset, _)| *offset >= start_offset);
+        let from = start_offset.max(self.compacted_end);
+        Ok(kept
+            .into_iter()
+            .chain(
+                self.messages
+                    .range(from - self.compacted_end..)
+                    .enumerate()
+                    .map(|(i, msg)| (from + i, *msg)),
+            )
+            .take(self.max_poll)
+            .collect())
+    }
+
+    /// End of the compacted segments if a poll from `start_offset` reaches into them: the
+    /// offsets below it that the poll skips were compacted away.
+    pub(crate) fn compacted_past(&self, start_offset: usize) -> Option<usize> {
+        (start_offset < self.compacted_end).then_some(self.compacted_end)
+    }
+
+    /// Advances the committed offset of `group` to `offset`, never moving it back.
+    pub(crate) fn commit_offset(&mut self, group: &str, offset: usize) {
+        let committed = self.committed_up_to.get(group);
+        if offset < self.len() && committed.is_none_or(|committed| offset > *committed) {
+            self.committed_up_to.insert(group.to_string(), offset);
+            self.apply_retention();
+        }
+    }
+
+    /// Sets the committed offset of `group` to `offset`, even if it is behind.
+    pub(crate) fn reset_offset(&mut self, group: &str, offset: usize) {
+        if offset < self.len() {
+            self.committed_up_to.insert(group.to_string(), offset);
+            self.apply_retention();
+        }
+    }
+
+    pub(crate) fn get_committed_offset(&self, group: &str) -> usize {
+        self.committed_up_to.get(group).copied().unwrap_or(0)
+    }
+
+    /// Consumer groups that committed an offset of the log.
+    pub(crate) fn groups(&self) -> impl Iterator<Item = &String> {
+        self.committed_up_to.keys()
+    }
+
+    /// Offset of the next message appended.
+    pub(crate) fn len(&self) -> usize {
+        self.compacted_end + self.messages.len()
+    }
+
+    /// Messages from `offset`, of a log that keeps every message.
+    pub(crate) fn messages_from(&self, offset: usize) -> Vec<usize> {
+        self.messages
             .iter()
-            .enumerate()
-            .map(|(i, &msg)| {
-                (start_offset + i, msg)
-            })
+            .skip(offset.saturating_sub(self.compacted_end))
+            .copied()
             .collect()
     }
 
-    fn commit_offset(&mut self, offset: usize) {
-        if offset < self.messages.len() {
-            self.committed_up_to = offset;
+    /// Appends the part of `msgs`, starting at `offset`, that is not in the log yet.
+    /// Returns `false` if `offset` is past the end of the log.
+    pub(crate) fn append_from(&mut self, offset: usize, msgs: &[usize]) -> bool {
+        if offset > self.len() {
+            return false;
+        }
+        let known = self.len() - offset;
+        for msg in msgs.iter().skip(known) {
+            self.append(*msg);
         }
+        true
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn log(retention: KafkaRetention) -> KafkaLog {
+        KafkaLog::new(100, 16).with_retention(KafkaRetention {
+            segment_size: 2,
+            ..retention
+        })
+    }
+
+    #[test]
+    fn keeps_every_message_by_default() {
+        let mut log = log(KafkaRetention::default());
+        for msg in 0..10 {
+            assert_eq!(log.append(msg), msg);
+        }
+        log.tick();
+        assert_eq!(log.poll(0).unwrap().len(), 10);
+        assert_eq!(log.poll(8), Ok(vec![(8, 8), (9, 9)]));
+    }
+
+    #[test]
+    fn drops_whole_segments_beyond_the_message_count() {
+        let mut log = log(KafkaRetention {
+            max_messages: Some(3),
+            ..KafkaRetention::default()
+        });
+        for msg in 0..5 {
+            log.append(msg);
+        }
+
+        assert_eq!(log.poll(1), Err(OffsetOutOfRange { low_water_mark: 2 }));
+        assert_eq!(log.poll(2), Ok(vec![(2, 2), (3, 3), (4, 4)]));
+        assert_eq!(log.len(), 5);
+    }
+
+    #[test]
+    fn drops_segments_once_every_group_committed_past_them() {
+        let mut log = log(KafkaRetention {
+            committed: true,
+            ..KafkaRetention::default()
+        });
+        for msg in 0..6 {
+            log.append(msg);
+        }
+
+        log.commit_offset("b", 3);
+        log.commit_offset("a", 4);
+        assert_eq!(log.poll(1), Err(OffsetOutOfRange { low_water_mark: 2 }));
+        assert_eq!(log.poll(2).map(|msgs| msgs.len()), Ok(4));
+
+        log.commit_offset("b", 5);
+        assert_eq!(log.poll(2), Err(OffsetOutOfRange { low_water_mark: 4 }));
+    }
+
+    #[test]
+    fn drops_segments_not_appended_to_for_enough_ticks() {
+        let mut log = log(KafkaRetention {
+            max_age: Some(Duration::from_millis(100)),
+            ..KafkaRetention::default()
+        });
+        log.append(0);
+        log.append(1);
+        for _ in 0..AGE_TICKS - 1 {
+            log.tick();
+        }
+        log.append(2);
+        log.tick();
+        assert_eq!(log.poll(0), Err(OffsetOutOfRange { low_water_mark: 2 }));
+
+        log.append(3);
+        log.append(4);
+        for _ in 0..AGE_TICKS - 1 {
+            log.tick();
+        }
+        assert_eq!(log.poll(2), Ok(vec![(2, 2), (3, 3), (4, 4)]));
+        log.tick();
+        assert_eq!(log.poll(2), Err(OffsetOutOfRange { low_water_mark: 4 }));
+    }
+
+    #[test]
+    fn compacts_nothing_before_a_group_committed() {
+        let mut log = log(KafkaRetention {
+            compaction: true,
+            ..KafkaRetention::default()
+        });
+        for offset in 0..5 {
+            log.append(10 + offset);
+        }
+        assert_eq!(log.poll(0).map(|msgs| msgs.len()), Ok(5));
+        assert_eq!(log.compacted_past(0), None);
+
+        log.commit_offset("a", 4);
+        assert_eq!(log.poll(0), Ok(vec![(3, 13), (4, 14)]));
+        assert_eq!(log.compacted_past(0), Some(4));
+        assert_eq!(log.poll(4), Ok(vec![(4, 14)]));
+        assert_eq!(log.compacted_past(4), None);
+        assert_eq!(log.len(), 5);
+    }
+
+    #[test]
+    fn compacts_only_the_segments_every_group_committed_past() {
+        let mut log = log(KafkaRetention {
+            compaction: true,
+            ..KafkaRetention::default()
+        });
+        log.append(10);
+        log.commit_offset("a", 0);
+        for offset in 1..6 {
+            log.append(10 + offset);
+        }
+        assert_eq!(log.poll(0).map(|msgs| msgs.len()), Ok(6));
+
+        log.commit_offset("a", 3);
+        assert_eq!(
+            log.poll(0),
+            Ok(vec![(1, 11), (2, 12), (3, 13), (4, 14), (5, 15)])
+        );
+        assert_eq!(log.compacted_past(0), Some(2));
+    }
+
+    #[test]
+    fn drops_compacted_segments_beyond_the_message_count() {
+        let mut log = log(KafkaRetention {
+            max_messages: Some(4),
+            compaction: true,
+            ..KafkaRetention::default()
+        });
+        for offset in 0..7 {
+            log.append(10 + offset);
+        }
+        log.commit_offset("a", 6);
+        assert_eq!(log.poll(0), Err(OffsetOutOfRange { low_water_mark: 2 }));
+        assert_eq!(log.poll(2), Ok(vec![(5, 15), (6, 16)]));
 
-    fn get_committed_offset(&self) -> usize {
-        self.committed_up_to
+        log.append(17);
+        log.append(18);
+        log.commit_offset("a", 8);
+        assert_eq!(log.poll(3), Err(OffsetOutOfRange { low_water_mark: 4 }));
+        assert_eq!(log.poll(4), Ok(vec![(7, 17), (8, 18)]));
+        assert_eq!(log.compacted_past(4), Some(8));
     }
 }
@@ -1,41 +1,77 @@
+use crate::config;
+use crate::crdt::{AntiEntropy, IntervalSet};
 use crate::node::{Node, common_init_node};
-use crate::payloads::{BroadcastPayload, Event, InitPayload, InjectedPayload};
+use crate::payloads::{BroadcastPayload, Event, GossipSet, InitPayload, InjectedPayload};
 use crate::stdout_json::StdoutJson;
+use crate::timer::Timers;
 use crate::{Body, Message};
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::time::Duration;
+
+pub mod delivery;
+pub mod push;
+pub mod topology;
+
+pub use delivery::{BackoffConfig, GossipDelivery, PeerHealth};
+pub use push::{PushConfig, PushFanout};
+pub use topology::Topology;
 
 #[derive(Debug, Clone)]
 pub struct MultiNodeBroadcast {
     pub id: String,
     pub msg_id: usize,
-    pub broadcast_messages: HashSet<usize>,
-    pub adj: HashSet<String>,
-    pub known: HashMap<String, HashSet<usize>>,
-    pub msg_communicated: HashMap<usize, HashSet<usize>>,
+    pub node_ids: Vec<String>,
+    pub broadcast_messages: IntervalSet,
+    pub anti_entropy: AntiEntropy<IntervalSet>,
+    pub topology: Topology,
+    /// Topology received from Maelstrom.
+    pub given_topology: HashMap<String, Vec<String>>,
+    /// Neighbors routed around by dynamic topologies.
+    pub down: HashSet<String>,
+    /// Immediate forwarding of new values, when enabled. Gossip then only repairs losses.
+    pub push: Option<PushFanout>,
+    /// Gossip waiting for an acknowledgement, retransmitted with backoff.
+    pub delivery: GossipDelivery,
+    /// Whether gossip lists runs of values instead of every value.
+    pub compact_gossip: bool,
+    timers: Timers<InjectedPayload>,
 }
 
 impl Node<BroadcastPayload, InjectedPayload> for MultiNodeBroadcast {
     fn init(
         init_msg: Message<InitPayload>,
         output: &mut StdoutJson,
-        tx_channel: std::sync::mpsc::Sender<Event<BroadcastPayload, InjectedPayload>>,
+        timers: &Timers<InjectedPayload>,
     ) -> anyhow::Result<Self>
     where
         Self: Sized,
     {
+        let topology = Topology::from_config()?;
+        let push_config = PushConfig::from_config()?;
+        let backoff = BackoffConfig::from_config()?;
         let (node_id, node_ids) = common_init_node(init_msg, output)?;
-        let multi_node_broadcast = Self {
+        let mut multi_node_broadcast = Self {
             id: node_id,
             msg_id: 0,
-            broadcast_messages: HashSet::new(),
-            adj: HashSet::with_capacity(node_ids.len()),
-            known: node_ids
-                .into_iter()
-                .map(|id| (id, HashSet::new()))
-                .collect(),
-            msg_communicated: Default::default(),
+            node_ids,
+            broadcast_messages: IntervalSet::new(),
+            anti_entropy: AntiEntropy::new([]),
+            topology,
+            given_topology: HashMap::new(),
+            down: HashSet::new(),
+            push: push_config.map(PushFanout::new),
+            delivery: GossipDelivery::new(backoff),
+            compact_gossip: config::parse("compact-gossip")?.unwrap_or(false),
+            timers: timers.clone(),
         };
-        Self::spawn_gossiping_thread(tx_channel);
+        if multi_node_broadcast.topology != Topology::Maelstrom {
+            multi_node_broadcast.rebuild_topology();
+        }
+        if let Some(push_config) = push_config {
+            timers.every("flush", push_config.window, InjectedPayload::Flush)?;
+        }
+        let interval = config::millis_or("gossip-interval-ms", Duration::from_millis(1000))?;
+        timers.every_with_jitter("gossip", interval, interval / 10, InjectedPayload::Gossip)?;
         Ok(multi_node_broadcast)
     }
 
@@ -51,7 +87,10 @@ impl Node<BroadcastPayload, InjectedPayload> for MultiNodeBroadcast {
                 let mut reply = message.into_reply(Some(&mut self.msg_id));
                 match reply.body.payload {
                     BroadcastPayload::Broadcast { message } => {
-                        self.broadcast_messages.insert(message);
+                        if self.broadcast_messages.insert(message) {
+                            self.anti_entropy.record(IntervalSet::from_iter([message]));
+                            self.push(message, None);
+                        }
                         reply.body.payload = BroadcastPayload::BroadcastOk;
                         output.write(&reply)?;
                     }
@@ -61,59 +100,95 @@ impl Node<BroadcastPayload, InjectedPayload> for MultiNodeBroadcast {
                         };
                         output.write(&reply)?;
                     }
-                    BroadcastPayload::Topology { .. } => {
-                        self.adj = self.construct_fully_connected_topology().collect();
+                    BroadcastPayload::Topology { topology } => {
+                        self.given_topology = topology;
+                        self.rebuild_topology();
                         reply.body.payload = BroadcastPayload::TopologyOk;
                         output.write(&reply)?;
                     }
-                    BroadcastPayload::Gossip { seen } => {
-                        self.broadcast_messages.extend(seen.clone());
-                        if let Some(adj_seen) = self.known.get_mut(&src) {
-                            adj_seen.extend(seen);
-                        }
-                        reply.body.payload = BroadcastPayload::GossipOk;
+                    BroadcastPayload::Gossip { seen, upto } => {
+                        self.anti_entropy.receive(
+                            &mut self.broadcast_messages,
+                            &src,
+                            seen.into_set(),
+                        );
+                        reply.body.payload = BroadcastPayload::GossipOk { upto };
                         output.write(&reply)?;
                     }
-                    BroadcastPayload::GossipOk => {
-                        if let Some((adj_seen, msg_communicated)) =
-                            self.known.get_mut(&src).zip(self.msg_communicated.get_mut(
-                                &in_reply_to.expect("gossip ok should have in_reply_to field"),
-                            ))
+                    BroadcastPayload::GossipOk { upto } => {
+                        self.anti_entropy.ack(&src, upto);
+                        if let Some(in_reply_to) = in_reply_to
+                            && self.delivery.ack(&src, in_reply_to)
                         {
-                            adj_seen.extend(msg_communicated.clone());
+                            self.timers.cancel(&Self::retransmit_timer(&src));
+                        }
+                    }
+                    BroadcastPayload::Forward { messages } => {
+                        let new = messages
+                            .into_iter()
+                            .filter(|message| !self.broadcast_messages.contains(message))
+                            .collect::<IntervalSet>();
+                        for message in new.iter() {
+                            self.push(message, Some(&src));
                         }
+                        self.anti_entropy
+                            .receive(&mut self.broadcast_messages, &src, new);
+                        reply.body.payload = BroadcastPayload::ForwardOk;
+                        output.write(&reply)?;
                     }
-                    BroadcastPayload::TopologyOk { .. }
+                    BroadcastPayload::ForwardOk => {
+                        if let (Some(push), Some(in_reply_to)) = (&mut self.push, in_reply_to) {
+                            push.ack(in_reply_to);
+                        }
+                    }
+                    BroadcastPayload::TopologyOk
                     | BroadcastPayload::ReadOk { .. }
                     | BroadcastPayload::BroadcastOk => {}
                 };
             }
             Event::InjectedPayload(injected_payload) => match injected_payload {
                 InjectedPayload::Gossip => {
-                    for adj_node_id in &self.adj {
-                        if self.is_fully_synced(adj_node_id) {
-                            continue;
-                        }
-
-                        let unseen_messages = self
-                            .get_unseen_messages(adj_node_id)
-                            .collect::<HashSet<_>>();
-                        let gossip_msg = Message {
+                    if self.topology.is_dynamic() {
+                        self.update_down_neighbors();
+                    }
+                    // Peers with gossip outstanding are served by their retransmissions.
+                    let delivery = &self.delivery;
+                    let outgoing = self
+                        .anti_entropy
+                        .outgoing_to(&self.broadcast_messages, |peer| {
+                            !delivery.is_outstanding(peer)
+                        });
+                    self.send_gossip(outgoing, output)?;
+                }
+                InjectedPayload::Retransmit { peer } => {
+                    if !self.delivery.retry(&peer) {
+                        return Ok(());
+                    }
+                    let outgoing = self
+                        .anti_entropy
+                        .outgoing_to(&self.broadcast_messages, |adj_node_id| adj_node_id == peer);
+                    if outgoing.is_empty() {
+                        self.delivery.settle(&peer);
+                    }
+                    self.send_gossip(outgoing, output)?;
+                }
+                InjectedPayload::Flush => {
+                    let Some(push) = &mut self.push else {
+                        return Ok(());
+                    };
+                    for (msg_id, adj_node_id, messages) in push.flush(&mut self.msg_id) {
+                        let forward_msg = Message {
                             src: self.id.clone(),
-                            dst: adj_node_id.clone(),
+                            dst: adj_node_id,
                             body: Body {
-                                msg_id: Some(self.msg_id),
+                                msg_id: Some(msg_id),
                                 in_reply_to: None,
-                                payload: BroadcastPayload::Gossip {
-                                    seen: unseen_messages.clone(),
+                                payload: BroadcastPayload::Forward {
+                                    messages: messages.into_iter().collect(),
                                 },
                             },
                         };
-
-                        self.msg_communicated.insert(self.msg_id, unseen_messages);
-                        self.msg_id += 1;
-
-                        output.write(&gossip_msg)?;
+                        output.write(&forward_msg)?;
                     }
                 }
             },
@@ -124,66 +199,88 @@ impl Node<BroadcastPayload, InjectedPayload> for MultiNodeBroadcast {
 }
 
 impl MultiNodeBroadcast {
-    fn spawn_gossiping_thread(
-        tx_channel: std::sync::mpsc::Sender<Event<BroadcastPayload, InjectedPayload>>,
-    ) {
-        std::thread::spawn(move || {
-            loop {
-                std::thread::sleep(std::time::Duration::from_millis(1000));
-                if let Err(_) = tx_channel.send(Event::InjectedPayload(InjectedPayload::Gossip)) {
-                    break;
-                }
-            }
-        });
+    /// Health of each neighbor, from its acknowledgements of gossip.
+    pub fn peer_health(&self) -> impl Iterator<Item = (&String, PeerHealth)> {
+        self.anti_entropy
+            .peers()
+            .map(|peer| (peer, self.delivery.health(peer)))
     }
 
-    #[allow(dead_code)]
-    fn construct_maelstrom_topology(
+    fn send_gossip(
         &mut self,
-        topology: &mut HashMap<String, Vec<String>>,
-    ) -> impl Iterator<Item = String> {
-        topology
-            .remove(&self.id)
-            .expect(&format!("topology for node {} not received!", self.id))
-            .into_iter()
-            .filter(|adj_node_id| adj_node_id != &self.id)
-    }
+        outgoing: Vec<(String, u64, IntervalSet)>,
+        output: &mut StdoutJson,
+    ) -> anyhow::Result<()> {
+        for (adj_node_id, upto, unseen_messages) in outgoing {
+            let msg_id = self.msg_id;
+            self.msg_id += 1;
+            let retransmit_after = self.delivery.sent(&adj_node_id, msg_id);
+            self.timers.after(
+                Self::retransmit_timer(&adj_node_id),
+                retransmit_after,
+                InjectedPayload::Retransmit {
+                    peer: adj_node_id.clone(),
+                },
+            );
 
-    #[allow(dead_code)]
-    fn construct_tree_topology(&self) -> impl Iterator<Item = String> {
-        let mut node_ids = self.known.keys().collect::<Vec<_>>();
-        node_ids.sort();
-        let start = node_ids.binary_search(&&self.id).unwrap();
-        let mut topology = Vec::with_capacity(2);
-        if let Some(left) = node_ids.get((start * 2) + 1) {
-            topology.push(left.to_string());
+            let gossip_msg = Message {
+                src: self.id.clone(),
+                dst: adj_node_id,
+                body: Body {
+                    msg_id: Some(msg_id),
+                    in_reply_to: None,
+                    payload: BroadcastPayload::Gossip {
+                        seen: GossipSet::encode(unseen_messages, self.compact_gossip),
+                        upto,
+                    },
+                },
+            };
+            output.write(&gossip_msg)?;
         }
-        if let Some(right) = node_ids.get((start * 2) + 2) {
-            topology.push(right.to_string());
-        }
-        topology.into_iter()
+        Ok(())
+    }
+
+    fn retransmit_timer(peer: &str) -> String {
+        format!("retransmit-{}", peer)
     }
 
-    fn construct_fully_connected_topology(&mut self) -> impl Iterator<Item = String> {
-        let node_ids = self
-            .known
-            .keys()
-            .filter(|node_id| **node_id != self.id)
-            .cloned()
-            .collect::<Vec<_>>();
-        node_ids.into_iter()
+    /// Queues a new value for every neighbor but the one it came from.
+    fn push(&mut self, message: usize, from: Option<&String>) {
+        let Some(push) = &mut self.push else {
+            return;
+        };
+        push.push(
+            self.anti_entropy.peers().filter(|peer| Some(*peer) != from),
+            message,
+        );
     }
 
-    fn is_fully_synced(&self, adj: impl AsRef<str>) -> bool {
-        let seen_by_adj = &self.known[adj.as_ref()];
-        seen_by_adj.len() == self.broadcast_messages.len()
+    /// Recomputes the neighbors to gossip with. Down neighbors stay peers, so they
+    /// keep being probed and rejoin the topology once they answer again.
+    fn rebuild_topology(&mut self) {
+        let neighbors =
+            self.topology
+                .neighbors(&self.id, &self.node_ids, &self.given_topology, &self.down);
+        let peers = neighbors
+            .into_iter()
+            .chain(self.down.iter().cloned())
+            .collect::<BTreeSet<_>>();
+        if let Some(push) = &mut self.push {
+            push.retain_peers(&peers);
+        }
+        self.delivery.retain_peers(&peers);
+        self.anti_entropy.set_peers(peers);
     }
 
-    fn get_unseen_messages(&self, adj: impl AsRef<str>) -> impl Iterator<Item = usize> {
-        let seen_by_adj = &self.known[adj.as_ref()];
-        self.broadcast_messages
-            .iter()
-            .filter(|msg| !seen_by_adj.contains(*msg))
-            .copied()
+    fn update_down_neighbors(&mut self) {
+        let down = self
+            .peer_health()
+            .filter(|(_, health)| *health == PeerHealth::Unreachable)
+            .map(|(peer, _)| peer.clone())
+            .collect::<HashSet<_>>();
+        if down != self.down {
+            self.down = down;
+            self.rebuild_topology();
+        }
     }
 }
@@ -0,0 +1,188 @@
+use crate::config;
+use std::collections::{BTreeSet, HashMap};
+use std::time::Duration;
+
+/// Retries without an acknowledgement after which a peer is considered unreachable.
+const UNREACHABLE_RETRIES: u32 = 3;
+
+/// Health of a peer, judged from the gossip it left unacknowledged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PeerHealth {
+    Healthy,
+    /// Gossip had to be retransmitted.
+    Suspect,
+    /// Several retransmissions in a row went unacknowledged.
+    Unreachable,
+}
+
+/// Retransmission delays of unacknowledged gossip, read at startup.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BackoffConfig {
+    pub initial: Duration,
+    pub max: Duration,
+}
+
+impl BackoffConfig {
+    /// Reads `gossip-retry-ms` (200 by default) and `gossip-max-backoff-ms` (5000 by default).
+    pub fn from_config() -> anyhow::Result<Self> {
+        Ok(Self {
+            initial: config::millis_or("gossip-retry-ms", Duration::from_millis(200))?,
+            max: config::millis_or("gossip-max-backoff-ms", Duration::from_millis(5000))?,
+        })
+    }
+
+    fn delay(&self, retries: u32) -> Duration {
+        self.initial
+            .saturating_mul(2u32.saturating_pow(retries))
+            .min(self.max)
+    }
+}
+
+/// Gossip sent to each peer and not acknowledged yet, by `msg_id`.
+///
+/// A peer has a single retransmission pending at a time: a retransmission carries
+/// everything the peer still misses and supersedes the gossip sent before it, whose
+/// late acknowledgements are still accepted. The delay before the next retransmission
+/// doubles with each one that goes unanswered, so a partitioned peer is probed less
+/// and less often.
+#[derive(Debug, Clone)]
+pub struct GossipDelivery {
+    backoff: BackoffConfig,
+    outstanding: HashMap<String, Outstanding>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Outstanding {
+    msg_ids: BTreeSet<usize>,
+    retries: u32,
+}
+
+impl GossipDelivery {
+    pub fn new(backoff: BackoffConfig) -> Self {
+        Self {
+            backoff,
+            outstanding: HashMap::new(),
+        }
+    }
+
+    pub fn is_outstanding(&self, peer: &str) -> bool {
+        self.outstanding
+            .get(peer)
+            .is_some_and(|outstanding| !outstanding.msg_ids.is_empty())
+    }
+
+    /// Records gossip sent to `peer` and returns how long to wait for its acknowledgement.
+    pub fn sent(&mut self, peer: &str, msg_id: usize) -> Duration {
+        let outstanding = self.outstanding.entry(peer.to_string()).or_default();
+        outstanding.msg_ids.insert(msg_id);
+        self.backoff.delay(outstanding.retries)
+    }
+
+    /// Counts a retransmission to `peer`. Returns `false` if nothing is outstanding.
+    pub fn retry(&mut self, peer: &str) -> bool {
+        match self.outstanding.get_mut(peer) {
+            Some(outstanding) if !outstanding.msg_ids.is_empty() => {
+                outstanding.retries += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Records the acknowledgement of `in_reply_to`, which also covers older gossip.
+    /// Returns `true` if nothing is outstanding to `peer` anymore.
+    pub fn ack(&mut self, peer: &str, in_reply_to: usize) -> bool {
+        let Some(outstanding) = self.outstanding.get_mut(peer) else {
+            return true;
+        };
+        outstanding.msg_ids = outstanding.msg_ids.split_off(&(in_reply_to + 1));
+        outstanding.retries = 0;
+        outstanding.msg_ids.is_empty()
+    }
+
+    /// Forgets the gossip outstanding to `peer`, e.g. when it has nothing left to receive.
+    pub fn settle(&mut self, peer: &str) {
+        if let Some(outstanding) = self.outstanding.get_mut(peer) {
+            outstanding.msg_ids.clear();
+        }
+    }
+
+    pub fn health(&self, peer: &str) -> PeerHealth {
+        match self
+            .outstanding
+            .get(peer)
+            .map_or(0, |outstanding| outstanding.retries)
+        {
+            0 => PeerHealth::Healthy,
+            retries if retries < UNREACHABLE_RETRIES => PeerHealth::Suspect,
+            _ => PeerHealth::Unreachable,
+        }
+    }
+
+    pub fn retain_peers(&mut self, peers: &BTreeSet<String>) {
+        self.outstanding.retain(|peer, _| peers.contains(peer));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn delivery() -> GossipDelivery {
+        GossipDelivery::new(BackoffConfig {
+            initial: Duration::from_millis(100),
+            max: Duration::from_millis(500),
+        })
+    }
+
+    #[test]
+    fn doubles_the_delay_with_each_retry_up_to_the_max() {
+        let mut delivery = delivery();
+        let delays = (0..5)
+            .map(|msg_id| {
+                let delay = delivery.sent("n1", msg_id);
+                assert!(delivery.retry("n1"));
+                delay.as_millis()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(delays, vec![100, 200, 400, 500, 500]);
+
+        delivery.ack("n1", 4);
+        assert_eq!(delivery.sent("n1", 5), Duration::from_millis(100));
+    }
+
+    #[test]
+    fn tracks_health_from_unacknowledged_retries() {
+        let mut delivery = delivery();
+        assert_eq!(delivery.health("n1"), PeerHealth::Healthy);
+        assert!(!delivery.retry("n1"));
+
+        delivery.sent("n1", 0);
+        for _ in 0..UNREACHABLE_RETRIES - 1 {
+            delivery.retry("n1");
+            assert_eq!(delivery.health("n1"), PeerHealth::Suspect);
+        }
+        delivery.retry("n1");
+        assert_eq!(delivery.health("n1"), PeerHealth::Unreachable);
+        assert_eq!(delivery.health("n2"), PeerHealth::Healthy);
+
+        assert!(delivery.ack("n1", 0));
+        assert_eq!(delivery.health("n1"), PeerHealth::Healthy);
+    }
+
+    #[test]
+    fn an_acknowledgement_covers_older_gossip_only() {
+        let mut delivery = delivery();
+        delivery.sent("n1", 1);
+        delivery.sent("n1", 3);
+        assert!(!delivery.ack("n1", 2));
+        assert!(delivery.is_outstanding("n1"));
+        assert!(delivery.ack("n1", 3));
+        assert!(!delivery.is_outstanding("n1"));
+
+        delivery.sent("n1", 4);
+        delivery.settle("n1");
+        assert!(!delivery.is_outstanding("n1"));
+        assert!(!delivery.retry("n1"));
+    }
+}
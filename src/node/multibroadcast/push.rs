@@ -0,0 +1,205 @@
+use crate::config;
+use std::collections::{BTreeSet, HashMap};
+use std::time::Duration;
+
+/// Settings of push-based fan-out, read at startup.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PushConfig {
+    /// How long values are batched per destination before being forwarded.
+    pub window: Duration,
+    /// How long a forward waits for its acknowledgement before being sent again.
+    pub retry_after: Duration,
+}
+
+impl PushConfig {
+    /// Reads `push-window-ms` and `push-retry-ms` (500 by default).
+    /// Push fan-out is disabled when no window is set.
+    pub fn from_config() -> anyhow::Result<Option<Self>> {
+        let Some(window) = config::millis("push-window-ms")? else {
+            return Ok(None);
+        };
+        let retry_after = config::millis_or("push-retry-ms", Duration::from_millis(500))?;
+        Ok(Some(Self {
+            window,
+            retry_after,
+        }))
+    }
+
+    /// Flushes a forward waits before being retried.
+    fn retry_flushes(&self) -> usize {
+        let window = self.window.as_millis().max(1);
+        (self.retry_after.as_millis().div_ceil(window) as usize).max(1)
+    }
+}
+
+/// Values waiting to be forwarded to each neighbor, and the forwards not yet acknowledged.
+///
+/// Values are batched per destination until the next flush. A forward not acknowledged
+/// after a few flushes is merged back into the batch of its destination, so a neighbor
+/// that does not answer has at most one outstanding forward per retry.
+#[derive(Debug, Clone)]
+pub struct PushFanout {
+    config: PushConfig,
+    batches: HashMap<String, BTreeSet<usize>>,
+    in_flight: HashMap<usize, InFlight>,
+}
+
+#[derive(Debug, Clone)]
+struct InFlight {
+    peer: String,
+    messages: BTreeSet<usize>,
+    flushes: usize,
+}
+
+impl PushFanout {
+    pub fn new(config: PushConfig) -> Self {
+        Self {
+            config,
+            batches: HashMap::new(),
+            in_flight: HashMap::new(),
+        }
+    }
+
+    pub fn config(&self) -> &PushConfig {
+        &self.config
+    }
+
+    /// Queues `message` for each of `peers`.
+    pub fn push<'a>(&mut self, peers: impl IntoIterator<Item = &'a String>, message: usize) {
+        for peer in peers {
+            self.batches
+                .entry(peer.clone())
+                .or_default()
+                .insert(message);
+        }
+    }
+
+    /// The forwards to send now, with the msg_id each is sent under.
+    pub fn flush(&mut self, msg_id: &mut usize) -> Vec<(usize, String, BTreeSet<usize>)> {
+        let retry_flushes = self.config.retry_flushes();
+        let expired = self
+            .in_flight
+            .iter_mut()
+            .filter_map(|(msg_id, in_flight)| {
+                in_flight.flushes += 1;
+                (in_flight.flushes > retry_flushes).then_some(*msg_id)
+            })
+            .collect::<Vec<_>>();
+        for expired_msg_id in expired {
+            let in_flight = self.in_flight.remove(&expired_msg_id).unwrap();
+            self.batches
+                .entry(in_flight.peer)
+                .or_default()
+                .extend(in_flight.messages);
+        }
+
+        let mut outgoing = Vec::with_capacity(self.batches.len());
+        for (peer, messages) in self.batches.drain() {
+            let forward_msg_id = *msg_id;
+            *msg_id += 1;
+            self.in_flight.insert(
+                forward_msg_id,
+                InFlight {
+                    peer: peer.clone(),
+                    messages: messages.clone(),
+                    flushes: 0,
+                },
+            );
+            outgoing.push((forward_msg_id, peer, messages));
+        }
+        outgoing
+    }
+
+    pub fn ack(&mut self, in_reply_to: usize) {
+        self.in_flight.remove(&in_reply_to);
+    }
+
+    /// Drops everything queued for nodes that are no longer neighbors.
+    pub fn retain_peers(&mut self, peers: &BTreeSet<String>) {
+        self.batches.retain(|peer, _| peers.contains(peer));
+        self.in_flight
+            .retain(|_, in_flight| peers.contains(&in_flight.peer));
+    }
+
+    /// Number of forwards waiting for an acknowledgement.
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fanout() -> PushFanout {
+        PushFanout::new(PushConfig {
+            window: Duration::from_millis(100),
+            retry_after: Duration::from_millis(200),
+        })
+    }
+
+    fn peers(peers: &[&str]) -> Vec<String> {
+        peers.iter().map(|peer| peer.to_string()).collect()
+    }
+
+    #[test]
+    fn batches_values_per_destination_until_the_flush() {
+        let mut fanout = fanout();
+        fanout.push(&peers(&["n1", "n2"]), 1);
+        fanout.push(&peers(&["n1"]), 2);
+        fanout.push(&peers(&["n1"]), 1);
+
+        let mut msg_id = 0;
+        let mut outgoing = fanout.flush(&mut msg_id);
+        outgoing.sort_by(|a, b| a.1.cmp(&b.1));
+        let batches = outgoing
+            .iter()
+            .map(|(_, peer, messages)| (peer.as_str(), messages.iter().copied().collect()))
+            .collect::<Vec<(&str, Vec<usize>)>>();
+        assert_eq!(batches, vec![("n1", vec![1, 2]), ("n2", vec![1])]);
+        assert_eq!(msg_id, 2);
+        assert_eq!(fanout.in_flight(), 2);
+        assert!(fanout.flush(&mut msg_id).is_empty());
+    }
+
+    #[test]
+    fn merges_unacknowledged_forwards_back_into_the_batch() {
+        let mut fanout = fanout();
+        let mut msg_id = 0;
+        fanout.push(&peers(&["n1", "n2"]), 1);
+        let outgoing = fanout.flush(&mut msg_id);
+        let acked = outgoing
+            .iter()
+            .find(|(_, peer, _)| peer == "n2")
+            .map(|(msg_id, _, _)| *msg_id)
+            .unwrap();
+        fanout.ack(acked);
+
+        fanout.push(&peers(&["n1"]), 2);
+        let outgoing = fanout.flush(&mut msg_id);
+        assert_eq!(outgoing.len(), 1);
+        assert_eq!(outgoing[0].2, BTreeSet::from([2]));
+
+        // A forward is retried once `retry_after / window` more flushes went unacknowledged.
+        assert!(fanout.flush(&mut msg_id).is_empty());
+        let outgoing = fanout.flush(&mut msg_id);
+        assert_eq!(outgoing.len(), 1);
+        assert_eq!(outgoing[0].1, "n1");
+        assert_eq!(outgoing[0].2, BTreeSet::from([1]));
+    }
+
+    #[test]
+    fn retain_peers_forgets_former_neighbors() {
+        let mut fanout = fanout();
+        let mut msg_id = 0;
+        fanout.push(&peers(&["n1", "n2"]), 1);
+        fanout.flush(&mut msg_id);
+        fanout.push(&peers(&["n1", "n2"]), 2);
+
+        fanout.retain_peers(&BTreeSet::from(["n1".to_string()]));
+        assert_eq!(fanout.in_flight(), 1);
+        let outgoing = fanout.flush(&mut msg_id);
+        assert_eq!(outgoing.len(), 1);
+        assert_eq!(outgoing[0].1, "n1");
+    }
+}
@@ -0,0 +1,367 @@
+use crate::config;
+use crate::rng::SimRng;
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+use std::str::FromStr;
+
+/// How broadcast nodes pick the neighbors they gossip with.
+///
+/// The static topologies are undirected, so a node is always a neighbor of its neighbors.
+/// A spanning tree is built from the nodes each node itself sees down, so it is only
+/// symmetric between nodes that agree on which nodes are down.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Topology {
+    /// The topology sent by Maelstrom in the `topology` request.
+    #[default]
+    Maelstrom,
+    FullyConnected,
+    Tree {
+        arity: usize,
+    },
+    Ring,
+    Grid,
+    /// Each node has `degree` neighbors, picked at random but identically by every node.
+    ///
+    /// The degree is capped at the number of other nodes. A graph where every node has the
+    /// same odd degree needs an even number of nodes, so with an odd number of nodes an odd
+    /// degree is rounded down to `degree - 1`.
+    RandomRegular {
+        degree: usize,
+    },
+    /// A spanning tree of the Maelstrom topology, rebuilt around nodes that stop answering.
+    SpanningTree,
+}
+
+impl Topology {
+    /// Reads the `topology` setting, e.g. `tree:4` or `ring`.
+    pub fn from_config() -> anyhow::Result<Self> {
+        Ok(config::parse("topology")?.unwrap_or_default())
+    }
+
+    /// Whether neighbors change when nodes go down.
+    pub fn is_dynamic(&self) -> bool {
+        matches!(self, Topology::SpanningTree)
+    }
+
+    /// Neighbors of `node_id`. `given` is the Maelstrom topology and `down` the nodes
+    /// to route around, used by dynamic topologies only.
+    pub fn neighbors(
+        &self,
+        node_id: &str,
+        node_ids: &[String],
+        given: &HashMap<String, Vec<String>>,
+        down: &HashSet<String>,
+    ) -> BTreeSet<String> {
+        let mut node_ids = node_ids.to_vec();
+        node_ids.sort();
+        node_ids.dedup();
+        let edges = match self {
+            Topology::Maelstrom => maelstrom_edges(given),
+            Topology::FullyConnected => fully_connected_edges(&node_ids),
+            Topology::Tree { arity } => tree_edges(&node_ids, *arity),
+            Topology::Ring => ring_edges(&node_ids),
+            Topology::Grid => grid_edges(&node_ids),
+            Topology::RandomRegular { degree } => random_regular_edges(&node_ids, *degree),
+            Topology::SpanningTree => {
+                let graph = if given.is_empty() {
+                    fully_connected_edges(&node_ids)
+                } else {
+                    maelstrom_edges(given)
+                };
+                spanning_tree_edges(&node_ids, &graph, down)
+            }
+        };
+
+        edges
+            .into_iter()
+            .filter_map(|(a, b)| match (a == node_id, b == node_id) {
+                (true, false) => Some(b),
+                (false, true) => Some(a),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+impl FromStr for Topology {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, param) = match s.split_once(':') {
+            Some((name, param)) => (name, Some(param.parse::<usize>()?)),
+            None => (s, None),
+        };
+        let topology = match (name, param) {
+            ("maelstrom", None) => Topology::Maelstrom,
+            ("full", None) => Topology::FullyConnected,
+            ("tree", arity) => Topology::Tree {
+                arity: arity.unwrap_or(2),
+            },
+            ("ring", None) => Topology::Ring,
+            ("grid", None) => Topology::Grid,
+            ("random-regular", degree) => Topology::RandomRegular {
+                degree: degree.unwrap_or(3),
+            },
+            ("spanning-tree", None) => Topology::SpanningTree,
+            ("maelstrom" | "full" | "ring" | "grid" | "spanning-tree", Some(_)) => {
+                anyhow::bail!("topology {} takes no parameter", name)
+            }
+            _ => anyhow::bail!("unknown topology {}", s),
+        };
+        if param == Some(0) {
+            anyhow::bail!("topology parameter must be greater than 0");
+        }
+        Ok(topology)
+    }
+}
+
+type Edge = (String, String);
+
+fn edge(a: &str, b: &str) -> Edge {
+    if a <= b {
+        (a.to_string(), b.to_string())
+    } else {
+        (b.to_string(), a.to_string())
+    }
+}
+
+fn maelstrom_edges(given: &HashMap<String, Vec<String>>) -> BTreeSet<Edge> {
+    given
+        .iter()
+        .flat_map(|(node_id, neighbors)| neighbors.iter().map(|neighbor| edge(node_id, neighbor)))
+        .filter(|(a, b)| a != b)
+        .collect()
+}
+
+fn fully_connected_edges(node_ids: &[String]) -> BTreeSet<Edge> {
+    node_ids
+        .iter()
+        .enumerate()
+        .flat_map(|(i, a)| node_ids[i + 1..].iter().map(|b| edge(a, b)))
+        .collect()
+}
+
+fn tree_edges(node_ids: &[String], arity: usize) -> BTreeSet<Edge> {
+    (1..node_ids.len())
+        .map(|i| edge(&node_ids[i], &node_ids[(i - 1) / arity]))
+        .collect()
+}
+
+fn ring_edges(node_ids: &[String]) -> BTreeSet<Edge> {
+    let n = node_ids.len();
+    (0..n)
+        .map(|i| edge(&node_ids[i], &node_ids[(i + 1) % n]))
+        .filter(|(a, b)| a != b)
+        .collect()
+}
+
+fn grid_edges(node_ids: &[String]) -> BTreeSet<Edge> {
+    let n = node_ids.len();
+    let width = (1..=n).find(|width| width * width >= n).unwrap_or(1);
+    let mut edges = BTreeSet::new();
+    for i in 0..n {
+        if (i + 1) % width != 0 && i + 1 < n {
+            edges.insert(edge(&node_ids[i], &node_ids[i + 1]));
+        }
+        if i + width < n {
+            edges.insert(edge(&node_ids[i], &node_ids[i + width]));
+        }
+    }
+    edges
+}
+
+/// Circulant graph over a shuffled order of the nodes. The shuffle is seeded with a
+/// constant so every node builds the same graph.
+///
+/// Each node is linked to the nodes `1..=degree / 2` positions away on both sides, plus,
+/// for an odd degree, the node opposite to it, which only exists for an even node count.
+fn random_regular_edges(node_ids: &[String], degree: usize) -> BTreeSet<Edge> {
+    let n = node_ids.len();
+    let mut order = node_ids.to_vec();
+    let mut rng = SimRng::new(0x7090_1097);
+    for i in (1..n).rev() {
+        order.swap(i, rng.below(i as u64 + 1) as usize);
+    }
+
+    let degree = degree.min(n.saturating_sub(1));
+    let mut offsets = (1..=degree / 2).collect::<Vec<_>>();
+    if !degree.is_multiple_of(2) && n.is_multiple_of(2) {
+        offsets.push(n / 2);
+    }
+    (0..n)
+        .flat_map(|i| offsets.iter().map(move |offset| (i, (i + offset) % n)))
+        .map(|(i, j)| edge(&order[i], &order[j]))
+        .filter(|(a, b)| a != b)
+        .collect()
+}
+
+/// Breadth-first spanning forest of `graph` over the nodes that are not down,
+/// rooted at the smallest node id of each component.
+fn spanning_tree_edges(
+    node_ids: &[String],
+    graph: &BTreeSet<Edge>,
+    down: &HashSet<String>,
+) -> BTreeSet<Edge> {
+    let mut adjacency: HashMap<&str, BTreeSet<&str>> = HashMap::new();
+    for (a, b) in graph {
+        if down.contains(a) || down.contains(b) {
+            continue;
+        }
+        adjacency.entry(a).or_default().insert(b);
+        adjacency.entry(b).or_default().insert(a);
+    }
+
+    let mut edges = BTreeSet::new();
+    let mut visited = HashSet::new();
+    for root in node_ids.iter().filter(|node_id| !down.contains(*node_id)) {
+        if !visited.insert(root.as_str()) {
+            continue;
+        }
+        let mut queue = VecDeque::from([root.as_str()]);
+        while let Some(node_id) = queue.pop_front() {
+            for neighbor in adjacency.get(node_id).into_iter().flatten() {
+                if visited.insert(neighbor) {
+                    edges.insert(edge(node_id, neighbor));
+                    queue.push_back(neighbor);
+                }
+            }
+        }
+    }
+    edges
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node_ids(n: usize) -> Vec<String> {
+        (0..n).map(|i| format!("n{}", i)).collect()
+    }
+
+    fn set(node_ids: &[&str]) -> BTreeSet<String> {
+        node_ids.iter().map(|node_id| node_id.to_string()).collect()
+    }
+
+    fn neighbors(topology: Topology, n: usize, node_id: &str) -> BTreeSet<String> {
+        topology.neighbors(node_id, &node_ids(n), &HashMap::new(), &HashSet::new())
+    }
+
+    #[test]
+    fn parses_names_and_parameters() {
+        assert_eq!(
+            "maelstrom".parse::<Topology>().unwrap(),
+            Topology::Maelstrom
+        );
+        assert_eq!(
+            "full".parse::<Topology>().unwrap(),
+            Topology::FullyConnected
+        );
+        assert_eq!(
+            "tree".parse::<Topology>().unwrap(),
+            Topology::Tree { arity: 2 }
+        );
+        assert_eq!(
+            "tree:4".parse::<Topology>().unwrap(),
+            Topology::Tree { arity: 4 }
+        );
+        assert_eq!(
+            "random-regular".parse::<Topology>().unwrap(),
+            Topology::RandomRegular { degree: 3 }
+        );
+        assert_eq!(
+            "spanning-tree".parse::<Topology>().unwrap(),
+            Topology::SpanningTree
+        );
+    }
+
+    #[test]
+    fn rejects_bad_names_and_parameters() {
+        for bad in ["star", "tree:0", "tree:x", "ring:2", "random-regular:-1"] {
+            assert!(bad.parse::<Topology>().is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn maelstrom_follows_the_given_topology_both_ways() {
+        let given = HashMap::from([
+            ("n0".to_string(), vec!["n1".to_string()]),
+            ("n2".to_string(), vec!["n1".to_string()]),
+        ]);
+        let neighbors = Topology::Maelstrom.neighbors("n1", &node_ids(3), &given, &HashSet::new());
+        assert_eq!(neighbors, set(&["n0", "n2"]));
+    }
+
+    #[test]
+    fn builds_the_static_topologies() {
+        assert_eq!(
+            neighbors(Topology::FullyConnected, 4, "n0"),
+            set(&["n1", "n2", "n3"])
+        );
+        let tree = Topology::Tree { arity: 2 };
+        assert_eq!(neighbors(tree, 7, "n1"), set(&["n0", "n3", "n4"]));
+        assert_eq!(neighbors(tree, 7, "n6"), set(&["n2"]));
+        assert_eq!(neighbors(Topology::Ring, 4, "n0"), set(&["n1", "n3"]));
+        assert_eq!(
+            neighbors(Topology::Grid, 9, "n4"),
+            set(&["n1", "n3", "n5", "n7"])
+        );
+        assert_eq!(neighbors(Topology::Grid, 9, "n2"), set(&["n1", "n5"]));
+    }
+
+    #[test]
+    fn static_topologies_are_undirected_and_random_regular_has_the_degree() {
+        let topologies = [
+            Topology::FullyConnected,
+            Topology::Tree { arity: 3 },
+            Topology::Ring,
+            Topology::Grid,
+            Topology::RandomRegular { degree: 3 },
+        ];
+        for topology in topologies {
+            for node_id in node_ids(8) {
+                for neighbor in neighbors(topology, 8, &node_id) {
+                    assert!(
+                        neighbors(topology, 8, &neighbor).contains(&node_id),
+                        "{:?}: {} -> {}",
+                        topology,
+                        node_id,
+                        neighbor
+                    );
+                }
+            }
+        }
+        for node_id in node_ids(8) {
+            let random = neighbors(Topology::RandomRegular { degree: 3 }, 8, &node_id);
+            assert_eq!(random.len(), 3);
+        }
+    }
+
+    #[test]
+    fn random_regular_rounds_an_odd_degree_down_for_an_odd_node_count() {
+        for (degree, n, expected) in [(3, 7, 2), (5, 7, 4), (4, 7, 4), (3, 8, 3), (9, 5, 4)] {
+            let topology = Topology::RandomRegular { degree };
+            for node_id in node_ids(n) {
+                assert_eq!(
+                    neighbors(topology, n, &node_id).len(),
+                    expected,
+                    "degree {} over {} nodes",
+                    degree,
+                    n
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn spanning_tree_routes_around_nodes_down() {
+        let topology = Topology::SpanningTree;
+        assert_eq!(neighbors(topology, 4, "n0"), set(&["n1", "n2", "n3"]));
+        assert_eq!(neighbors(topology, 4, "n1"), set(&["n0"]));
+
+        let down = HashSet::from(["n0".to_string()]);
+        let given = HashMap::new();
+        let neighbors_of = |node_id| topology.neighbors(node_id, &node_ids(4), &given, &down);
+        assert_eq!(neighbors_of("n1"), set(&["n2", "n3"]));
+        assert_eq!(neighbors_of("n2"), set(&["n1"]));
+        assert!(neighbors_of("n0").is_empty());
+    }
+}
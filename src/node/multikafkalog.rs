@@ -1,188 +1,540 @@
-use crate::payloads::{KafkaLogOrKvPayload, KafkaLogPayload, KvErrorCode, KvPayload};
+use crate::config;
+use crate::node::AsyncNode;
+use crate::payloads::{
+    DEFAULT_CONSUMER_GROUP, ErrorCode, Event, InitPayload, KafkaKvValue, KafkaLogOrKvPayload,
+    KafkaLogPayload, KafkaLogTimer, KvErrorCode, KvPayload, consumer_group,
+};
+use crate::timer::Timers;
 use crate::{Body, Message};
 use dashmap::DashMap;
-use std::collections::HashMap;
-use std::ops::{Deref, DerefMut};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+use std::fmt::{Display, Formatter};
+use std::ops::{Deref, DerefMut, Range, RangeInclusive};
 use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::Arc;
-
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Key of the consumer groups that committed an offset, sorted.
+const CONSUMER_GROUPS_KEY: &str = "consumer-groups";
+
+/// Kafka-style log backed by Maelstrom's `lin-kv`.
+///
+/// The last offset of each log lives in `offset-{key}`, and its messages in segments
+/// `{key}-segment-{n}`, each holding the `[offset, msg]` pairs of `segment_size`
+/// consecutive offsets, so a poll reads one or two kv values.
+///
+/// Every client request has a deadline, after which it is answered with a timeout error
+/// and its state is dropped; kv requests left unanswered are sent again in the meantime.
+/// At most `max_in_flight` operations run on the log of a key, the others wait in a
+/// bounded queue.
+///
+/// Each log caches the newest `cache_size` messages it appended or read, and answers
+/// a poll from the cache when it holds every offset the log knows of in the polled range,
+/// reading from lin-kv only the segments of the offsets it misses.
+///
+/// Committed offsets are kept per consumer group, those of the default group in
+/// `committed-offset-{key}` and the others in `committed-offset-{key}@{group}`, with `\` and
+/// `@` escaped in `{key}` so that the keys of different groups never collide. The groups
+/// that committed are registered in `consumer-groups` so that any node can list them.
 #[derive(Debug)]
 pub struct MultiKafkaLogNode {
     id: String,
     max_poll: usize,
+    segment_size: usize,
+    cache_size: usize,
+    limits: KafkaLogLimits,
     msg_generator: Arc<MsgGenerator>,
-    input_channel_rx: tokio::sync::mpsc::UnboundedReceiver<Message<KafkaLogOrKvPayload>>,
     stdout_channel_tx: tokio::sync::mpsc::UnboundedSender<Message<KafkaLogOrKvPayload>>,
     log_by_key: HashMap<String, AsyncKafkaLog>,
-    completed_polls: HashMap<NodeMsgId, Progress<(String, Vec<(usize, usize)>)>>,
-    completed_commits: HashMap<NodeMsgId, Progress<String>>,
+    completed_polls: HashMap<NodeMsgId, PollProgress>,
+    completed_commits: HashMap<NodeMsgId, CommitProgress>,
     completed_offset_reads: HashMap<NodeMsgId, Progress<(String, usize)>>,
+    deadlines: HashMap<NodeMsgId, Duration>,
+    queued_by_key: HashMap<String, VecDeque<QueuedOperation>>,
+    /// Consumer groups last read from or written to `consumer-groups`.
+    known_groups: BTreeSet<String>,
+    group_requests: HashMap<usize, GroupsRequest>,
+    timers: Timers<KafkaLogTimer>,
+}
+
+/// Bounds on the work a [`MultiKafkaLogNode`] keeps in flight, read at startup.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KafkaLogLimits {
+    /// Time a client request may take before it is answered with a timeout error.
+    pub request_timeout: Duration,
+    /// Time after which an unanswered kv request is sent again.
+    pub kv_retry: Duration,
+    /// Operations running on the log of a key; further ones are queued.
+    pub max_in_flight: usize,
+    /// Operations queued on the log of a key; further ones are rejected.
+    pub max_queued: usize,
+}
+
+impl KafkaLogLimits {
+    /// Reads `kafka-request-timeout-ms` (2000 by default), `kafka-kv-retry-ms` (250 by default),
+    /// `kafka-max-in-flight` (16 by default) and `kafka-max-queued` (256 by default).
+    pub fn from_config() -> anyhow::Result<Self> {
+        let limits = Self {
+            request_timeout: config::millis_or(
+                "kafka-request-timeout-ms",
+                Duration::from_millis(2000),
+            )?,
+            kv_retry: config::millis_or("kafka-kv-retry-ms", Duration::from_millis(250))?,
+            max_in_flight: config::parse("kafka-max-in-flight")?.unwrap_or(16),
+            max_queued: config::parse("kafka-max-queued")?.unwrap_or(256),
+        };
+        anyhow::ensure!(
+            limits.max_in_flight > 0,
+            "kafka-max-in-flight must be greater than 0"
+        );
+        anyhow::ensure!(
+            !limits.kv_retry.is_zero(),
+            "kafka-kv-retry-ms must be greater than 0"
+        );
+        Ok(limits)
+    }
+}
+
+impl AsyncNode<KafkaLogOrKvPayload, KafkaLogTimer> for MultiKafkaLogNode {
+    fn init(
+        init_msg: Message<InitPayload>,
+        output: tokio::sync::mpsc::UnboundedSender<Message<KafkaLogOrKvPayload>>,
+        _tx_channel: tokio::sync::mpsc::UnboundedSender<Event<KafkaLogOrKvPayload, KafkaLogTimer>>,
+        timers: &Timers<KafkaLogTimer>,
+    ) -> anyhow::Result<Self>
+    where
+        Self: Sized,
+    {
+        let segment_size = config::parse("kafka-segment-size")?.unwrap_or(32);
+        anyhow::ensure!(
+            segment_size > 0,
+            "kafka-segment-size must be greater than 0"
+        );
+        let limits = KafkaLogLimits::from_config()?;
+        timers.every("expire", limits.kv_retry / 2, KafkaLogTimer::Expire)?;
+        Ok(Self::new(
+            init_msg.body.payload.node_id,
+            5,
+            segment_size,
+            config::parse("kafka-cache-size")?.unwrap_or(1024),
+            limits,
+            output,
+            timers.clone(),
+        ))
+    }
+
+    async fn step(
+        &mut self,
+        event: Event<KafkaLogOrKvPayload, KafkaLogTimer>,
+    ) -> anyhow::Result<()> {
+        let input_msg = match event {
+            Event::Message(input_msg) => input_msg,
+            Event::InjectedPayload(KafkaLogTimer::Expire) => {
+                self.expire();
+                return Ok(());
+            }
+        };
+        match input_msg.body.payload {
+            KafkaLogOrKvPayload::Node(kafka_log_payload) => {
+                self.handle_kafka_log_msg(input_msg.src, input_msg.body.msg_id, kafka_log_payload);
+            }
+            KafkaLogOrKvPayload::Error(error_payload) => {
+                eprintln!(
+                    "Unexpected error from {}: {:?}",
+                    input_msg.src, error_payload
+                );
+            }
+            KafkaLogOrKvPayload::Service(kv_payload) => {
+                let Some(in_reply_to) = input_msg.body.in_reply_to else {
+                    eprintln!("ignoring kv msg without in_reply_to: {:?}", kv_payload);
+                    return Ok(());
+                };
+                self.handle_kv_msg(in_reply_to, kv_payload);
+            }
+        }
+        Ok(())
+    }
 }
 
 impl MultiKafkaLogNode {
     pub fn new(
         node_id: String,
         max_poll: usize,
-        stdin_channel_rx: tokio::sync::mpsc::UnboundedReceiver<Message<KafkaLogOrKvPayload>>,
+        segment_size: usize,
+        cache_size: usize,
+        limits: KafkaLogLimits,
         stdout_channel_tx: tokio::sync::mpsc::UnboundedSender<Message<KafkaLogOrKvPayload>>,
+        timers: Timers<KafkaLogTimer>,
     ) -> Self {
         Self {
             id: node_id,
             max_poll,
+            segment_size,
+            cache_size,
+            limits,
             msg_generator: Arc::new(MsgGenerator::new()),
-            input_channel_rx: stdin_channel_rx,
             stdout_channel_tx,
             log_by_key: HashMap::new(),
             completed_polls: HashMap::new(),
             completed_commits: HashMap::new(),
             completed_offset_reads: HashMap::new(),
+            deadlines: HashMap::new(),
+            queued_by_key: HashMap::new(),
+            known_groups: BTreeSet::new(),
+            group_requests: HashMap::new(),
+            timers,
         }
     }
 
-    pub async fn run(&mut self) {
-        loop {
-            tokio::select! {
-                Some(input_msg) = self.input_channel_rx.recv() => {
-                    match input_msg.body.payload {
-                        KafkaLogOrKvPayload::KafkaLog(kafka_log_payload) => {
-                            match kafka_log_payload {
-                                KafkaLogPayload::Send { key, msg } => {
-                                    self.send(
-                                        input_msg.src,
-                                        input_msg.body.msg_id.expect("msg_id is required (send)"),
-                                        key,
-                                        msg
-                                    );
-                                },
-                                KafkaLogPayload::Poll { offsets } => {
-                                    self.poll(
-                                        input_msg.src,
-                                        input_msg.body.msg_id.expect("msg_id is required (poll)"),
-                                        offsets
-                                    );
-                                },
-                                KafkaLogPayload::CommitOffsets { offsets } => {
-                                    self.commit_offsets(
-                                        input_msg.src,
-                                        input_msg.body.msg_id.expect("msg_id is required (commit offsets)"),
-                                        offsets
-                                    )
-                                },
-                                KafkaLogPayload::ListCommittedOffsets { keys } => {
-                                    self.list_committed_offsets(
-                                        input_msg.src,
-                                        input_msg.body.msg_id.expect("msg_id is required (list committed offsets)"),
-                                        keys
-                                    );
-                                },
-                                _ => {},
-                            }
-                        },
-                        KafkaLogOrKvPayload::Kv(kv_payload) => {
-                            let Some(in_reply_to) = input_msg.body.in_reply_to else { return; };
-                            match kv_payload {
-                                KvPayload::CasOk => {
-                                    self.cas_ok(in_reply_to);
-                                },
-                                KvPayload::Error { code, text: _text } if code == KvErrorCode::CAS_ERROR => {
-                                    self.cas_error(in_reply_to);
-                                },
-                                KvPayload::Error { code, text: _text } if code == KvErrorCode::KEY_NOT_FOUND => {
-                                    self.key_not_found(in_reply_to);
-                                },
-                                KvPayload::ReadOk { value } => {
-                                    self.read_ok(in_reply_to, value);
-                                }
-                                _ => {
-                                    eprintln!("Unhandled KvPayload: {:?}", kv_payload);
-                                }
-                            }
-                        },
-                    }
-                },
+    /// Client requests waiting for a reply.
+    pub fn pending_requests(&self) -> usize {
+        self.deadlines.len()
+    }
+
+    fn handle_kafka_log_msg(
+        &mut self,
+        src: String,
+        msg_id: Option<usize>,
+        kafka_log_payload: KafkaLogPayload,
+    ) {
+        let is_request = matches!(
+            kafka_log_payload,
+            KafkaLogPayload::Send { .. }
+                | KafkaLogPayload::Poll { .. }
+                | KafkaLogPayload::CommitOffsets { .. }
+                | KafkaLogPayload::ListCommittedOffsets { .. }
+                | KafkaLogPayload::ResetOffsets { .. }
+                | KafkaLogPayload::ListGroups
+        );
+        let Some(msg_id) = msg_id else {
+            if is_request {
+                self.reply_error(src, None, ErrorCode::MalformedRequest, "msg_id is required");
+            }
+            return;
+        };
+
+        match kafka_log_payload {
+            KafkaLogPayload::Send { key, msg } => self.send(src, msg_id, key, msg),
+            KafkaLogPayload::Poll { offsets } => self.poll(src, msg_id, offsets),
+            KafkaLogPayload::CommitOffsets { offsets, group } => {
+                let group = consumer_group(group);
+                self.commit_offsets(src, msg_id, group, offsets, CommitMode::Advance)
             }
+            KafkaLogPayload::ListCommittedOffsets { keys, group } => {
+                self.list_committed_offsets(src, msg_id, consumer_group(group), keys)
+            }
+            KafkaLogPayload::ResetOffsets { offsets, group } => {
+                let group = consumer_group(group);
+                self.commit_offsets(src, msg_id, group, offsets, CommitMode::Reset)
+            }
+            KafkaLogPayload::ListGroups => self.list_groups(src, msg_id),
+            KafkaLogPayload::SendOk { .. }
+            | KafkaLogPayload::PollOk { .. }
+            | KafkaLogPayload::CommitOffsetsOk
+            | KafkaLogPayload::ListCommittedOffsetsOk { .. }
+            | KafkaLogPayload::ResetOffsetsOk
+            | KafkaLogPayload::ListGroupsOk { .. } => {
+                self.reply_error(
+                    src,
+                    Some(msg_id),
+                    ErrorCode::NotSupported,
+                    "kafka replies are not accepted as requests",
+                );
+            }
+        }
+    }
+
+    fn reply_error(&self, dest: String, in_reply_to: Option<usize>, code: ErrorCode, text: &str) {
+        let reply = Message::error_reply(self.id.clone(), dest, in_reply_to, code, text)
+            .map_payload(KafkaLogOrKvPayload::Error);
+        write(&self.stdout_channel_tx, reply);
+    }
+
+    fn handle_kv_msg(&mut self, in_reply_to: usize, kv_payload: KvPayload<KafkaKvValue>) {
+        let reply = match KvReply::try_from(kv_payload) {
+            Ok(reply) => reply,
+            Err(kv_payload) => {
+                eprintln!("Unhandled KvPayload: {:?}", kv_payload);
+                return;
+            }
+        };
+        let Some(log_key) = self.msg_generator.consume_log_key(in_reply_to) else {
+            self.handle_groups_reply(in_reply_to, reply);
+            return;
+        };
+        let Some(log) = self.log_by_key.get(&log_key) else {
+            eprintln!(
+                "ignoring kv reply to unknown msg_id {}: {:?}",
+                in_reply_to, reply
+            );
+            return;
+        };
+
+        match log.transition(in_reply_to, reply) {
+            Ok(status) => self.operation_status(&log_key, status),
+            Err(error) => eprintln!("ignoring kv reply for log {}: {}", log_key, error),
         }
+        self.start_queued(&log_key);
     }
 
     fn send(&mut self, src: String, msg_id: usize, key: String, msg: usize) {
-        let log = self.get_log_by_key_or_insert(key);
+        self.get_log_by_key_or_insert(key.clone());
         let send_id = NodeMsgId::new(src, msg_id);
-        log.send(send_id, msg);
+        self.track(send_id.clone());
+        self.start(key, send_id, LogOperation::Send { msg });
     }
 
     fn poll(&mut self, src: String, msg_id: usize, offsets: HashMap<String, usize>) {
         let filtered_offsets = self.filter_offsets(offsets);
 
         let poll_id = NodeMsgId::new(src, msg_id);
-        if filtered_offsets.len() == 0 {
+        if filtered_offsets.is_empty() {
             self.build_and_send_empty_poll_ok_msg(&poll_id);
             return;
         }
 
         self.completed_polls
             .insert(poll_id.clone(), Progress::new(filtered_offsets.len()));
+        self.track(poll_id.clone());
 
         for (key, offset) in filtered_offsets {
-            let log = self.log_by_key.get_mut(&key).unwrap();
-            log.poll(poll_id.clone(), offset);
+            if !self.start(key, poll_id.clone(), LogOperation::Poll { offset }) {
+                break;
+            }
         }
     }
 
-    fn commit_offsets(&mut self, src: String, msg_id: usize, offsets: HashMap<String, usize>) {
+    fn commit_offsets(
+        &mut self,
+        src: String,
+        msg_id: usize,
+        group: String,
+        offsets: HashMap<String, usize>,
+        mode: CommitMode,
+    ) {
         let filtered_offsets = self.filter_offsets(offsets);
 
         let commit_id = NodeMsgId::new(src, msg_id);
-        if filtered_offsets.len() == 0 {
-            self.build_and_send_commit_offset_ok_msg(&commit_id);
+        if filtered_offsets.is_empty() {
+            self.build_and_send_commit_offset_ok_msg(&commit_id, mode);
             return;
         }
 
-        self.completed_commits
-            .insert(commit_id.clone(), Progress::new(filtered_offsets.len()));
+        // A group not known to be registered yet is registered before the commit completes.
+        let register = !self.known_groups.contains(&group);
+        self.completed_commits.insert(
+            commit_id.clone(),
+            CommitProgress {
+                mode,
+                logs: Progress::new(filtered_offsets.len()),
+                registering: register,
+            },
+        );
+        self.track(commit_id.clone());
+        if register {
+            self.register_group(commit_id.clone(), group.clone());
+        }
 
         for (key, offset) in filtered_offsets {
-            let log = self.log_by_key.get_mut(&key).unwrap();
-            match log.commit_offset(commit_id.clone(), offset) {
-                OperationStatus::CommitOffsetCompleted { commit_id } => {
-                    self.commit_offset_completed(key, &commit_id);
-                }
-                OperationStatus::InProgress => {}
-                OperationStatus::PollCompleted { .. } => {
-                    panic!("received poll completed for commit_offsets");
-                }
-                OperationStatus::ListCommitOffsetCompleted { .. } => {
-                    panic!("received list commit offset completed for commit_offsets");
-                }
+            let commit = Commit {
+                group: group.clone(),
+                offset,
+                mode,
             };
+            if !self.start(key, commit_id.clone(), LogOperation::CommitOffset(commit)) {
+                break;
+            }
         }
     }
 
     fn commit_offset_completed(&mut self, log_key: String, commit_id: &NodeMsgId) {
-        let completed_commits = self.completed_commits.get_mut(&commit_id).unwrap();
-        completed_commits.push(log_key);
-        if completed_commits.is_completed() {
-            self.completed_commits.remove(&commit_id);
-            self.build_and_send_commit_offset_ok_msg(commit_id);
+        let Some(commit_progress) = self.completed_commits.get_mut(commit_id) else {
+            return;
+        };
+        commit_progress.logs.push(log_key);
+        self.complete_commit(commit_id);
+    }
+
+    fn group_registered(&mut self, commit_id: &NodeMsgId) {
+        let Some(commit_progress) = self.completed_commits.get_mut(commit_id) else {
+            return;
+        };
+        commit_progress.registering = false;
+        self.complete_commit(commit_id);
+    }
+
+    /// Answers `commit_id` once its group is registered and the offsets of every log are
+    /// committed.
+    fn complete_commit(&mut self, commit_id: &NodeMsgId) {
+        let Some(commit_progress) = self.completed_commits.get(commit_id) else {
+            return;
+        };
+        if commit_progress.is_completed() {
+            let mode = commit_progress.mode;
+            self.completed_commits.remove(commit_id);
+            self.deadlines.remove(commit_id);
+            self.build_and_send_commit_offset_ok_msg(commit_id, mode);
+        }
+    }
+
+    /// Adds `group` to the consumer groups registered in lin-kv, as a step of `commit_id`.
+    fn register_group(&mut self, commit_id: NodeMsgId, group: String) {
+        let mut groups = self.known_groups.clone();
+        groups.insert(group.clone());
+        self.group_request(
+            GroupsOperation::CasRegister { commit_id, group },
+            KvPayload::Cas {
+                key: CONSUMER_GROUPS_KEY.to_string(),
+                from: KafkaKvValue::Groups {
+                    groups: self.known_groups.iter().cloned().collect(),
+                },
+                to: KafkaKvValue::Groups {
+                    groups: groups.into_iter().collect(),
+                },
+                create_if_not_exists: true,
+            },
+        );
+    }
+
+    fn list_groups(&mut self, src: String, msg_id: usize) {
+        let list_groups_id = NodeMsgId::new(src, msg_id);
+        self.track(list_groups_id.clone());
+        self.group_request(
+            GroupsOperation::ReadGroups { list_groups_id },
+            KvPayload::Read {
+                key: CONSUMER_GROUPS_KEY.to_string(),
+            },
+        );
+    }
+
+    /// Sends `payload` about the registered consumer groups to lin-kv, remembering what to
+    /// do with its reply.
+    fn group_request(&mut self, operation: GroupsOperation, payload: KvPayload<KafkaKvValue>) {
+        let msg_id = self.msg_generator.generate_msg_id();
+        self.group_requests.insert(
+            msg_id,
+            GroupsRequest {
+                operation,
+                payload: payload.clone(),
+                sent_at: self.timers.now(),
+            },
+        );
+        write(
+            &self.stdout_channel_tx,
+            Message {
+                src: self.id.clone(),
+                dst: AsyncKafkaLog::LIN_KV.to_string(),
+                body: Body {
+                    msg_id: Some(msg_id),
+                    in_reply_to: None,
+                    payload: KafkaLogOrKvPayload::Service(payload),
+                },
+            },
+        );
+    }
+
+    fn handle_groups_reply(&mut self, msg_id: usize, reply: KvReply) {
+        let Some(request) = self.group_requests.remove(&msg_id) else {
+            eprintln!(
+                "ignoring kv reply to unknown msg_id {}: {:?}",
+                msg_id, reply
+            );
+            return;
+        };
+
+        match (request.operation, reply) {
+            (GroupsOperation::CasRegister { commit_id, group }, KvReply::CasOk) => {
+                self.known_groups.insert(group);
+                self.group_registered(&commit_id);
+            }
+            (GroupsOperation::CasRegister { commit_id, group }, KvReply::CasError) => {
+                self.group_request(
+                    GroupsOperation::ReadRegistered { commit_id, group },
+                    KvPayload::Read {
+                        key: CONSUMER_GROUPS_KEY.to_string(),
+                    },
+                );
+            }
+            (
+                GroupsOperation::ReadRegistered { commit_id, group },
+                KvReply::ReadOk(KafkaKvValue::Groups { groups }),
+            ) => {
+                self.known_groups = groups.into_iter().collect();
+                if self.known_groups.contains(&group) {
+                    self.group_registered(&commit_id);
+                } else {
+                    self.register_group(commit_id, group);
+                }
+            }
+            (GroupsOperation::ReadRegistered { commit_id, group }, KvReply::KeyNotFound) => {
+                self.known_groups.clear();
+                self.register_group(commit_id, group);
+            }
+            (
+                GroupsOperation::ReadGroups { list_groups_id },
+                KvReply::ReadOk(KafkaKvValue::Groups { groups }),
+            ) => {
+                self.known_groups = groups.iter().cloned().collect();
+                self.list_groups_completed(&list_groups_id, groups);
+            }
+            (GroupsOperation::ReadGroups { list_groups_id }, KvReply::KeyNotFound) => {
+                self.list_groups_completed(&list_groups_id, Vec::new());
+            }
+            (operation, reply) => {
+                eprintln!(
+                    "ignoring unexpected reply {:?} to msg_id {} in {:?}",
+                    reply, msg_id, operation
+                );
+                self.group_requests.insert(
+                    msg_id,
+                    GroupsRequest {
+                        operation,
+                        payload: request.payload,
+                        sent_at: request.sent_at,
+                    },
+                );
+            }
+        }
+    }
+
+    fn list_groups_completed(&mut self, list_groups_id: &NodeMsgId, groups: Vec<String>) {
+        if self.deadlines.remove(list_groups_id).is_some() {
+            self.build_and_send_list_groups_ok_msg(list_groups_id, groups);
         }
     }
 
-    fn list_committed_offsets(&mut self, src: String, msg_id: usize, log_keys: Vec<String>) {
+    fn list_committed_offsets(
+        &mut self,
+        src: String,
+        msg_id: usize,
+        group: String,
+        log_keys: Vec<String>,
+    ) {
         let filtered_log_keys = log_keys
             .into_iter()
             .filter(|key| self.log_by_key.contains_key(key))
             .collect::<Vec<_>>();
 
         let list_committed_offsets_id = NodeMsgId::new(src, msg_id);
-        if filtered_log_keys.len() == 0 {
+        if filtered_log_keys.is_empty() {
             self.build_and_send_empty_list_committed_offsets_ok_msg(&list_committed_offsets_id);
         } else {
             self.completed_offset_reads.insert(
                 list_committed_offsets_id.clone(),
                 Progress::new(filtered_log_keys.len()),
             );
+            self.track(list_committed_offsets_id.clone());
             for key in filtered_log_keys {
-                let log = self.log_by_key.get(&key).unwrap();
-                log.read_committed_offset(list_committed_offsets_id.clone());
+                let started = self.start(
+                    key,
+                    list_committed_offsets_id.clone(),
+                    LogOperation::ReadCommittedOffset {
+                        group: group.clone(),
+                    },
+                );
+                if !started {
+                    break;
+                }
             }
         }
     }
@@ -194,11 +546,145 @@ impl MultiKafkaLogNode {
             .collect::<HashMap<_, _>>()
     }
 
+    fn track(&mut self, request_id: NodeMsgId) {
+        self.deadlines
+            .insert(request_id, self.timers.now() + self.limits.request_timeout);
+    }
+
+    /// Runs `operation` on the log of `key`, or queues it while the log has
+    /// `max_in_flight` operations running. Returns `false` if the queue is full
+    /// and the request was rejected.
+    fn start(&mut self, key: String, request_id: NodeMsgId, operation: LogOperation) -> bool {
+        // Callers only start operations on logs they created or found in `log_by_key`, and
+        // logs are never removed.
+        let log = self
+            .log_by_key
+            .get(&key)
+            .expect("log of a started operation");
+        let queued = self.queued_by_key.entry(key.clone()).or_default();
+        if queued.is_empty() && log.in_flight() < self.limits.max_in_flight {
+            self.run(key, request_id, operation);
+            return true;
+        }
+
+        if queued.len() >= self.limits.max_queued {
+            self.fail(
+                &request_id,
+                ErrorCode::TemporarilyUnavailable,
+                "too many operations in flight",
+            );
+            return false;
+        }
+        queued.push_back(QueuedOperation {
+            request_id,
+            operation,
+        });
+        true
+    }
+
+    fn start_queued(&mut self, key: &str) {
+        loop {
+            let Some(log) = self.log_by_key.get(key) else {
+                return;
+            };
+            if log.in_flight() >= self.limits.max_in_flight {
+                return;
+            }
+            let Some(queued) = self
+                .queued_by_key
+                .get_mut(key)
+                .and_then(VecDeque::pop_front)
+            else {
+                return;
+            };
+            self.run(key.to_string(), queued.request_id, queued.operation);
+        }
+    }
+
+    fn run(&mut self, key: String, request_id: NodeMsgId, operation: LogOperation) {
+        // Only called from `start` and `start_queued`, which both found the log of `key`.
+        let log = self
+            .log_by_key
+            .get(&key)
+            .expect("log of a started operation");
+        let status = match operation {
+            LogOperation::Send { msg } => {
+                log.send(request_id, msg);
+                OperationStatus::InProgress
+            }
+            LogOperation::Poll { offset } => log.poll(request_id, offset),
+            LogOperation::CommitOffset(commit) => log.commit_offset(request_id, commit),
+            LogOperation::ReadCommittedOffset { group } => {
+                log.read_committed_offset(request_id, group);
+                OperationStatus::InProgress
+            }
+        };
+        self.operation_status(&key, status);
+    }
+
+    /// Fails every request past its deadline and sends again the kv requests left unanswered.
+    fn expire(&mut self) {
+        let now = self.timers.now();
+        let expired = self
+            .deadlines
+            .iter()
+            .filter(|(_, deadline)| **deadline <= now)
+            .map(|(request_id, _)| request_id.clone())
+            .collect::<Vec<_>>();
+        for request_id in expired {
+            self.fail(&request_id, ErrorCode::Timeout, "request timed out");
+        }
+
+        if let Some(sent_before) = now.checked_sub(self.limits.kv_retry) {
+            for log in self.log_by_key.values() {
+                log.retry_stalled(sent_before);
+            }
+            let stalled = self
+                .group_requests
+                .iter()
+                .filter(|(_, request)| request.sent_at <= sent_before)
+                .map(|(msg_id, _)| *msg_id)
+                .collect::<Vec<_>>();
+            for msg_id in stalled {
+                if let Some(request) = self.group_requests.remove(&msg_id) {
+                    self.group_request(request.operation, request.payload);
+                }
+            }
+        }
+    }
+
+    /// Drops the state of a request, answering it with an error.
+    fn fail(&mut self, request_id: &NodeMsgId, code: ErrorCode, text: &str) {
+        self.deadlines.remove(request_id);
+        self.completed_polls.remove(request_id);
+        self.completed_commits.remove(request_id);
+        self.completed_offset_reads.remove(request_id);
+        self.group_requests
+            .retain(|_, request| request.operation.request_id() != request_id);
+        for queued in self.queued_by_key.values_mut() {
+            queued.retain(|queued| queued.request_id != *request_id);
+        }
+        let freed_keys = self
+            .log_by_key
+            .iter()
+            .filter(|(_, log)| log.abandon(request_id))
+            .map(|(key, _)| key.clone())
+            .collect::<Vec<_>>();
+
+        self.reply_error(
+            request_id.node_id.clone(),
+            Some(request_id.msg_id),
+            code,
+            text,
+        );
+        for key in freed_keys {
+            self.start_queued(&key);
+        }
+    }
+
     fn build_and_send_empty_poll_ok_msg(&self, poll_id: &NodeMsgId) {
         let poll_ok = self.build_empty_poll_ok_msg(poll_id);
-        self.stdout_channel_tx
-            .send(poll_ok)
-            .expect("failed to write to seq-kv");
+        write(&self.stdout_channel_tx, poll_ok);
     }
 
     fn build_empty_poll_ok_msg(&self, poll_id: &NodeMsgId) -> Message<KafkaLogOrKvPayload> {
@@ -211,9 +697,7 @@ impl MultiKafkaLogNode {
         msgs: impl Into<HashMap<String, Vec<(usize, usize)>>>,
     ) {
         let poll_ok = self.build_poll_ok_msg(poll_id, msgs);
-        self.stdout_channel_tx
-            .send(poll_ok)
-            .expect("failed to write to seq-kv");
+        write(&self.stdout_channel_tx, poll_ok);
     }
 
     fn build_poll_ok_msg(
@@ -227,41 +711,60 @@ impl MultiKafkaLogNode {
             body: Body {
                 msg_id: Some(self.msg_generator.generate_msg_id()),
                 in_reply_to: Some(poll_id.msg_id),
-                payload: KafkaLogOrKvPayload::KafkaLog(KafkaLogPayload::PollOk {
+                payload: KafkaLogOrKvPayload::Node(KafkaLogPayload::PollOk {
                     msgs: msgs.into(),
+                    out_of_range: HashMap::new(),
+                    compacted: HashMap::new(),
                 }),
             },
         }
     }
 
-    fn build_and_send_commit_offset_ok_msg(&self, commit_id: &NodeMsgId) {
-        let commit_offset_ok = self.build_commit_offset_ok_msg(commit_id);
-        self.stdout_channel_tx
-            .send(commit_offset_ok)
-            .expect("failed to send commit_offsets_ok");
+    fn build_and_send_commit_offset_ok_msg(&self, commit_id: &NodeMsgId, mode: CommitMode) {
+        let commit_offset_ok = self.build_commit_offset_ok_msg(commit_id, mode);
+        write(&self.stdout_channel_tx, commit_offset_ok);
     }
 
-    fn build_commit_offset_ok_msg(&self, commit_id: &NodeMsgId) -> Message<KafkaLogOrKvPayload> {
+    fn build_commit_offset_ok_msg(
+        &self,
+        commit_id: &NodeMsgId,
+        mode: CommitMode,
+    ) -> Message<KafkaLogOrKvPayload> {
+        let payload = match mode {
+            CommitMode::Advance => KafkaLogPayload::CommitOffsetsOk,
+            CommitMode::Reset => KafkaLogPayload::ResetOffsetsOk,
+        };
         Message {
             src: self.id.clone(),
             dst: commit_id.node_id.clone(),
             body: Body {
                 msg_id: None,
                 in_reply_to: Some(commit_id.msg_id),
-                payload: KafkaLogOrKvPayload::KafkaLog(KafkaLogPayload::CommitOffsetsOk),
+                payload: KafkaLogOrKvPayload::Node(payload),
             },
         }
     }
 
+    fn build_and_send_list_groups_ok_msg(&self, list_groups_id: &NodeMsgId, groups: Vec<String>) {
+        let list_groups_ok = Message {
+            src: self.id.clone(),
+            dst: list_groups_id.node_id.clone(),
+            body: Body {
+                msg_id: None,
+                in_reply_to: Some(list_groups_id.msg_id),
+                payload: KafkaLogOrKvPayload::Node(KafkaLogPayload::ListGroupsOk { groups }),
+            },
+        };
+        write(&self.stdout_channel_tx, list_groups_ok);
+    }
+
     fn build_and_send_empty_list_committed_offsets_ok_msg(
         &self,
         list_committed_offsets_id: &NodeMsgId,
     ) {
         let list_committed_offsets_ok =
             self.build_empty_list_committed_offsets_ok_msg(list_committed_offsets_id);
-        self.stdout_channel_tx
-            .send(list_committed_offsets_ok)
-            .expect("failed to send list_committed_offsets_ok");
+        write(&self.stdout_channel_tx, list_committed_offsets_ok);
     }
 
     fn build_empty_list_committed_offsets_ok_msg(
@@ -278,9 +781,7 @@ impl MultiKafkaLogNode {
     ) {
         let list_committed_offsets_ok =
             self.build_list_committed_offsets_ok_msg(list_committed_offsets_id, offsets);
-        self.stdout_channel_tx
-            .send(list_committed_offsets_ok)
-            .expect("failed to send list_committed_offsets_ok");
+        write(&self.stdout_channel_tx, list_committed_offsets_ok);
     }
 
     fn build_list_committed_offsets_ok_msg(
@@ -294,7 +795,7 @@ impl MultiKafkaLogNode {
             body: Body {
                 msg_id: None,
                 in_reply_to: Some(list_committed_offsets_id.msg_id),
-                payload: KafkaLogOrKvPayload::KafkaLog(KafkaLogPayload::ListCommittedOffsetsOk {
+                payload: KafkaLogOrKvPayload::Node(KafkaLogPayload::ListCommittedOffsetsOk {
                     offsets,
                 }),
             },
@@ -303,9 +804,7 @@ impl MultiKafkaLogNode {
 
     fn build_and_send_send_ok_msg(&self, send_id: &NodeMsgId, offset: usize) {
         let send_ok = self.build_send_ok_msg(send_id, offset);
-        self.stdout_channel_tx
-            .send(send_ok)
-            .expect("failed to write to seq-kv");
+        write(&self.stdout_channel_tx, send_ok);
     }
 
     fn build_send_ok_msg(
@@ -319,7 +818,7 @@ impl MultiKafkaLogNode {
             body: Body {
                 msg_id: Some(self.msg_generator.generate_msg_id()),
                 in_reply_to: Some(send_id.msg_id),
-                payload: KafkaLogOrKvPayload::KafkaLog(KafkaLogPayload::SendOk { offset }),
+                payload: KafkaLogOrKvPayload::Node(KafkaLogPayload::SendOk { offset }),
             },
         }
     }
@@ -331,122 +830,47 @@ impl MultiKafkaLogNode {
                 key.to_string(),
                 self.id.clone(),
                 self.max_poll,
+                self.segment_size,
+                self.cache_size,
                 self.stdout_channel_tx.clone(),
                 Arc::clone(&self.msg_generator),
+                self.timers.clone(),
             ))
     }
 
-    fn cas_ok(&mut self, in_reply_to: usize) {
-        let node_id = self.id.clone();
-        let (log_key, log) = self
-            .consume_log_key(in_reply_to)
-            .expect("cas_ok: log not found");
-
-        let cas_id = NodeMsgId::new(node_id, in_reply_to);
-        match log.cas_ok(&cas_id) {
-            CasSemantics::SendCas { send_id, offset } => {
-                self.cas_ok_send(&send_id, offset);
-            }
-            CasSemantics::CommitCas { commit_id, offset } => {
-                self.cas_ok_commit(log_key, &commit_id, offset);
-            }
-        }
-    }
-
-    fn cas_ok_send(&self, send_id: &NodeMsgId, offset: usize) {
-        self.build_and_send_send_ok_msg(&send_id, offset);
-    }
-
-    fn cas_ok_commit(&mut self, log_key: String, commit_id: &NodeMsgId, _offset: usize) {
-        let commit_progress = self
-            .completed_commits
-            .get_mut(&commit_id)
-            .expect("commit progress not found");
-        commit_progress.push(log_key);
-
-        if commit_progress.is_completed() {
-            self.completed_commits.remove(&commit_id);
-            self.stdout_channel_tx
-                .send(Message {
-                    src: self.id.clone(),
-                    dst: commit_id.node_id.clone(),
-                    body: Body {
-                        msg_id: Some(self.msg_generator.generate_msg_id()),
-                        in_reply_to: Some(commit_id.msg_id),
-                        payload: KafkaLogOrKvPayload::KafkaLog(KafkaLogPayload::CommitOffsetsOk),
-                    },
-                })
-                .expect("failed to write to seq-kv");
+    fn cas_ok_send(&mut self, send_id: &NodeMsgId, offset: usize) {
+        if self.deadlines.remove(send_id).is_some() {
+            self.build_and_send_send_ok_msg(send_id, offset);
         }
     }
 
-    fn consume_log_key(&mut self, msg_id: usize) -> Option<(String, &AsyncKafkaLog)> {
-        self.msg_generator
-            .consume_log_key(msg_id)
-            .and_then(|log_key| self.log_by_key.get(&log_key).map(|log| (log_key, log)))
-    }
-
     fn poll_completed(
         &mut self,
         poll_id: &NodeMsgId,
     ) -> Option<HashMap<String, Vec<(usize, usize)>>> {
+        self.deadlines.remove(poll_id);
         self.completed_polls
-            .remove(&poll_id)
+            .remove(poll_id)
             .map(|poll_progress| poll_progress.into())
     }
 
-    fn cas_error(&mut self, msg_id: usize) {
-        let node_id = self.id.clone();
-        let (_, log) = self
-            .consume_log_key(msg_id)
-            .expect("cas_error: log not found");
-        log.cas_error(&NodeMsgId::new(node_id, msg_id));
-    }
-
-    fn key_not_found(&mut self, msg_id: usize) {
-        let (log_key, log) = self
-            .consume_log_key(msg_id)
-            .expect("key_not_found: log not found");
-
-        match log.missing_key_error(msg_id) {
-            OperationStatus::PollCompleted { poll_id, msgs } => {
-                self.poll_step_completed(log_key, msgs, &poll_id);
-            }
-            OperationStatus::CommitOffsetCompleted { .. } => {
-                panic!("received commit offset completed for poll read missing key");
-            }
-            OperationStatus::ListCommitOffsetCompleted {
-                list_committed_offset_id,
-                offset,
-            } => {
-                self.list_committed_offsets_step_completed(
-                    log_key,
-                    &list_committed_offset_id,
-                    offset,
-                );
+    fn operation_status(&mut self, log_key: &str, status: OperationStatus) {
+        match status {
+            OperationStatus::SendCompleted { send_id, offset } => {
+                self.cas_ok_send(&send_id, offset);
             }
-            OperationStatus::InProgress => {}
-        }
-    }
-
-    fn read_ok(&mut self, msg_id: usize, value: usize) {
-        let (log_key, log) = self
-            .consume_log_key(msg_id)
-            .expect("read_ok: log not found");
-
-        match log.read_ok(msg_id, value) {
             OperationStatus::PollCompleted { poll_id, msgs } => {
-                self.poll_step_completed(log_key, msgs, &poll_id);
+                self.poll_step_completed(log_key.to_string(), msgs, &poll_id);
             }
             OperationStatus::CommitOffsetCompleted { commit_id } => {
-                self.commit_offset_step_completed(log_key, &commit_id);
+                self.commit_offset_completed(log_key.to_string(), &commit_id);
             }
             OperationStatus::ListCommitOffsetCompleted {
                 list_committed_offset_id,
                 offset,
             } => {
                 self.list_committed_offsets_step_completed(
-                    log_key,
+                    log_key.to_string(),
                     &list_committed_offset_id,
                     offset,
                 );
@@ -461,28 +885,15 @@ impl MultiKafkaLogNode {
         msgs: Vec<(usize, usize)>,
         poll_id: &NodeMsgId,
     ) {
-        let poll_progress = self
-            .completed_polls
-            .get_mut(&poll_id)
-            .expect("poll progress not found");
+        let Some(poll_progress) = self.completed_polls.get_mut(poll_id) else {
+            return;
+        };
         poll_progress.push((log_key, msgs));
 
         if poll_progress.is_completed() {
-            let msgs = self.poll_completed(&poll_id).expect("poll_id not found");
-            self.build_and_send_poll_ok_msg(&poll_id, msgs);
-        }
-    }
-
-    fn commit_offset_step_completed(&mut self, log_key: String, commit_id: &NodeMsgId) {
-        let commit_progress = self
-            .completed_commits
-            .get_mut(&commit_id)
-            .expect("commit progress not found");
-
-        commit_progress.push(log_key);
-        if commit_progress.is_completed() {
-            self.completed_commits.remove(&commit_id);
-            self.build_and_send_commit_offset_ok_msg(&commit_id);
+            // Present: it was just looked up above.
+            let msgs = self.poll_completed(poll_id).expect("poll_id not found");
+            self.build_and_send_poll_ok_msg(poll_id, msgs);
         }
     }
 
@@ -492,17 +903,21 @@ impl MultiKafkaLogNode {
         list_committed_offset_id: &NodeMsgId,
         offset: usize,
     ) {
-        let list_committed_offset_progress = self
+        let Some(list_committed_offset_progress) = self
             .completed_offset_reads
-            .get_mut(&list_committed_offset_id)
-            .expect("list_committed_offset_progress not found");
+            .get_mut(list_committed_offset_id)
+        else {
+            return;
+        };
 
         list_committed_offset_progress.push((log_key, offset));
         if list_committed_offset_progress.is_completed() {
+            // Present: it was just looked up above.
             let list_committed_offset_progress = self
                 .completed_offset_reads
-                .remove(&list_committed_offset_id)
+                .remove(list_committed_offset_id)
                 .unwrap();
+            self.deadlines.remove(list_committed_offset_id);
             self.build_and_send_list_committed_offsets_ok_msg(
                 list_committed_offset_id,
                 list_committed_offset_progress.into(),
@@ -511,6 +926,23 @@ impl MultiKafkaLogNode {
     }
 }
 
+/// Hands `msg` to the stdout writer. Its channel only closes once the main loop stopped,
+/// with nobody left to answer, so a failed write is logged rather than taking the node down.
+fn write(
+    stdout_channel_tx: &tokio::sync::mpsc::UnboundedSender<Message<KafkaLogOrKvPayload>>,
+    msg: Message<KafkaLogOrKvPayload>,
+) {
+    if let Err(error) = stdout_channel_tx.send(msg) {
+        eprintln!("failed to write {:?}: stdout closed", error.0);
+    }
+}
+
+/// Allocates the msg_ids of the node and remembers which log each kv request belongs to.
+///
+/// The kv requests do not go through [`Rpc`](crate::rpc::Rpc): their retries and the
+/// deadlines of client requests follow the node's [`Timers`] clock, virtual under the
+/// simulator, while `Rpc` waits on tokio timers, and each reply drives the typed
+/// transition of its log instead of resuming an awaiting task.
 #[derive(Debug)]
 struct MsgGenerator {
     free_msg_id: AtomicUsize,
@@ -542,30 +974,40 @@ impl MsgGenerator {
     }
 }
 
-
 #[derive(Debug)]
 struct AsyncKafkaLog {
     key: String,
     node_id: String,
     max_poll: usize,
+    segment_size: usize,
     stdout_channel_tx: tokio::sync::mpsc::UnboundedSender<Message<KafkaLogOrKvPayload>>,
     msg_generator: Arc<MsgGenerator>,
-    semantics_by_msg_id: DashMap<NodeMsgId, LogMsgSemantics>,
-    local_committed_offset: AtomicUsize,
+    semantics_by_msg_id: DashMap<NodeMsgId, KvRequest>,
+    /// Last known committed offset of each consumer group.
+    local_committed_offsets: DashMap<String, usize>,
     local_offset: AtomicUsize,
-    completed_polls: DashMap<NodeMsgId, Vec<Option<(usize, usize)>>>,
+    /// Last known value of the newest segment, the `from` of the next append cas.
+    tail_segment: Mutex<Option<TailSegment>>,
+    /// Newest `cache_size` messages appended or read by this node, by offset.
+    cache: Mutex<BTreeMap<usize, usize>>,
+    cache_size: usize,
+    completed_polls: DashMap<NodeMsgId, SegmentPoll>,
+    timers: Timers<KafkaLogTimer>,
 }
 
 impl AsyncKafkaLog {
     const LIN_KV: &'static str = "lin-kv";
-    const SEQ_KV: &'static str = "seq-kv";
 
+    #[allow(clippy::too_many_arguments)]
     fn new(
         key: String,
         node_id: String,
         max_poll: usize,
+        segment_size: usize,
+        cache_size: usize,
         stdout_channel_tx: tokio::sync::mpsc::UnboundedSender<Message<KafkaLogOrKvPayload>>,
         msg_generator: Arc<MsgGenerator>,
+        timers: Timers<KafkaLogTimer>,
     ) -> Self {
         if max_poll == 0 {
             panic!("max_poll must be greater than 0");
@@ -574,483 +1016,590 @@ impl AsyncKafkaLog {
         Self {
             key,
             max_poll,
+            segment_size,
             node_id,
             stdout_channel_tx,
             msg_generator,
             semantics_by_msg_id: DashMap::new(),
-            local_committed_offset: AtomicUsize::new(0),
+            local_committed_offsets: DashMap::new(),
             local_offset: AtomicUsize::new(0),
+            tail_segment: Mutex::new(None),
+            cache: Mutex::new(BTreeMap::new()),
+            cache_size,
             completed_polls: DashMap::new(),
+            timers,
         }
     }
 
-    fn send(&self, send_id: NodeMsgId, msg: usize) {
-        let cas_msg_id = self.msg_generator.generate_log_msg_id(self.key.clone());
-        let local_offset = self.local_offset.load(Ordering::Relaxed);
+    /// Sends `payload` to lin-kv, remembering what to do with its reply.
+    fn request(&self, semantics: LogMsgSemantics, payload: KvPayload<KafkaKvValue>) {
+        let msg_id = self.msg_generator.generate_log_msg_id(self.key.clone());
         self.semantics_by_msg_id.insert(
-            NodeMsgId::new(self.node_id.clone(), cas_msg_id),
-            LogMsgSemantics::CasSend {
-                send_id,
-                msg,
-                offset: local_offset + 1,
+            NodeMsgId::new(self.node_id.clone(), msg_id),
+            KvRequest {
+                semantics,
+                payload: payload.clone(),
+                sent_at: self.timers.now(),
             },
         );
-        if let Err(_) = self.stdout_channel_tx.send(Message {
-            src: self.node_id.clone(),
-            dst: Self::LIN_KV.to_string(),
-            body: Body {
-                msg_id: Some(cas_msg_id),
-                in_reply_to: None,
-                payload: KafkaLogOrKvPayload::Kv(KvPayload::Cas {
-                    key: self.log_offset_key(),
-                    from: local_offset,
-                    to: local_offset + 1,
-                    create_if_not_exists: true,
-                }),
+        write(
+            &self.stdout_channel_tx,
+            Message {
+                src: self.node_id.clone(),
+                dst: Self::LIN_KV.to_string(),
+                body: Body {
+                    msg_id: Some(msg_id),
+                    in_reply_to: None,
+                    payload: KafkaLogOrKvPayload::Service(payload),
+                },
             },
-        }) {}
+        );
     }
 
-    fn log_offset_key(&self) -> String {
-        format!("offset-{}", self.key)
+    /// Client requests with kv requests waiting for a reply.
+    fn in_flight(&self) -> usize {
+        self.semantics_by_msg_id
+            .iter()
+            .map(|entry| entry.value().semantics.request_id().clone())
+            .collect::<HashSet<_>>()
+            .len()
     }
 
-    fn cas_ok(&self, cas_id: &NodeMsgId) -> CasSemantics {
-        match self
+    /// Sends again, under a new msg_id, the kv requests sent before `sent_before`:
+    /// a late reply to the previous attempt is ignored.
+    fn retry_stalled(&self, sent_before: Duration) {
+        let stalled = self
             .semantics_by_msg_id
-            .remove(cas_id)
-            .expect("cas_ok called for unknown msg_id")
-            .1
-        {
-            LogMsgSemantics::CasSend {
-                send_id,
-                msg,
-                offset,
-            } => {
-                let (send_id, offset) = self.cas_ok_send(send_id, msg, offset);
-                CasSemantics::SendCas { send_id, offset }
-            }
-            LogMsgSemantics::CasCommitOffset { commit_id, offset } => {
-                let (commit_id, offset) = self.cas_ok_commit_offset(commit_id, offset);
-                CasSemantics::CommitCas { commit_id, offset }
-            }
-            LogMsgSemantics::ReadUpdatedOffset { .. } => {
-                panic!("cas_ok called for ReadUpdateOffset")
-            }
-            LogMsgSemantics::ReadPollMessage { .. } => {
-                panic!("cas_ok called for ReadPollMessage")
-            }
-            LogMsgSemantics::ReadUpdatedCommittedOffset { .. } => {
-                panic!("cas_ok called for ReadUpdatedCommittedOffset")
+            .iter()
+            .filter(|entry| entry.value().sent_at <= sent_before)
+            .map(|entry| entry.key().clone())
+            .collect::<Vec<_>>();
+        for msg_id in stalled {
+            self.msg_generator.consume_log_key(msg_id.msg_id);
+            if let Some((_, request)) = self.semantics_by_msg_id.remove(&msg_id) {
+                self.request(request.semantics, request.payload);
             }
         }
     }
 
-    fn cas_ok_send(&self, send_id: NodeMsgId, msg: usize, offset: usize) -> (NodeMsgId, usize) {
-        self.local_offset.store(offset, Ordering::Relaxed);
-        let write_msg_id = self.msg_generator.generate_log_msg_id(self.key.clone());
-        self.stdout_channel_tx
-            .send(Message {
-                src: self.node_id.clone(),
-                dst: Self::SEQ_KV.to_string(),
-                body: Body {
-                    msg_id: Some(write_msg_id),
-                    in_reply_to: None,
-                    payload: KafkaLogOrKvPayload::Kv(KvPayload::Write {
-                        key: self.msg_offset_key(offset),
-                        value: msg,
-                    }),
-                },
-            })
-            .expect("failed to write to seq-kv");
-        (send_id, offset)
+    /// Forgets the kv requests of a client request. Returns whether any was in flight.
+    fn abandon(&self, request_id: &NodeMsgId) -> bool {
+        self.completed_polls.remove(request_id);
+        let abandoned = self
+            .semantics_by_msg_id
+            .iter()
+            .filter(|entry| entry.value().semantics.request_id() == request_id)
+            .map(|entry| entry.key().clone())
+            .collect::<Vec<_>>();
+        for msg_id in &abandoned {
+            self.msg_generator.consume_log_key(msg_id.msg_id);
+            self.semantics_by_msg_id.remove(msg_id);
+        }
+        !abandoned.is_empty()
     }
 
-    fn cas_ok_commit_offset(&self, send_id: NodeMsgId, offset: usize) -> (NodeMsgId, usize) {
-        self.local_committed_offset.store(offset, Ordering::Relaxed);
-        (send_id, offset)
+    fn send(&self, send_id: NodeMsgId, msg: usize) {
+        let local_offset = self.local_offset.load(Ordering::Relaxed);
+        self.request(
+            LogMsgSemantics::CasSend {
+                send_id,
+                msg,
+                offset: local_offset + 1,
+            },
+            KvPayload::Cas {
+                key: self.log_offset_key(),
+                from: KafkaKvValue::Offset(local_offset),
+                to: KafkaKvValue::Offset(local_offset + 1),
+                create_if_not_exists: true,
+            },
+        );
     }
 
-    fn msg_offset_key(&self, offset: usize) -> String {
-        format!("{}-{}", self.key, offset)
+    fn log_offset_key(&self) -> String {
+        format!("offset-{}", self.key)
     }
 
-    fn cas_error(&self, cas_id: &NodeMsgId) {
-        match self
-            .semantics_by_msg_id
-            .remove(cas_id)
-            .expect(&format!("cas_error called for unknown msg_id {:?}", cas_id))
-            .1
-        {
-            LogMsgSemantics::CasSend {
-                send_id,
-                msg,
-                offset,
-            } => {
+    /// Applies to the log the reply to its kv request `msg_id`, moving the client request
+    /// it is part of to its next step.
+    ///
+    /// A reply no request is waiting for, such as a duplicate or a late reply to a retried
+    /// or abandoned request, is rejected. So is a reply that does not fit its request,
+    /// which then stays pending and is retried.
+    fn transition(
+        &self,
+        msg_id: usize,
+        reply: KvReply,
+    ) -> Result<OperationStatus, TransitionError> {
+        let request_msg_id = NodeMsgId::new(self.node_id.clone(), msg_id);
+        let Some((_, request)) = self.semantics_by_msg_id.remove(&request_msg_id) else {
+            return Err(TransitionError::UnknownMsgId(msg_id));
+        };
+
+        let status = match (request.semantics, reply) {
+            (
+                LogMsgSemantics::CasSend {
+                    send_id,
+                    msg,
+                    offset,
+                },
+                KvReply::CasOk,
+            ) => {
+                self.cas_ok_send(send_id, msg, offset);
+                OperationStatus::InProgress
+            }
+            (
+                LogMsgSemantics::CasSend {
+                    send_id,
+                    msg,
+                    offset,
+                },
+                KvReply::CasError,
+            ) => {
                 self.cas_error_send(send_id, msg, offset);
+                OperationStatus::InProgress
             }
-            LogMsgSemantics::CasCommitOffset { commit_id, offset } => {
-                self.cas_error_commit_offset(commit_id, offset);
+            (
+                LogMsgSemantics::ReadUpdatedOffset {
+                    send_id,
+                    msg,
+                    attempted,
+                },
+                KvReply::ReadOk(KafkaKvValue::Offset(new_offset)),
+            ) => {
+                self.local_offset.store(new_offset, Ordering::Relaxed);
+                if new_offset == attempted {
+                    // The cas of a previous attempt may have gone through with its reply
+                    // lost. The offset is taken over rather than left as a gap, the append
+                    // finding out whether another send holds it.
+                    self.append(send_id, msg, attempted);
+                } else {
+                    self.send(send_id, msg);
+                }
+                OperationStatus::InProgress
             }
-            LogMsgSemantics::ReadUpdatedOffset { .. } => {
-                panic!("cas_error called for ReadUpdateOffset");
+            (
+                LogMsgSemantics::CasAppend {
+                    send_id,
+                    offset,
+                    segment,
+                    messages,
+                    ..
+                },
+                KvReply::CasOk,
+            ) => {
+                self.learn(&messages);
+                self.update_tail_segment(segment, messages);
+                OperationStatus::SendCompleted { send_id, offset }
             }
-            LogMsgSemantics::ReadPollMessage { .. } => {
-                panic!("cas_error called for ReadPollMessage");
+            (
+                LogMsgSemantics::CasAppend {
+                    send_id,
+                    msg,
+                    offset,
+                    segment,
+                    ..
+                },
+                KvReply::CasError,
+            ) => {
+                self.read_append_segment(send_id, msg, offset, segment);
+                OperationStatus::InProgress
             }
-            LogMsgSemantics::ReadUpdatedCommittedOffset { .. } => {
-                panic!("cas_error called for ReadUpdatedCommittedOffset");
+            (
+                LogMsgSemantics::ReadAppendSegment {
+                    send_id,
+                    msg,
+                    offset,
+                    segment,
+                },
+                KvReply::ReadOk(KafkaKvValue::Segment(messages)),
+            ) => self.append_segment_read(send_id, msg, offset, segment, messages),
+            (
+                LogMsgSemantics::ReadAppendSegment {
+                    send_id,
+                    msg,
+                    offset,
+                    segment,
+                },
+                KvReply::KeyNotFound,
+            ) => self.append_segment_read(send_id, msg, offset, segment, Vec::new()),
+            (
+                LogMsgSemantics::ReadPollSegment { poll_id, offset },
+                KvReply::ReadOk(KafkaKvValue::Segment(messages)),
+            ) => self
+                .poll_segment_read(poll_id, offset, messages)
+                .ok_or(TransitionError::UnknownMsgId(msg_id))?,
+            (LogMsgSemantics::ReadPollSegment { poll_id, offset }, KvReply::KeyNotFound) => self
+                .poll_segment_read(poll_id, offset, Vec::new())
+                .ok_or(TransitionError::UnknownMsgId(msg_id))?,
+            (
+                LogMsgSemantics::CasCommitOffset {
+                    commit_id,
+                    commit,
+                    to,
+                },
+                KvReply::CasOk,
+            ) => {
+                self.local_committed_offsets.insert(commit.group, to);
+                OperationStatus::CommitOffsetCompleted { commit_id }
             }
-        }
+            (
+                LogMsgSemantics::CasCommitOffset {
+                    commit_id, commit, ..
+                },
+                KvReply::CasError,
+            ) => {
+                self.cas_error_commit_offset(commit_id, commit);
+                OperationStatus::InProgress
+            }
+            (
+                LogMsgSemantics::ReadUpdatedCommittedOffset { commit_id, commit },
+                KvReply::ReadOk(KafkaKvValue::Offset(new_offset)),
+            ) => self.committed_offset_read(commit_id, commit, new_offset),
+            (
+                LogMsgSemantics::ReadUpdatedCommittedOffset { commit_id, commit },
+                KvReply::KeyNotFound,
+            ) => self.committed_offset_read(commit_id, commit, 0),
+            (
+                LogMsgSemantics::ReadCommittedOffset {
+                    list_committed_offset_id,
+                    group,
+                },
+                KvReply::ReadOk(KafkaKvValue::Offset(new_offset)),
+            ) => {
+                self.local_committed_offsets.insert(group, new_offset);
+                OperationStatus::ListCommitOffsetCompleted {
+                    list_committed_offset_id,
+                    offset: new_offset,
+                }
+            }
+            (
+                LogMsgSemantics::ReadCommittedOffset {
+                    list_committed_offset_id,
+                    ..
+                },
+                KvReply::KeyNotFound,
+            ) => OperationStatus::ListCommitOffsetCompleted {
+                list_committed_offset_id,
+                offset: 0,
+            },
+            (semantics, reply) => {
+                let error = TransitionError::UnexpectedReply {
+                    msg_id,
+                    state: format!("{:?}", semantics),
+                    reply: format!("{:?}", reply),
+                };
+                self.semantics_by_msg_id.insert(
+                    request_msg_id,
+                    KvRequest {
+                        semantics,
+                        payload: request.payload,
+                        sent_at: request.sent_at,
+                    },
+                );
+                return Err(error);
+            }
+        };
+        Ok(status)
     }
 
-    fn cas_error_send(&self, send_id: NodeMsgId, msg: usize, _offset: usize) {
-        let read_msg_id = self.msg_generator.generate_log_msg_id(self.key.clone());
-        self.stdout_channel_tx
-            .send(Message {
-                src: self.node_id.clone(),
-                dst: Self::LIN_KV.to_string(),
-                body: Body {
-                    msg_id: Some(read_msg_id),
-                    in_reply_to: None,
-                    payload: KafkaLogOrKvPayload::Kv(KvPayload::Read {
-                        key: self.log_offset_key(),
-                    }),
-                },
-            })
-            .expect("failed to read from lin-kv");
+    fn cas_ok_send(&self, send_id: NodeMsgId, msg: usize, offset: usize) {
+        self.local_offset.fetch_max(offset, Ordering::Relaxed);
+        self.append(send_id, msg, offset);
+    }
 
-        self.semantics_by_msg_id.insert(
-            NodeMsgId::new(self.node_id.clone(), read_msg_id),
-            LogMsgSemantics::ReadUpdatedOffset { send_id, msg },
-        );
+    /// Adds `msg` to the segment of `offset`, expecting the segment to hold its last known
+    /// value: on a cas error the segment is read again and the append retried.
+    fn append(&self, send_id: NodeMsgId, msg: usize, offset: usize) {
+        let segment = self.segment_of(offset);
+        let messages = match &*self.tail_segment.lock().unwrap() {
+            Some(tail) if tail.segment == segment => tail.messages.clone(),
+            _ => Vec::new(),
+        };
+        self.append_to(send_id, msg, offset, segment, messages);
     }
 
-    fn cas_error_commit_offset(&self, commit_id: NodeMsgId, offset: usize) {
-        let read_msg_id = self.msg_generator.generate_log_msg_id(self.key.clone());
-        self.stdout_channel_tx
-            .send(Message {
-                src: self.node_id.clone(),
-                dst: Self::LIN_KV.to_string(),
-                body: Body {
-                    msg_id: Some(read_msg_id),
-                    in_reply_to: None,
-                    payload: KafkaLogOrKvPayload::Kv(KvPayload::Read {
-                        key: self.committed_offset_key(),
-                    }),
-                },
-            })
-            .expect("failed to read from lin-kv");
+    fn append_to(
+        &self,
+        send_id: NodeMsgId,
+        msg: usize,
+        offset: usize,
+        segment: usize,
+        mut messages: Vec<(usize, usize)>,
+    ) {
+        let from = KafkaKvValue::Segment(messages.clone());
+        let position = messages.partition_point(|(msg_offset, _)| *msg_offset < offset);
+        messages.insert(position, (offset, msg));
 
-        self.semantics_by_msg_id.insert(
-            NodeMsgId::new(self.node_id.clone(), read_msg_id),
-            LogMsgSemantics::ReadUpdatedCommittedOffset {
-                commit_id,
-                offset: Some(offset),
+        self.request(
+            LogMsgSemantics::CasAppend {
+                send_id,
+                msg,
+                offset,
+                segment,
+                messages: messages.clone(),
+            },
+            KvPayload::Cas {
+                key: self.segment_key(segment),
+                from,
+                to: KafkaKvValue::Segment(messages),
+                create_if_not_exists: true,
             },
         );
     }
 
-    fn read_ok(&self, read_msg_id: usize, value: usize) -> OperationStatus {
-        let read_semantics = match self
-            .semantics_by_msg_id
-            .get(&NodeMsgId::new(self.node_id.clone(), read_msg_id))
-        {
-            None => panic!("read_ok called for unknown msg_id {}", read_msg_id),
-            Some(entry) => match entry.value() {
-                LogMsgSemantics::ReadUpdatedOffset { .. } => ReadOkSemantics::ReadUpdatedOffset,
-                LogMsgSemantics::ReadPollMessage { .. } => ReadOkSemantics::ReadPollMessage,
-                LogMsgSemantics::ReadUpdatedCommittedOffset { commit_id: _commit_id, offset }
-                    if offset.is_some() =>
-                {
-                    ReadOkSemantics::ReadUpdatedCommittedOffset
-                }
-                LogMsgSemantics::ReadUpdatedCommittedOffset { .. } => {
-                    ReadOkSemantics::ListCommittedOffset
-                }
-                LogMsgSemantics::CasSend { .. } => {
-                    panic!("read_ok called for cas send msg_id {}", read_msg_id);
-                }
-                LogMsgSemantics::CasCommitOffset { .. } => {
-                    panic!(
-                        "read_ok called for cas commit offset msg_id {}",
-                        read_msg_id
-                    );
-                }
-            },
-        };
-
-        match read_semantics {
-            ReadOkSemantics::ReadUpdatedOffset => self.update_offset_read_ok(read_msg_id, value),
-            ReadOkSemantics::ReadPollMessage => self.poll_read_ok(read_msg_id, value),
-            ReadOkSemantics::ReadUpdatedCommittedOffset => {
-                self.read_updated_committed_offset_read_ok(read_msg_id, value)
-            }
-            ReadOkSemantics::ListCommittedOffset => {
-                self.list_committed_offset_read_ok(read_msg_id, value)
-            }
+    fn update_tail_segment(&self, segment: usize, messages: Vec<(usize, usize)>) {
+        let mut tail_segment = self.tail_segment.lock().unwrap();
+        match &*tail_segment {
+            Some(tail) if tail.segment > segment => {}
+            _ => *tail_segment = Some(TailSegment { segment, messages }),
         }
     }
 
-    fn list_committed_offset_read_ok(
-        &self,
-        read_msg_id: usize,
-        new_offset: usize,
-    ) -> OperationStatus {
-        let LogMsgSemantics::ReadUpdatedCommittedOffset {
-            commit_id: list_committed_offset_id,
-            offset: None,
-        } = self
-            .semantics_by_msg_id
-            .remove(&NodeMsgId::new(self.node_id.clone(), read_msg_id))
-            .expect(&format!(
-                "list_committed_offset_read_ok called for unknown msg_id {}",
-                read_msg_id
-            ))
-            .1
-        else {
-            panic!(
-                "list_committed_offset_read_ok called for unknown msg_id {}",
-                read_msg_id
-            )
-        };
-        self.local_committed_offset
-            .store(new_offset, Ordering::Relaxed);
-        OperationStatus::ListCommitOffsetCompleted {
-            list_committed_offset_id,
-            offset: new_offset,
+    /// Caches `messages` read from or written to a segment, evicting the oldest offsets
+    /// beyond `cache_size`.
+    fn learn(&self, messages: &[(usize, usize)]) {
+        let mut cache = self.cache.lock().unwrap();
+        cache.extend(messages.iter().copied());
+        while cache.len() > self.cache_size {
+            cache.pop_first();
+        }
+        if let Some(last_offset) = messages.iter().map(|(msg_offset, _)| *msg_offset).max() {
+            self.local_offset.fetch_max(last_offset, Ordering::Relaxed);
         }
     }
 
-    fn read_updated_committed_offset_read_ok(
-        &self,
-        read_msg_id: usize,
-        new_offset: usize,
-    ) -> OperationStatus {
-        let LogMsgSemantics::ReadUpdatedCommittedOffset {
-            commit_id,
-            offset: Some(offset),
-        } = self
-            .semantics_by_msg_id
-            .remove(&NodeMsgId::new(self.node_id.clone(), read_msg_id))
-            .expect(&format!(
-                "update_committed_offset called for unknown msg_id {}",
-                read_msg_id
-            ))
-            .1
-        else {
-            panic!(
-                "update_committed_offset called for unknown msg_id {}",
-                read_msg_id
-            )
-        };
-        self.local_committed_offset
-            .store(new_offset, Ordering::Relaxed);
-        self.commit_offset(commit_id, offset)
+    fn segment_of(&self, offset: usize) -> usize {
+        offset / self.segment_size
     }
 
-    fn update_offset_read_ok(&self, read_msg_id: usize, new_offset: usize) -> OperationStatus {
-        let LogMsgSemantics::ReadUpdatedOffset { send_id, msg } = self
-            .semantics_by_msg_id
-            .remove(&NodeMsgId::new(self.node_id.clone(), read_msg_id))
-            .expect(&format!(
-                "update_offset called for unknown msg_id {}",
-                read_msg_id
-            ))
-            .1
-        else {
-            panic!("update_offset called for unknown msg_id {}", read_msg_id)
-        };
+    /// Segments holding the offsets from `from` up to the end of `polled`.
+    fn poll_segments(&self, from: usize, polled: &Range<usize>) -> RangeInclusive<usize> {
+        self.segment_of(from)..=self.segment_of(polled.end - 1)
+    }
 
-        self.local_offset.store(new_offset, Ordering::Relaxed);
-        self.send(send_id, msg);
-        OperationStatus::InProgress
+    fn segment_key(&self, segment: usize) -> String {
+        format!("{}-segment-{}", self.key, segment)
     }
 
-    fn poll(&self, poll_id: NodeMsgId, offset: usize) {
-        self.completed_polls
-            .insert(poll_id.clone(), Vec::with_capacity(self.max_poll));
-        for i in 0..self.max_poll {
-            let read_msg_id = self.msg_generator.generate_log_msg_id(self.key.clone());
-            let msg_offset = offset + i;
-            self.semantics_by_msg_id.insert(
-                NodeMsgId::new(self.node_id.clone(), read_msg_id),
-                LogMsgSemantics::ReadPollMessage {
-                    poll_id: poll_id.clone(),
-                    offset: msg_offset,
-                },
-            );
-            self.stdout_channel_tx
-                .send(Message {
-                    src: self.node_id.clone(),
-                    dst: Self::SEQ_KV.to_string(),
-                    body: Body {
-                        msg_id: Some(read_msg_id),
-                        in_reply_to: None,
-                        payload: KafkaLogOrKvPayload::Kv(KvPayload::Read {
-                            key: self.msg_offset_key(msg_offset),
-                        }),
-                    },
-                })
-                .expect("failed to read from lin-kv");
-        }
+    fn cas_error_send(&self, send_id: NodeMsgId, msg: usize, attempted: usize) {
+        self.request(
+            LogMsgSemantics::ReadUpdatedOffset {
+                send_id,
+                msg,
+                attempted,
+            },
+            KvPayload::Read {
+                key: self.log_offset_key(),
+            },
+        );
     }
 
-    fn missing_key_error(&self, read_msg_id: usize) -> OperationStatus {
-        match self
-            .semantics_by_msg_id
-            .remove(&NodeMsgId::new(self.node_id.clone(), read_msg_id))
-            .expect("missing_key_error called for unknown msg_id")
-            .1
-        {
-            LogMsgSemantics::ReadUpdatedOffset { .. } => {
-                panic!("missing_key_error called for ReadUpdateOffset");
-            }
-            LogMsgSemantics::ReadPollMessage {
-                poll_id,
-                offset: _offset,
-            } => self.poll_read_missing_key(poll_id),
-            LogMsgSemantics::ReadUpdatedCommittedOffset {
-                commit_id,
-                offset: _offset,
-            } => OperationStatus::ListCommitOffsetCompleted {
-                list_committed_offset_id: commit_id,
-                offset: 0,
+    fn read_append_segment(&self, send_id: NodeMsgId, msg: usize, offset: usize, segment: usize) {
+        self.request(
+            LogMsgSemantics::ReadAppendSegment {
+                send_id,
+                msg,
+                offset,
+                segment,
+            },
+            KvPayload::Read {
+                key: self.segment_key(segment),
             },
-            LogMsgSemantics::CasSend { .. } => {
-                panic!("missing_key_error called for CasSend");
+        );
+    }
+
+    fn cas_error_commit_offset(&self, commit_id: NodeMsgId, commit: Commit) {
+        let key = self.committed_offset_key(&commit.group);
+        self.request(
+            LogMsgSemantics::ReadUpdatedCommittedOffset { commit_id, commit },
+            KvPayload::Read { key },
+        );
+    }
+
+    fn append_segment_read(
+        &self,
+        send_id: NodeMsgId,
+        msg: usize,
+        offset: usize,
+        segment: usize,
+        messages: Vec<(usize, usize)>,
+    ) -> OperationStatus {
+        let held = messages
+            .binary_search_by_key(&offset, |(msg_offset, _)| *msg_offset)
+            .ok()
+            .map(|position| messages[position].1);
+        self.learn(&messages);
+        self.update_tail_segment(segment, messages.clone());
+        match held {
+            // The cas of a previous attempt went through but its reply was lost.
+            Some(held) if held == msg => OperationStatus::SendCompleted { send_id, offset },
+            // Another send holds an offset taken over after a lost cas reply.
+            Some(_) => {
+                self.send(send_id, msg);
+                OperationStatus::InProgress
             }
-            LogMsgSemantics::CasCommitOffset { .. } => {
-                panic!("missing_key_error called for CasCommitOffset");
+            None => {
+                self.append_to(send_id, msg, offset, segment, messages);
+                OperationStatus::InProgress
             }
         }
     }
 
-    fn poll_read_missing_key(&self, poll_id: NodeMsgId) -> OperationStatus {
-        let is_completed = {
-            let mut completed_polls = self
-                .completed_polls
-                .get_mut(&poll_id)
-                .expect("poll_id not found");
-
-            completed_polls.push(None);
-            completed_polls.len() == self.max_poll
-        };
-
-        if is_completed {
-            let completed_pool = self
-                .completed_polls
-                .remove(&poll_id)
-                .expect("poll_id not found");
-            OperationStatus::PollCompleted {
+    /// Answers a poll from `offset` from the cache if it holds every offset of the polled
+    /// range up to the last one the log knows of. Otherwise reads the segments from the first
+    /// offset missing in the cache, merging their messages with the cached ones.
+    fn poll(&self, poll_id: NodeMsgId, offset: usize) -> OperationStatus {
+        let polled = offset..offset.saturating_add(self.max_poll);
+        let msgs = self
+            .cache
+            .lock()
+            .unwrap()
+            .range(polled.clone())
+            .map(|(msg_offset, msg)| (*msg_offset, *msg))
+            .collect::<BTreeMap<_, _>>();
+
+        // Offsets start from 1, and the ones after the last known offset may not exist yet.
+        let last_offset = self.local_offset.load(Ordering::Relaxed);
+        let known = offset.max(1)..polled.end.min(last_offset.saturating_add(1));
+        let first_missing = known
+            .clone()
+            .find(|msg_offset| !msgs.contains_key(msg_offset));
+        if !known.is_empty() && first_missing.is_none() {
+            return OperationStatus::PollCompleted {
                 poll_id,
-                msgs: completed_pool.1.into_iter().flatten().collect(),
-            }
-        } else {
-            OperationStatus::InProgress
+                msgs: msgs.into_iter().collect(),
+            };
         }
-    }
 
-    fn poll_read_ok(&self, read_msg_id: usize, value: usize) -> OperationStatus {
-        let LogMsgSemantics::ReadPollMessage { poll_id, offset } = self
-            .semantics_by_msg_id
-            .remove(&NodeMsgId::new(self.node_id.clone(), read_msg_id))
-            .expect(&format!(
-                "poll_read_ok called for unknown msg_id {}",
-                read_msg_id
-            ))
-            .1
-        else {
-            panic!("poll_read_ok called for unknown msg_id {}", read_msg_id)
-        };
+        let segments = self.poll_segments(first_missing.unwrap_or(offset), &polled);
+        self.completed_polls.insert(
+            poll_id.clone(),
+            SegmentPoll {
+                pending_segments: segments.clone().count(),
+                msgs,
+            },
+        );
+        for segment in segments {
+            self.request(
+                LogMsgSemantics::ReadPollSegment {
+                    poll_id: poll_id.clone(),
+                    offset,
+                },
+                KvPayload::Read {
+                    key: self.segment_key(segment),
+                },
+            );
+        }
+        OperationStatus::InProgress
+    }
 
+    fn poll_segment_read(
+        &self,
+        poll_id: NodeMsgId,
+        offset: usize,
+        messages: Vec<(usize, usize)>,
+    ) -> Option<OperationStatus> {
+        self.learn(&messages);
+        let polled = offset..offset.saturating_add(self.max_poll);
         let is_completed = {
-            let mut completed_polls = self
-                .completed_polls
-                .get_mut(&poll_id)
-                .expect("poll_id not found");
+            let mut completed_polls = self.completed_polls.get_mut(&poll_id)?;
 
-            completed_polls.push(Some((offset, value)));
-            completed_polls.len() == self.max_poll
+            completed_polls.msgs.extend(
+                messages
+                    .into_iter()
+                    .filter(|(msg_offset, _)| polled.contains(msg_offset)),
+            );
+            completed_polls.pending_segments -= 1;
+            completed_polls.pending_segments == 0
         };
 
-        if is_completed {
-            let completed_pool = self
-                .completed_polls
-                .remove(&poll_id)
-                .expect("poll_id not found");
-            OperationStatus::PollCompleted {
-                poll_id,
-                msgs: completed_pool.1.into_iter().flatten().collect(),
-            }
-        } else {
-            OperationStatus::InProgress
+        if !is_completed {
+            return Some(OperationStatus::InProgress);
         }
+        let (_, completed_pool) = self.completed_polls.remove(&poll_id)?;
+        Some(OperationStatus::PollCompleted {
+            poll_id,
+            msgs: completed_pool.msgs.into_iter().collect(),
+        })
+    }
+
+    /// Moves the committed offset of a consumer group, expecting it to hold its last known
+    /// value: on a cas error the offset is read again and the commit retried. An advancing
+    /// commit behind the committed offset leaves it as it is.
+    fn commit_offset(&self, commit_id: NodeMsgId, commit: Commit) -> OperationStatus {
+        let local_committed_offset = self
+            .local_committed_offsets
+            .get(&commit.group)
+            .map_or(0, |offset| *offset);
+        self.commit_offset_from(commit_id, commit, local_committed_offset);
+        OperationStatus::InProgress
     }
 
-    fn commit_offset(&self, commit_id: NodeMsgId, offset: usize) -> OperationStatus {
-        if self.local_committed_offset.load(Ordering::Relaxed) >= offset {
-            return OperationStatus::CommitOffsetCompleted { commit_id };
-        }
-
-        let cas_msg_id = self.msg_generator.generate_log_msg_id(self.key.clone());
-        self.semantics_by_msg_id.insert(
-            NodeMsgId::new(self.node_id.clone(), cas_msg_id),
-            LogMsgSemantics::CasCommitOffset { commit_id, offset },
+    fn commit_offset_from(&self, commit_id: NodeMsgId, commit: Commit, from: usize) {
+        let to = match commit.mode {
+            CommitMode::Advance => from.max(commit.offset),
+            CommitMode::Reset => commit.offset,
+        };
+        let key = self.committed_offset_key(&commit.group);
+        self.request(
+            LogMsgSemantics::CasCommitOffset {
+                commit_id,
+                commit,
+                to,
+            },
+            KvPayload::Cas {
+                key,
+                from: KafkaKvValue::Offset(from),
+                to: KafkaKvValue::Offset(to),
+                create_if_not_exists: true,
+            },
         );
+    }
 
-        self.stdout_channel_tx
-            .send(Message {
-                src: self.node_id.clone(),
-                dst: Self::LIN_KV.to_string(),
-                body: Body {
-                    msg_id: Some(cas_msg_id),
-                    in_reply_to: None,
-                    payload: KafkaLogOrKvPayload::Kv(KvPayload::Cas {
-                        key: self.committed_offset_key(),
-                        from: self.local_committed_offset.load(Ordering::Relaxed),
-                        to: offset,
-                        create_if_not_exists: true,
-                    }),
-                },
-            })
-            .expect("failed to write to lin-kv");
-
+    fn committed_offset_read(
+        &self,
+        commit_id: NodeMsgId,
+        commit: Commit,
+        committed_offset: usize,
+    ) -> OperationStatus {
+        self.local_committed_offsets
+            .insert(commit.group.clone(), committed_offset);
+        if commit.mode == CommitMode::Advance && committed_offset >= commit.offset {
+            return OperationStatus::CommitOffsetCompleted { commit_id };
+        }
+        self.commit_offset_from(commit_id, commit, committed_offset);
         OperationStatus::InProgress
     }
 
-    fn read_committed_offset(&self, list_commit_offset_id: NodeMsgId) {
-        let read_msg_id = self.msg_generator.generate_log_msg_id(self.key.clone());
-        self.semantics_by_msg_id.insert(
-            NodeMsgId::new(self.node_id.clone(), read_msg_id),
-            LogMsgSemantics::ReadUpdatedCommittedOffset {
-                commit_id: list_commit_offset_id,
-                offset: None,
+    fn read_committed_offset(&self, list_committed_offset_id: NodeMsgId, group: String) {
+        let key = self.committed_offset_key(&group);
+        self.request(
+            LogMsgSemantics::ReadCommittedOffset {
+                list_committed_offset_id,
+                group,
             },
+            KvPayload::Read { key },
         );
-
-        self.stdout_channel_tx
-            .send(Message {
-                src: self.node_id.clone(),
-                dst: Self::LIN_KV.to_string(),
-                body: Body {
-                    msg_id: Some(read_msg_id),
-                    in_reply_to: None,
-                    payload: KafkaLogOrKvPayload::Kv(KvPayload::Read {
-                        key: self.committed_offset_key(),
-                    }),
-                },
-            })
-            .expect("failed to read from lin-kv");
     }
 
-    fn committed_offset_key(&self) -> String {
-        format!("committed-offset-{}", self.key)
+    /// The default group keeps the key used before consumer groups were introduced. The log
+    /// key is escaped so that the `@` before a group is its only unescaped one.
+    fn committed_offset_key(&self, group: &str) -> String {
+        let key = self.key.replace('\\', "\\\\").replace('@', "\\@");
+        if group == DEFAULT_CONSUMER_GROUP {
+            format!("committed-offset-{}", key)
+        } else {
+            format!("committed-offset-{}@{}", key, group)
+        }
     }
 }
 
 #[derive(Debug, Clone)]
 enum OperationStatus {
+    SendCompleted {
+        send_id: NodeMsgId,
+        offset: usize,
+    },
     PollCompleted {
         poll_id: NodeMsgId,
         msgs: Vec<(usize, usize)>,
@@ -1065,6 +1614,14 @@ enum OperationStatus {
     InProgress,
 }
 
+/// A kv request waiting for its reply.
+#[derive(Debug)]
+struct KvRequest {
+    semantics: LogMsgSemantics,
+    payload: KvPayload<KafkaKvValue>,
+    sent_at: Duration,
+}
+
 #[derive(Debug)]
 enum LogMsgSemantics {
     CasSend {
@@ -1072,36 +1629,207 @@ enum LogMsgSemantics {
         msg: usize,
         offset: usize,
     },
+    CasAppend {
+        send_id: NodeMsgId,
+        msg: usize,
+        offset: usize,
+        segment: usize,
+        messages: Vec<(usize, usize)>,
+    },
     CasCommitOffset {
         commit_id: NodeMsgId,
-        offset: usize,
+        commit: Commit,
+        to: usize,
     },
     ReadUpdatedOffset {
         send_id: NodeMsgId,
         msg: usize,
+        /// Offset the failed cas tried to reserve.
+        attempted: usize,
+    },
+    ReadAppendSegment {
+        send_id: NodeMsgId,
+        msg: usize,
+        offset: usize,
+        segment: usize,
     },
-    ReadPollMessage {
+    ReadPollSegment {
         poll_id: NodeMsgId,
         offset: usize,
     },
     ReadUpdatedCommittedOffset {
         commit_id: NodeMsgId,
-        offset: Option<usize>,
+        commit: Commit,
     },
+    ReadCommittedOffset {
+        list_committed_offset_id: NodeMsgId,
+        group: String,
+    },
+}
+
+impl LogMsgSemantics {
+    /// The client request the kv request is part of.
+    fn request_id(&self) -> &NodeMsgId {
+        match self {
+            LogMsgSemantics::CasSend { send_id, .. }
+            | LogMsgSemantics::CasAppend { send_id, .. }
+            | LogMsgSemantics::ReadUpdatedOffset { send_id, .. }
+            | LogMsgSemantics::ReadAppendSegment { send_id, .. } => send_id,
+            LogMsgSemantics::ReadPollSegment { poll_id, .. } => poll_id,
+            LogMsgSemantics::CasCommitOffset { commit_id, .. }
+            | LogMsgSemantics::ReadUpdatedCommittedOffset { commit_id, .. } => commit_id,
+            LogMsgSemantics::ReadCommittedOffset {
+                list_committed_offset_id,
+                ..
+            } => list_committed_offset_id,
+        }
+    }
+}
+
+/// Replies of lin-kv the log acts on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum KvReply {
+    CasOk,
+    CasError,
+    KeyNotFound,
+    ReadOk(KafkaKvValue),
 }
 
-#[derive(Debug, Clone, Ord, PartialOrd, Eq, PartialEq, Hash)]
-enum ReadOkSemantics {
-    ReadUpdatedOffset,
-    ReadPollMessage,
-    ReadUpdatedCommittedOffset,
-    ListCommittedOffset,
+impl TryFrom<KvPayload<KafkaKvValue>> for KvReply {
+    type Error = KvPayload<KafkaKvValue>;
+
+    fn try_from(kv_payload: KvPayload<KafkaKvValue>) -> Result<Self, Self::Error> {
+        match kv_payload {
+            KvPayload::CasOk => Ok(KvReply::CasOk),
+            KvPayload::ReadOk { value } => Ok(KvReply::ReadOk(value)),
+            KvPayload::Error { code, .. } if code == KvErrorCode::CAS_ERROR => {
+                Ok(KvReply::CasError)
+            }
+            KvPayload::Error { code, .. } if code == KvErrorCode::KEY_NOT_FOUND => {
+                Ok(KvReply::KeyNotFound)
+            }
+            kv_payload => Err(kv_payload),
+        }
+    }
 }
 
-#[derive(Debug, Clone, Ord, PartialOrd, Eq, PartialEq, Hash)]
-enum CasSemantics {
-    SendCas { send_id: NodeMsgId, offset: usize },
-    CommitCas { commit_id: NodeMsgId, offset: usize },
+/// Why a kv reply was not applied to a log.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum TransitionError {
+    /// No request is waiting for the reply.
+    UnknownMsgId(usize),
+    /// The reply does not answer the kind of request it replies to.
+    UnexpectedReply {
+        msg_id: usize,
+        state: String,
+        reply: String,
+    },
+}
+
+impl Display for TransitionError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TransitionError::UnknownMsgId(msg_id) => {
+                write!(f, "no request is waiting for msg_id {}", msg_id)
+            }
+            TransitionError::UnexpectedReply {
+                msg_id,
+                state,
+                reply,
+            } => write!(
+                f,
+                "unexpected reply {} to msg_id {} in {}",
+                reply, msg_id, state
+            ),
+        }
+    }
+}
+
+impl std::error::Error for TransitionError {}
+
+/// The part of a client request that runs on the log of one key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum LogOperation {
+    Send { msg: usize },
+    Poll { offset: usize },
+    CommitOffset(Commit),
+    ReadCommittedOffset { group: String },
+}
+
+/// A kv request about the registered consumer groups waiting for its reply.
+#[derive(Debug)]
+struct GroupsRequest {
+    operation: GroupsOperation,
+    payload: KvPayload<KafkaKvValue>,
+    sent_at: Duration,
+}
+
+#[derive(Debug)]
+enum GroupsOperation {
+    CasRegister { commit_id: NodeMsgId, group: String },
+    ReadRegistered { commit_id: NodeMsgId, group: String },
+    ReadGroups { list_groups_id: NodeMsgId },
+}
+
+impl GroupsOperation {
+    /// The client request the kv request is part of.
+    fn request_id(&self) -> &NodeMsgId {
+        match self {
+            GroupsOperation::CasRegister { commit_id, .. }
+            | GroupsOperation::ReadRegistered { commit_id, .. } => commit_id,
+            GroupsOperation::ReadGroups { list_groups_id } => list_groups_id,
+        }
+    }
+}
+
+/// A move of the committed offset of a consumer group on one log.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Commit {
+    group: String,
+    offset: usize,
+    mode: CommitMode,
+}
+
+/// Progress of a commit or reset of offsets of a consumer group.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct CommitProgress {
+    mode: CommitMode,
+    /// Log keys whose offset is committed.
+    logs: Progress<String>,
+    /// Whether the group is still being registered in `consumer-groups`.
+    registering: bool,
+}
+
+impl CommitProgress {
+    fn is_completed(&self) -> bool {
+        !self.registering && self.logs.is_completed()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CommitMode {
+    /// Only moves the committed offset forward, as `commit_offsets`.
+    Advance,
+    /// Moves the committed offset anywhere, as `reset_offsets`.
+    Reset,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct QueuedOperation {
+    request_id: NodeMsgId,
+    operation: LogOperation,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct TailSegment {
+    segment: usize,
+    messages: Vec<(usize, usize)>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct SegmentPoll {
+    pending_segments: usize,
+    msgs: BTreeMap<usize, usize>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
@@ -1122,6 +1850,8 @@ struct Progress<T> {
     progress: Vec<T>,
 }
 
+type PollProgress = Progress<(String, Vec<(usize, usize)>)>;
+
 impl<T> Progress<T> {
     fn new(tot_log_keys: usize) -> Self {
         Self {
@@ -1205,3 +1935,292 @@ impl From<Progress<(String, usize)>> for HashMap<String, usize> {
         value.progress.into_iter().collect()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::sync::mpsc::UnboundedReceiver;
+
+    fn log(
+        cache_size: usize,
+    ) -> (
+        AsyncKafkaLog,
+        UnboundedReceiver<Message<KafkaLogOrKvPayload>>,
+    ) {
+        let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+        let log = AsyncKafkaLog::new(
+            "k".to_string(),
+            "n0".to_string(),
+            5,
+            4,
+            cache_size,
+            tx,
+            Arc::new(MsgGenerator::new()),
+            Timers::new(0),
+        );
+        (log, rx)
+    }
+
+    /// The kv request the log sent last, with its msg_id.
+    fn sent(
+        rx: &mut UnboundedReceiver<Message<KafkaLogOrKvPayload>>,
+    ) -> (usize, KvPayload<KafkaKvValue>) {
+        let msg = rx.try_recv().expect("a kv request was sent");
+        let KafkaLogOrKvPayload::Service(payload) = msg.body.payload else {
+            panic!("not a kv request: {:?}", msg);
+        };
+        (msg.body.msg_id.unwrap(), payload)
+    }
+
+    fn client(msg_id: usize) -> NodeMsgId {
+        NodeMsgId::new("c1".to_string(), msg_id)
+    }
+
+    #[test]
+    fn transition_rejects_stray_and_duplicate_replies() {
+        let (log, mut rx) = log(16);
+        log.send(client(1), 10);
+        let (cas_offset, _) = sent(&mut rx);
+
+        assert_eq!(
+            log.transition(99, KvReply::CasOk).unwrap_err(),
+            TransitionError::UnknownMsgId(99)
+        );
+        let unexpected = log.transition(cas_offset, KvReply::ReadOk(KafkaKvValue::Offset(3)));
+        assert!(matches!(
+            unexpected,
+            Err(TransitionError::UnexpectedReply { msg_id, .. }) if msg_id == cas_offset
+        ));
+        assert_eq!(log.in_flight(), 1);
+
+        assert!(matches!(
+            log.transition(cas_offset, KvReply::CasOk),
+            Ok(OperationStatus::InProgress)
+        ));
+        let (cas_append, payload) = sent(&mut rx);
+        assert!(matches!(payload, KvPayload::Cas { key, .. } if key == "k-segment-0"));
+        assert_eq!(
+            log.transition(cas_offset, KvReply::CasOk).unwrap_err(),
+            TransitionError::UnknownMsgId(cas_offset)
+        );
+
+        assert!(matches!(
+            log.transition(cas_append, KvReply::CasOk),
+            Ok(OperationStatus::SendCompleted { send_id, offset: 1 }) if send_id == client(1)
+        ));
+        assert_eq!(log.in_flight(), 0);
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn committed_offset_keys_of_groups_do_not_collide() {
+        let committed_offset_key = |key: &str, group: &str| {
+            let (tx, _rx) = tokio::sync::mpsc::unbounded_channel();
+            let log = AsyncKafkaLog::new(
+                key.to_string(),
+                "n0".to_string(),
+                5,
+                4,
+                16,
+                tx,
+                Arc::new(MsgGenerator::new()),
+                Timers::new(0),
+            );
+            log.committed_offset_key(group)
+        };
+
+        assert_eq!(
+            committed_offset_key("a", DEFAULT_CONSUMER_GROUP),
+            "committed-offset-a"
+        );
+        assert_eq!(committed_offset_key("a", "b"), "committed-offset-a@b");
+        assert_eq!(
+            committed_offset_key("a@b", DEFAULT_CONSUMER_GROUP),
+            "committed-offset-a\\@b"
+        );
+        assert_ne!(
+            committed_offset_key("a\\", "b"),
+            committed_offset_key("a\\@b", DEFAULT_CONSUMER_GROUP)
+        );
+        assert_ne!(
+            committed_offset_key("a@b", "c"),
+            committed_offset_key("a", "b@c")
+        );
+    }
+
+    #[test]
+    fn takes_over_the_offset_of_a_cas_whose_reply_was_lost() {
+        let (log, mut rx) = log(16);
+        log.send(client(1), 10);
+        // The cas reserving offset 1 goes through but its reply is lost.
+        let (lost, _) = sent(&mut rx);
+        log.retry_stalled(Duration::ZERO);
+        let (cas_offset, payload) = sent(&mut rx);
+        assert!(matches!(
+            payload,
+            KvPayload::Cas {
+                from: KafkaKvValue::Offset(0),
+                ..
+            }
+        ));
+        assert_eq!(
+            log.transition(lost, KvReply::CasOk).unwrap_err(),
+            TransitionError::UnknownMsgId(lost)
+        );
+
+        log.transition(cas_offset, KvReply::CasError).unwrap();
+        let (read_offset, _) = sent(&mut rx);
+        log.transition(read_offset, KvReply::ReadOk(KafkaKvValue::Offset(1)))
+            .unwrap();
+        let (cas_append, payload) = sent(&mut rx);
+        assert!(matches!(
+            payload,
+            KvPayload::Cas { key, to: KafkaKvValue::Segment(messages), .. }
+                if key == "k-segment-0" && messages == vec![(1, 10)]
+        ));
+        assert!(matches!(
+            log.transition(cas_append, KvReply::CasOk),
+            Ok(OperationStatus::SendCompleted { offset: 1, .. })
+        ));
+
+        // With no gap left at offset 1, polls are still served from the cache.
+        assert!(matches!(
+            log.poll(client(2), 0),
+            OperationStatus::PollCompleted { msgs, .. } if msgs == vec![(1, 10)]
+        ));
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn reserves_another_offset_if_the_one_taken_over_is_held() {
+        let (log, mut rx) = log(16);
+        log.send(client(1), 10);
+        let (cas_offset, _) = sent(&mut rx);
+        // Another node reserved offset 1 first.
+        log.transition(cas_offset, KvReply::CasError).unwrap();
+        let (read_offset, _) = sent(&mut rx);
+        log.transition(read_offset, KvReply::ReadOk(KafkaKvValue::Offset(1)))
+            .unwrap();
+        let (cas_append, _) = sent(&mut rx);
+        log.transition(cas_append, KvReply::CasError).unwrap();
+        let (read_segment, _) = sent(&mut rx);
+        log.transition(
+            read_segment,
+            KvReply::ReadOk(KafkaKvValue::Segment(vec![(1, 20)])),
+        )
+        .unwrap();
+
+        let (cas_offset, payload) = sent(&mut rx);
+        assert!(matches!(
+            payload,
+            KvPayload::Cas {
+                from: KafkaKvValue::Offset(1),
+                to: KafkaKvValue::Offset(2),
+                ..
+            }
+        ));
+        log.transition(cas_offset, KvReply::CasOk).unwrap();
+        let (cas_append, _) = sent(&mut rx);
+        assert!(matches!(
+            log.transition(cas_append, KvReply::CasOk),
+            Ok(OperationStatus::SendCompleted { offset: 2, .. })
+        ));
+    }
+
+    #[test]
+    fn answers_a_poll_after_a_send_from_the_cache() {
+        let (log, mut rx) = log(16);
+        log.send(client(1), 10);
+        let (cas_offset, _) = sent(&mut rx);
+        log.transition(cas_offset, KvReply::CasOk).unwrap();
+        let (cas_append, _) = sent(&mut rx);
+        log.transition(cas_append, KvReply::CasOk).unwrap();
+
+        assert!(matches!(
+            log.poll(client(2), 0),
+            OperationStatus::PollCompleted { msgs, .. } if msgs == vec![(1, 10)]
+        ));
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn evicts_the_oldest_offsets_and_reads_them_back_from_lin_kv() {
+        let (log, mut rx) = log(2);
+        log.learn(&[(1, 10), (2, 20), (3, 30)]);
+        assert_eq!(
+            log.cache
+                .lock()
+                .unwrap()
+                .keys()
+                .copied()
+                .collect::<Vec<_>>(),
+            vec![2, 3]
+        );
+        assert!(matches!(
+            log.poll(client(1), 2),
+            OperationStatus::PollCompleted { msgs, .. } if msgs == vec![(2, 20), (3, 30)]
+        ));
+        assert!(rx.try_recv().is_err());
+
+        assert!(matches!(
+            log.poll(client(2), 1),
+            OperationStatus::InProgress
+        ));
+        // The polled offsets 1 to 5 span the segments 0 and 1.
+        let (read_first, payload) = sent(&mut rx);
+        assert!(matches!(payload, KvPayload::Read { key } if key == "k-segment-0"));
+        let (read_second, _) = sent(&mut rx);
+        let segment = vec![(1, 10), (2, 20), (3, 30)];
+        assert!(matches!(
+            log.transition(
+                read_first,
+                KvReply::ReadOk(KafkaKvValue::Segment(segment.clone()))
+            ),
+            Ok(OperationStatus::InProgress)
+        ));
+        assert!(matches!(
+            log.transition(read_second, KvReply::KeyNotFound),
+            Ok(OperationStatus::PollCompleted { msgs, .. }) if msgs == segment
+        ));
+    }
+
+    #[test]
+    fn completes_a_commit_once_its_group_is_registered() {
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let limits = KafkaLogLimits {
+            request_timeout: Duration::from_secs(2),
+            kv_retry: Duration::from_millis(250),
+            max_in_flight: 16,
+            max_queued: 256,
+        };
+        let mut node =
+            MultiKafkaLogNode::new("n0".to_string(), 5, 4, 16, limits, tx, Timers::new(0));
+        node.get_log_by_key_or_insert("k".to_string());
+
+        // The group is named like the log key it commits on.
+        node.handle_kafka_log_msg(
+            "c1".to_string(),
+            Some(1),
+            KafkaLogPayload::CommitOffsets {
+                offsets: HashMap::from([("k".to_string(), 2)]),
+                group: Some("k".to_string()),
+            },
+        );
+        let (register, payload) = sent(&mut rx);
+        assert!(matches!(payload, KvPayload::Cas { key, .. } if key == CONSUMER_GROUPS_KEY));
+        let (commit, payload) = sent(&mut rx);
+        assert!(
+            matches!(payload, KvPayload::Cas { key, .. } if key.starts_with("committed-offset"))
+        );
+
+        node.handle_kv_msg(commit, KvPayload::CasOk);
+        assert!(rx.try_recv().is_err());
+        node.handle_kv_msg(register, KvPayload::CasOk);
+        let reply = rx.try_recv().expect("the commit is answered");
+        assert!(matches!(
+            reply.body.payload,
+            KafkaLogOrKvPayload::Node(KafkaLogPayload::CommitOffsetsOk)
+        ));
+        assert_eq!(node.pending_requests(), 0);
+    }
+}
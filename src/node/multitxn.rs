@@ -1,9 +1,9 @@
 use crate::node::{Node, common_init_node};
 use crate::payloads::{Event, InitPayload, TxnOperation, TxnPayload};
 use crate::stdout_json::StdoutJson;
+use crate::timer::Timers;
 use crate::{Body, Message};
 use std::collections::HashMap;
-use std::sync::mpsc::Sender;
 
 #[derive(Debug)]
 pub struct MultiTxnNode {
@@ -17,7 +17,7 @@ impl Node<TxnPayload, ()> for MultiTxnNode {
     fn init(
         init_msg: Message<InitPayload>,
         output: &mut StdoutJson,
-        _tx_channel: Sender<Event<TxnPayload, ()>>,
+        _timers: &Timers<()>,
     ) -> anyhow::Result<Self>
     where
         Self: Sized,
@@ -38,12 +38,16 @@ impl Node<TxnPayload, ()> for MultiTxnNode {
     ) -> anyhow::Result<()> {
         match event {
             Event::Message(msg) => {
+                // Writes replicated by other nodes are applied without being passed on.
+                let from_node = self.node_ids.contains(&msg.src);
                 let mut reply = msg.into_reply(Some(&mut self.msg_id));
                 let TxnPayload::Txn { txn } = reply.body.payload else {
                     return Ok(());
                 };
                 let txn_reply = self.process_txn(txn);
-                self.communicate_txn(&txn_reply, output)?;
+                if !from_node {
+                    self.communicate_txn(&txn_reply, output)?;
+                }
                 reply.body.payload = TxnPayload::TxnOk { txn: txn_reply };
                 output.write(&reply)?;
             }
@@ -79,7 +83,7 @@ impl MultiTxnNode {
 
     fn communicate_txn(
         &mut self,
-        txn: &Vec<TxnOperation>,
+        txn: &[TxnOperation],
         output: &mut StdoutJson,
     ) -> anyhow::Result<()> {
         for node_id in &self.node_ids {
@@ -95,9 +99,9 @@ impl MultiTxnNode {
                     in_reply_to: None,
                     payload: TxnPayload::Txn {
                         txn: txn
-                            .into_iter()
+                            .iter()
+                            .filter(|op| matches!(op, TxnOperation::Write { .. }))
                             .cloned()
-                            .filter(|op| !matches!(op, TxnOperation::Write { .. }))
                             .collect(),
                     },
                 },
@@ -0,0 +1,574 @@
+use crate::config;
+use crate::node::{Node, common_init_node};
+use crate::payloads::{
+    Either, ErrorCode, ErrorPayload, Event, InitPayload, KvPayload, RaftEntry, RaftOrKvPayload,
+    RaftPayload, RaftTimer,
+};
+use crate::rng::SimRng;
+use crate::rpc::{RpcCallbacks, RpcError, RpcOptions};
+use crate::stdout_json::StdoutJson;
+use crate::timer::Timers;
+use crate::{Body, Message};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::time::Duration;
+
+const TICK: Duration = Duration::from_millis(10);
+const HEARTBEAT_TICKS: usize = 3;
+const MIN_ELECTION_TICKS: usize = 15;
+const MAX_ELECTION_TICKS: usize = 30;
+const MAX_ENTRIES_PER_APPEND: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    Follower,
+    Candidate,
+    Leader,
+}
+
+/// Linearizable key-value store replicated with Raft.
+///
+/// Every client operation, reads included, goes through the log and is answered by the
+/// node that accepted it once applied. Followers forward client operations to the leader
+/// they know of. Time is measured in [`RaftTimer::Tick`]s, injected every 10ms.
+#[derive(Debug)]
+pub struct RaftNode {
+    id: String,
+    msg_id: usize,
+    peers: Vec<String>,
+    role: Role,
+    current_term: usize,
+    voted_for: Option<String>,
+    leader_id: Option<String>,
+    votes: HashSet<String>,
+    log: Vec<RaftEntry>,
+    commit_index: usize,
+    last_applied: usize,
+    next_index: HashMap<String, usize>,
+    match_index: HashMap<String, usize>,
+    state: HashMap<String, usize>,
+    ticks_since_heard: usize,
+    election_ticks: usize,
+    ticks_since_heartbeat: usize,
+    rng: SimRng,
+    rpc: RpcCallbacks<Self, RaftOrKvPayload>,
+    timers: Timers<RaftTimer>,
+}
+
+impl Node<RaftOrKvPayload, RaftTimer> for RaftNode {
+    fn init(
+        init_msg: Message<InitPayload>,
+        output: &mut StdoutJson,
+        timers: &Timers<RaftTimer>,
+    ) -> anyhow::Result<Self>
+    where
+        Self: Sized,
+    {
+        let tick_interval = config::millis_or("raft-tick-ms", TICK)?;
+        let (node_id, node_ids) = common_init_node(init_msg, output)?;
+        let mut hasher = DefaultHasher::new();
+        node_id.hash(&mut hasher);
+        let mut rng = SimRng::new(hasher.finish());
+        let raft_node = Self {
+            msg_id: 0,
+            peers: node_ids
+                .into_iter()
+                .filter(|peer| *peer != node_id)
+                .collect(),
+            role: Role::Follower,
+            current_term: 0,
+            voted_for: None,
+            leader_id: None,
+            votes: HashSet::new(),
+            log: Vec::new(),
+            commit_index: 0,
+            last_applied: 0,
+            next_index: HashMap::new(),
+            match_index: HashMap::new(),
+            state: HashMap::new(),
+            ticks_since_heard: 0,
+            election_ticks: Self::election_ticks(&mut rng),
+            ticks_since_heartbeat: 0,
+            rng,
+            timers: timers.clone(),
+            rpc: RpcCallbacks::new(
+                node_id.clone(),
+                RpcOptions::new(Duration::from_millis(1000)),
+            ),
+            id: node_id,
+        };
+        timers.every("tick", tick_interval, RaftTimer::Tick)?;
+        Ok(raft_node)
+    }
+
+    fn step(
+        &mut self,
+        event: Event<RaftOrKvPayload, RaftTimer>,
+        output: &mut StdoutJson,
+    ) -> anyhow::Result<()> {
+        match event {
+            Event::Message(message) => {
+                if let Some(callback) = self.rpc.take_callback(&message) {
+                    return callback(self, Ok(message), output);
+                }
+                match message.body.payload {
+                    Either::Left(_) => self.handle_client_request(message, output),
+                    Either::Right(raft_payload) => {
+                        self.handle_raft_msg(message.src, raft_payload, output)
+                    }
+                }
+            }
+            Event::InjectedPayload(RaftTimer::Tick) => self.tick(output),
+        }
+    }
+}
+
+impl RaftNode {
+    pub fn role(&self) -> Role {
+        self.role
+    }
+
+    pub fn current_term(&self) -> usize {
+        self.current_term
+    }
+
+    pub fn commit_index(&self) -> usize {
+        self.commit_index
+    }
+
+    fn election_ticks(rng: &mut SimRng) -> usize {
+        MIN_ELECTION_TICKS + rng.below((MAX_ELECTION_TICKS - MIN_ELECTION_TICKS) as u64) as usize
+    }
+
+    fn tick(&mut self, output: &mut StdoutJson) -> anyhow::Result<()> {
+        for callback in self
+            .rpc
+            .expire(&mut self.msg_id, self.timers.now(), output)?
+        {
+            callback(self, Err(RpcError::Timeout), output)?;
+        }
+
+        if self.role == Role::Leader {
+            self.ticks_since_heartbeat += 1;
+            if self.ticks_since_heartbeat >= HEARTBEAT_TICKS {
+                self.replicate(output)?;
+            }
+            return Ok(());
+        }
+
+        self.ticks_since_heard += 1;
+        if self.ticks_since_heard >= self.election_ticks {
+            self.start_election(output)?;
+        }
+        Ok(())
+    }
+
+    fn handle_client_request(
+        &mut self,
+        message: Message<RaftOrKvPayload>,
+        output: &mut StdoutJson,
+    ) -> anyhow::Result<()> {
+        let Either::Left(op) = &message.body.payload else {
+            return Ok(());
+        };
+        if !matches!(
+            op,
+            KvPayload::Read { .. } | KvPayload::Write { .. } | KvPayload::Cas { .. }
+        ) {
+            return Ok(());
+        }
+        let Some(client_msg_id) = message.body.msg_id else {
+            return self.reply_error(
+                message,
+                ErrorCode::MalformedRequest,
+                "msg_id is required",
+                output,
+            );
+        };
+
+        match (self.role, self.leader_id.clone()) {
+            (Role::Leader, _) => {
+                let Either::Left(op) = message.body.payload else {
+                    return Ok(());
+                };
+                self.log.push(RaftEntry {
+                    term: self.current_term,
+                    node: self.id.clone(),
+                    client: message.src,
+                    client_msg_id,
+                    op,
+                });
+                self.advance_commit_index(output)
+            }
+            (_, Some(leader_id)) => {
+                let payload = message.body.payload.clone();
+                self.rpc.call(
+                    &mut self.msg_id,
+                    &leader_id,
+                    payload,
+                    self.timers.now(),
+                    output,
+                    move |node: &mut Self, reply, output| match reply {
+                        Ok(reply) => {
+                            let mut client_reply = message.into_reply(Some(&mut node.msg_id));
+                            client_reply.body.payload = reply.body.payload;
+                            output.write(&client_reply)
+                        }
+                        Err(_) => node.reply_error(
+                            message,
+                            ErrorCode::Timeout,
+                            "leader did not answer in time",
+                            output,
+                        ),
+                    },
+                )?;
+                Ok(())
+            }
+            (_, None) => self.reply_error(
+                message,
+                ErrorCode::TemporarilyUnavailable,
+                "no leader elected",
+                output,
+            ),
+        }
+    }
+
+    fn handle_raft_msg(
+        &mut self,
+        src: String,
+        raft_payload: RaftPayload,
+        output: &mut StdoutJson,
+    ) -> anyhow::Result<()> {
+        match raft_payload {
+            RaftPayload::RequestVote {
+                term,
+                candidate_id,
+                last_log_index,
+                last_log_term,
+            } => {
+                self.observe_term(term);
+                let up_to_date = (last_log_term, last_log_index)
+                    >= (self.term_at(self.log.len()), self.log.len());
+                let vote_granted = term == self.current_term
+                    && up_to_date
+                    && self
+                        .voted_for
+                        .as_ref()
+                        .is_none_or(|voted_for| *voted_for == candidate_id);
+                if vote_granted {
+                    self.voted_for = Some(candidate_id);
+                    self.ticks_since_heard = 0;
+                }
+                self.send(
+                    &src,
+                    RaftPayload::RequestVoteOk {
+                        term: self.current_term,
+                        vote_granted,
+                    },
+                    output,
+                )
+            }
+            RaftPayload::RequestVoteOk { term, vote_granted } => {
+                self.observe_term(term);
+                if self.role != Role::Candidate || term != self.current_term || !vote_granted {
+                    return Ok(());
+                }
+                self.votes.insert(src);
+                if self.has_majority(self.votes.len()) {
+                    self.become_leader(output)?;
+                }
+                Ok(())
+            }
+            RaftPayload::AppendEntries {
+                term,
+                leader_id,
+                prev_log_index,
+                prev_log_term,
+                entries,
+                leader_commit,
+            } => {
+                self.observe_term(term);
+                if term < self.current_term {
+                    return self.send(
+                        &src,
+                        RaftPayload::AppendEntriesOk {
+                            term: self.current_term,
+                            success: false,
+                            match_index: 0,
+                        },
+                        output,
+                    );
+                }
+                self.role = Role::Follower;
+                self.leader_id = Some(leader_id);
+                self.ticks_since_heard = 0;
+
+                if prev_log_index > self.log.len() || self.term_at(prev_log_index) != prev_log_term
+                {
+                    let retry_from = prev_log_index.saturating_sub(1).min(self.log.len());
+                    return self.send(
+                        &src,
+                        RaftPayload::AppendEntriesOk {
+                            term: self.current_term,
+                            success: false,
+                            match_index: retry_from,
+                        },
+                        output,
+                    );
+                }
+
+                let last_new_index = prev_log_index + entries.len();
+                for (index, entry) in (prev_log_index + 1..).zip(entries) {
+                    if index <= self.log.len() {
+                        if self.term_at(index) == entry.term {
+                            continue;
+                        }
+                        self.log.truncate(index - 1);
+                    }
+                    self.log.push(entry);
+                }
+                if leader_commit > self.commit_index {
+                    self.commit_index = leader_commit.min(last_new_index);
+                    self.apply_committed(output)?;
+                }
+                self.send(
+                    &src,
+                    RaftPayload::AppendEntriesOk {
+                        term: self.current_term,
+                        success: true,
+                        match_index: last_new_index,
+                    },
+                    output,
+                )
+            }
+            RaftPayload::AppendEntriesOk {
+                term,
+                success,
+                match_index,
+            } => {
+                self.observe_term(term);
+                if self.role != Role::Leader || term != self.current_term {
+                    return Ok(());
+                }
+                if success {
+                    let matched = self.match_index.entry(src.clone()).or_default();
+                    *matched = (*matched).max(match_index);
+                    self.next_index.insert(src, *matched + 1);
+                    self.advance_commit_index(output)
+                } else {
+                    let next_index = self.next_index.entry(src).or_insert(1);
+                    *next_index = (match_index + 1).min(*next_index).max(1);
+                    Ok(())
+                }
+            }
+        }
+    }
+
+    /// Steps down to follower if `term` is newer than ours.
+    fn observe_term(&mut self, term: usize) {
+        if term > self.current_term {
+            self.current_term = term;
+            self.voted_for = None;
+            self.role = Role::Follower;
+            self.leader_id = None;
+        }
+    }
+
+    fn start_election(&mut self, output: &mut StdoutJson) -> anyhow::Result<()> {
+        self.current_term += 1;
+        self.role = Role::Candidate;
+        self.voted_for = Some(self.id.clone());
+        self.leader_id = None;
+        self.votes = HashSet::from([self.id.clone()]);
+        self.ticks_since_heard = 0;
+        self.election_ticks = Self::election_ticks(&mut self.rng);
+
+        if self.has_majority(self.votes.len()) {
+            return self.become_leader(output);
+        }
+        let request_vote = RaftPayload::RequestVote {
+            term: self.current_term,
+            candidate_id: self.id.clone(),
+            last_log_index: self.log.len(),
+            last_log_term: self.term_at(self.log.len()),
+        };
+        for peer in self.peers.clone() {
+            self.send(&peer, request_vote.clone(), output)?;
+        }
+        Ok(())
+    }
+
+    fn become_leader(&mut self, output: &mut StdoutJson) -> anyhow::Result<()> {
+        self.role = Role::Leader;
+        self.leader_id = Some(self.id.clone());
+        self.next_index = self
+            .peers
+            .iter()
+            .map(|peer| (peer.clone(), self.log.len() + 1))
+            .collect();
+        self.match_index = self.peers.iter().map(|peer| (peer.clone(), 0)).collect();
+        self.advance_commit_index(output)?;
+        self.replicate(output)
+    }
+
+    /// Sends every peer the entries it is missing, or a heartbeat if none.
+    fn replicate(&mut self, output: &mut StdoutJson) -> anyhow::Result<()> {
+        self.ticks_since_heartbeat = 0;
+        for peer in self.peers.clone() {
+            let next_index = self.next_index.get(&peer).copied().unwrap_or(1);
+            let prev_log_index = next_index - 1;
+            let entries = self
+                .log
+                .iter()
+                .skip(prev_log_index)
+                .take(MAX_ENTRIES_PER_APPEND)
+                .cloned()
+                .collect();
+            let append_entries = RaftPayload::AppendEntries {
+                term: self.current_term,
+                leader_id: self.id.clone(),
+                prev_log_index,
+                prev_log_term: self.term_at(prev_log_index),
+                entries,
+                leader_commit: self.commit_index,
+            };
+            self.send(&peer, append_entries, output)?;
+        }
+        Ok(())
+    }
+
+    /// Commits the highest entry of the current term stored on a majority of the cluster.
+    fn advance_commit_index(&mut self, output: &mut StdoutJson) -> anyhow::Result<()> {
+        let committable = (self.commit_index + 1..=self.log.len())
+            .rev()
+            .find(|&index| {
+                let replicas = 1 + self
+                    .match_index
+                    .values()
+                    .filter(|&&matched| matched >= index)
+                    .count();
+                self.term_at(index) == self.current_term && self.has_majority(replicas)
+            });
+        if let Some(index) = committable {
+            self.commit_index = index;
+            self.apply_committed(output)?;
+        }
+        Ok(())
+    }
+
+    fn apply_committed(&mut self, output: &mut StdoutJson) -> anyhow::Result<()> {
+        while self.last_applied < self.commit_index {
+            self.last_applied += 1;
+            let entry = self.log[self.last_applied - 1].clone();
+            let result = self.apply(entry.op);
+            if entry.node != self.id {
+                continue;
+            }
+            output.write(&Message {
+                src: self.id.clone(),
+                dst: entry.client,
+                body: Body {
+                    msg_id: Some(self.next_msg_id()),
+                    in_reply_to: Some(entry.client_msg_id),
+                    payload: Either::<KvPayload, RaftPayload>::Left(result),
+                },
+            })?;
+        }
+        Ok(())
+    }
+
+    fn apply(&mut self, op: KvPayload) -> KvPayload {
+        match op {
+            KvPayload::Read { key } => match self.state.get(&key) {
+                Some(value) => KvPayload::ReadOk { value: *value },
+                None => Self::key_does_not_exist(&key),
+            },
+            KvPayload::Write { key, value } => {
+                self.state.insert(key, value);
+                KvPayload::WriteOk
+            }
+            KvPayload::Cas {
+                key,
+                from,
+                to,
+                create_if_not_exists,
+            } => match self.state.get_mut(&key) {
+                Some(current) if *current == from => {
+                    *current = to;
+                    KvPayload::CasOk
+                }
+                Some(current) => KvPayload::Error {
+                    code: ErrorCode::PreconditionFailed,
+                    text: Some(format!("expected {}, but had {}", from, current)),
+                },
+                None if create_if_not_exists => {
+                    self.state.insert(key, to);
+                    KvPayload::CasOk
+                }
+                None => Self::key_does_not_exist(&key),
+            },
+            op => KvPayload::Error {
+                code: ErrorCode::NotSupported,
+                text: Some(format!("{:?} is not an operation", op)),
+            },
+        }
+    }
+
+    fn key_does_not_exist(key: &str) -> KvPayload {
+        KvPayload::Error {
+            code: ErrorCode::KeyDoesNotExist,
+            text: Some(format!("key {} does not exist", key)),
+        }
+    }
+
+    fn term_at(&self, index: usize) -> usize {
+        match index {
+            0 => 0,
+            index => self.log[index - 1].term,
+        }
+    }
+
+    fn has_majority(&self, count: usize) -> bool {
+        count * 2 > self.peers.len() + 1
+    }
+
+    fn next_msg_id(&mut self) -> usize {
+        let msg_id = self.msg_id;
+        self.msg_id += 1;
+        msg_id
+    }
+
+    fn send(
+        &mut self,
+        dest: &str,
+        raft_payload: RaftPayload,
+        output: &mut StdoutJson,
+    ) -> anyhow::Result<()> {
+        let msg_id = self.next_msg_id();
+        output.write(&Message {
+            src: self.id.clone(),
+            dst: dest.to_string(),
+            body: Body {
+                msg_id: Some(msg_id),
+                in_reply_to: None,
+                payload: Either::<KvPayload, RaftPayload>::Right(raft_payload),
+            },
+        })
+    }
+
+    fn reply_error(
+        &mut self,
+        message: Message<RaftOrKvPayload>,
+        code: ErrorCode,
+        text: &str,
+        output: &mut StdoutJson,
+    ) -> anyhow::Result<()> {
+        let error = ErrorPayload::new(code, text);
+        output.write(
+            &message
+                .into_reply(Some(&mut self.msg_id))
+                .map_payload(|_| error),
+        )
+    }
+}
@@ -0,0 +1,724 @@
+use crate::config;
+use crate::node::kafkalog::KafkaLog;
+use crate::node::{Node, common_init_node};
+use crate::payloads::{
+    DEFAULT_CONSUMER_GROUP, Either, ErrorCode, Event, InitPayload, KafkaLogPayload,
+    KafkaReplicationPayload, KafkaReplicationTimer, ReplicatedKafkaLogPayload, consumer_group,
+};
+use crate::rpc::{RpcCallbacks, RpcError, RpcOptions};
+use crate::stdout_json::StdoutJson;
+use crate::timer::Timers;
+use crate::{Body, Message};
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::time::Duration;
+
+/// Ticks without a `replicate_ok` after which a follower is sent the log again.
+const RESEND_TICKS: usize = 5;
+
+/// How long a `send` waits for its followers before it is answered with a timeout,
+/// shorter than the forwarding timeout so that forwarded sends get the owner's answer.
+const REPLICATION_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Multi-node kafka log that keeps the logs itself instead of in a Maelstrom KV service.
+///
+/// Keys are partitioned across nodes by hash. The owner of a key assigns offsets and
+/// serves commits for it, and copies its log to the `replication-factor - 1` nodes that
+/// follow it in node id order. A `send` is acknowledged once every follower has the message,
+/// so polls are served by any node holding a copy of the log. Requests reaching any other
+/// node are forwarded to the owners of their keys, and the replies of the owners gathered
+/// into one.
+///
+/// Committed offsets are kept per consumer group by the owners, which copy to followers
+/// those of the default group only.
+#[derive(Debug)]
+pub struct ReplicatedKafkaLogNode {
+    pub id: String,
+    pub msg_id: usize,
+    /// Every node, sorted: the ring keys are placed on.
+    pub node_ids: Vec<String>,
+    pub replication_factor: usize,
+    /// Logs owned by this node and copies of the logs it follows.
+    logs: HashMap<String, KafkaLog>,
+    /// Replication progress of each follower, by follower and key.
+    replicas: HashMap<(String, String), ReplicaProgress>,
+    /// Client requests waiting for the replies of other owners, by request id.
+    pending: HashMap<usize, PendingRequest>,
+    /// Sends to owned keys waiting for their followers, by key in offset order.
+    unreplicated: HashMap<String, VecDeque<UnreplicatedSend>>,
+    next_request_id: usize,
+    ticks: usize,
+    rpc: RpcCallbacks<Self, ReplicatedKafkaLogPayload>,
+    timers: Timers<KafkaReplicationTimer>,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct ReplicaProgress {
+    /// Length and committed offset the follower acknowledged.
+    len: usize,
+    committed: usize,
+    /// End of the log last sent, and when.
+    sent_len: usize,
+    sent_at: usize,
+}
+
+#[derive(Debug)]
+struct UnreplicatedSend {
+    request: Message<ReplicatedKafkaLogPayload>,
+    offset: usize,
+    appended_at: Duration,
+}
+
+#[derive(Debug)]
+struct PendingRequest {
+    request: Message<ReplicatedKafkaLogPayload>,
+    remaining: usize,
+    reply: Option<KafkaLogPayload>,
+}
+
+impl Node<ReplicatedKafkaLogPayload, KafkaReplicationTimer> for ReplicatedKafkaLogNode {
+    fn init(
+        init_msg: Message<InitPayload>,
+        output: &mut StdoutJson,
+        timers: &Timers<KafkaReplicationTimer>,
+    ) -> anyhow::Result<Self>
+    where
+        Self: Sized,
+    {
+        let replication_factor = config::parse("replication-factor")?.unwrap_or(2);
+        let interval = config::millis_or("replication-interval-ms", Duration::from_millis(50))?;
+        let (node_id, mut node_ids) = common_init_node(init_msg, output)?;
+        node_ids.sort();
+        timers.every("tick", interval, KafkaReplicationTimer::Tick)?;
+        Ok(Self {
+            timers: timers.clone(),
+            rpc: RpcCallbacks::new(
+                node_id.clone(),
+                RpcOptions::new(Duration::from_millis(1000)),
+            ),
+            id: node_id,
+            msg_id: 0,
+            replication_factor: replication_factor.clamp(1, node_ids.len().max(1)),
+            node_ids,
+            logs: HashMap::new(),
+            replicas: HashMap::new(),
+            pending: HashMap::new(),
+            unreplicated: HashMap::new(),
+            next_request_id: 0,
+            ticks: 0,
+        })
+    }
+
+    fn step(
+        &mut self,
+        event: Event<ReplicatedKafkaLogPayload, KafkaReplicationTimer>,
+        output: &mut StdoutJson,
+    ) -> anyhow::Result<()> {
+        match event {
+            Event::Message(message) => {
+                if let Some(callback) = self.rpc.take_callback(&message) {
+                    return callback(self, Ok(message), output);
+                }
+                match message.body.payload {
+                    Either::Left(_) => self.handle_request(message, output),
+                    Either::Right(replication_payload) => {
+                        self.handle_replication(message.src, replication_payload, output)
+                    }
+                }
+            }
+            Event::InjectedPayload(KafkaReplicationTimer::Tick) => self.tick(output),
+        }
+    }
+}
+
+impl ReplicatedKafkaLogNode {
+    /// Node that assigns the offsets of `key`.
+    pub fn owner_of(&self, key: &str) -> &str {
+        &self.node_ids[Self::hash(key) as usize % self.node_ids.len()]
+    }
+
+    /// Nodes holding a copy of the log of `key`, its owner excluded.
+    pub fn followers_of(&self, key: &str) -> impl Iterator<Item = &String> {
+        let owner = Self::hash(key) as usize % self.node_ids.len();
+        (1..self.replication_factor).map(move |i| &self.node_ids[(owner + i) % self.node_ids.len()])
+    }
+
+    /// Node a poll of `key` is served by: this node if it holds a copy of the log,
+    /// the owner otherwise.
+    fn reader_of(&self, key: &str) -> &str {
+        let holds_copy = self.logs.contains_key(key)
+            && (self.owner_of(key) == self.id || self.followers_of(key).any(|f| *f == self.id));
+        if holds_copy {
+            &self.id
+        } else {
+            self.owner_of(key)
+        }
+    }
+
+    /// FNV-1a, so that every node places a key on the same owner.
+    fn hash(key: &str) -> u64 {
+        key.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+        })
+    }
+
+    fn tick(&mut self, output: &mut StdoutJson) -> anyhow::Result<()> {
+        self.ticks += 1;
+        for callback in self
+            .rpc
+            .expire(&mut self.msg_id, self.timers.now(), output)?
+        {
+            callback(self, Err(RpcError::Timeout), output)?;
+        }
+        self.expire_unreplicated(output)?;
+        self.replicate(output)
+    }
+
+    /// Serves a request, forwarding the keys owned by other nodes to their owners.
+    /// Requests forwarded by other nodes are served locally.
+    fn handle_request(
+        &mut self,
+        message: Message<ReplicatedKafkaLogPayload>,
+        output: &mut StdoutJson,
+    ) -> anyhow::Result<()> {
+        let Either::Left(request) = message.body.payload.clone() else {
+            return Ok(());
+        };
+        if matches!(
+            request,
+            KafkaLogPayload::SendOk { .. }
+                | KafkaLogPayload::PollOk { .. }
+                | KafkaLogPayload::CommitOffsetsOk
+                | KafkaLogPayload::ListCommittedOffsetsOk { .. }
+                | KafkaLogPayload::ResetOffsetsOk
+                | KafkaLogPayload::ListGroupsOk { .. }
+        ) {
+            return Ok(());
+        }
+
+        let mut gathered = empty_reply(&request);
+        let mut parts = if self.node_ids.contains(&message.src) {
+            HashMap::from([(self.id.clone(), request)])
+        } else {
+            self.split_by_owner(request)
+        };
+        if let Some(part) = parts.remove(&self.id) {
+            let sent_key = match &part {
+                KafkaLogPayload::Send { key, .. } => Some(key.clone()),
+                _ => None,
+            };
+            let served = self.serve(part);
+            if let (Some(key), KafkaLogPayload::SendOk { offset }) = (sent_key, &served)
+                && self.replication_factor > 1
+            {
+                let offset = *offset;
+                return self.await_replication(key, offset, message, output);
+            }
+            gathered = Some(merge_replies(gathered, served));
+        }
+        if parts.is_empty() {
+            let Some(gathered) = gathered else {
+                return Ok(());
+            };
+            let mut reply = message.into_reply(Some(&mut self.msg_id));
+            reply.body.payload = Either::Left(gathered);
+            return output.write(&reply);
+        }
+
+        let request_id = self.next_request_id;
+        self.next_request_id += 1;
+        self.pending.insert(
+            request_id,
+            PendingRequest {
+                request: message,
+                remaining: parts.len(),
+                reply: gathered,
+            },
+        );
+        for (owner, part) in parts {
+            self.rpc.call(
+                &mut self.msg_id,
+                &owner,
+                Either::Left(part),
+                self.timers.now(),
+                output,
+                move |node: &mut Self, reply, output| {
+                    node.complete_part(request_id, reply.map(|reply| reply.body.payload), output)
+                },
+            )?;
+        }
+        Ok(())
+    }
+
+    fn split_by_owner(&self, request: KafkaLogPayload) -> HashMap<String, KafkaLogPayload> {
+        let mut parts = HashMap::new();
+        match request {
+            KafkaLogPayload::Send { ref key, .. } => {
+                parts.insert(self.owner_of(key).to_string(), request);
+            }
+            KafkaLogPayload::Poll { offsets } => {
+                for (key, offset) in offsets {
+                    let part = parts
+                        .entry(self.reader_of(&key).to_string())
+                        .or_insert_with(|| KafkaLogPayload::Poll {
+                            offsets: HashMap::new(),
+                        });
+                    if let KafkaLogPayload::Poll { offsets } = part {
+                        offsets.insert(key, offset);
+                    }
+                }
+            }
+            KafkaLogPayload::CommitOffsets { offsets, group } => {
+                for (key, offset) in offsets {
+                    let part = parts
+                        .entry(self.owner_of(&key).to_string())
+                        .or_insert_with(|| KafkaLogPayload::CommitOffsets {
+                            offsets: HashMap::new(),
+                            group: group.clone(),
+                        });
+                    if let KafkaLogPayload::CommitOffsets { offsets, .. } = part {
+                        offsets.insert(key, offset);
+                    }
+                }
+            }
+            KafkaLogPayload::ListCommittedOffsets { keys, group } => {
+                for key in keys {
+                    let part = parts
+                        .entry(self.owner_of(&key).to_string())
+                        .or_insert_with(|| KafkaLogPayload::ListCommittedOffsets {
+                            keys: Vec::new(),
+                            group: group.clone(),
+                        });
+                    if let KafkaLogPayload::ListCommittedOffsets { keys, .. } = part {
+                        keys.push(key);
+                    }
+                }
+            }
+            KafkaLogPayload::ResetOffsets { offsets, group } => {
+                for (key, offset) in offsets {
+                    let part = parts
+                        .entry(self.owner_of(&key).to_string())
+                        .or_insert_with(|| KafkaLogPayload::ResetOffsets {
+                            offsets: HashMap::new(),
+                            group: group.clone(),
+                        });
+                    if let KafkaLogPayload::ResetOffsets { offsets, .. } = part {
+                        offsets.insert(key, offset);
+                    }
+                }
+            }
+            KafkaLogPayload::ListGroups => {
+                for node_id in &self.node_ids {
+                    parts.insert(node_id.clone(), KafkaLogPayload::ListGroups);
+                }
+            }
+            KafkaLogPayload::SendOk { .. }
+            | KafkaLogPayload::PollOk { .. }
+            | KafkaLogPayload::CommitOffsetsOk
+            | KafkaLogPayload::ListCommittedOffsetsOk { .. }
+            | KafkaLogPayload::ResetOffsetsOk
+            | KafkaLogPayload::ListGroupsOk { .. } => {}
+        }
+        parts
+    }
+
+    /// Applies a request for keys owned by this node and returns its reply.
+    fn serve(&mut self, request: KafkaLogPayload) -> KafkaLogPayload {
+        match request {
+            KafkaLogPayload::Send { key, msg } => {
+                let log = self
+                    .logs
+                    .entry(key)
+                    .or_insert_with(|| KafkaLog::new(5, 100));
+                KafkaLogPayload::SendOk {
+                    offset: log.append(msg),
+                }
+            }
+            KafkaLogPayload::Poll { offsets } => {
+                let mut msgs = HashMap::with_capacity(offsets.len());
+                let mut out_of_range = HashMap::new();
+                let mut compacted = HashMap::new();
+                for (key, offset) in offsets {
+                    if let Some(log) = self.logs.get(&key) {
+                        match log.poll(offset) {
+                            Ok(polled) => {
+                                if let Some(compacted_end) = log.compacted_past(offset) {
+                                    compacted.insert(key.clone(), compacted_end);
+                                }
+                                msgs.insert(key, polled);
+                            }
+                            Err(error) => {
+                                out_of_range.insert(key, error.low_water_mark);
+                            }
+                        }
+                    }
+                }
+                KafkaLogPayload::PollOk {
+                    msgs,
+                    out_of_range,
+                    compacted,
+                }
+            }
+            KafkaLogPayload::CommitOffsets { offsets, group } => {
+                let group = consumer_group(group);
+                for (key, offset) in offsets {
+                    if let Some(log) = self.logs.get_mut(&key) {
+                        log.commit_offset(&group, offset);
+                    }
+                }
+                KafkaLogPayload::CommitOffsetsOk
+            }
+            KafkaLogPayload::ListCommittedOffsets { keys, group } => {
+                let group = consumer_group(group);
+                KafkaLogPayload::ListCommittedOffsetsOk {
+                    offsets: keys
+                        .into_iter()
+                        .filter_map(|key| {
+                            let committed = self.logs.get(&key)?.get_committed_offset(&group);
+                            Some((key, committed))
+                        })
+                        .collect(),
+                }
+            }
+            KafkaLogPayload::ResetOffsets { offsets, group } => {
+                let group = consumer_group(group);
+                for (key, offset) in offsets {
+                    if let Some(log) = self.logs.get_mut(&key) {
+                        log.reset_offset(&group, offset);
+                    }
+                }
+                KafkaLogPayload::ResetOffsetsOk
+            }
+            KafkaLogPayload::ListGroups => {
+                let groups = self
+                    .logs
+                    .iter()
+                    .filter(|(key, _)| self.owner_of(key) == self.id)
+                    .flat_map(|(_, log)| log.groups())
+                    .cloned()
+                    .collect::<BTreeSet<_>>();
+                KafkaLogPayload::ListGroupsOk {
+                    groups: groups.into_iter().collect(),
+                }
+            }
+            reply => reply,
+        }
+    }
+
+    fn complete_part(
+        &mut self,
+        request_id: usize,
+        reply: Result<ReplicatedKafkaLogPayload, RpcError>,
+        output: &mut StdoutJson,
+    ) -> anyhow::Result<()> {
+        let Some(pending) = self.pending.get_mut(&request_id) else {
+            return Ok(());
+        };
+        let (code, text) = match reply {
+            Ok(Either::Left(part)) => {
+                pending.reply = Some(merge_replies(pending.reply.take(), part));
+                pending.remaining -= 1;
+                return self.complete_request(request_id, output);
+            }
+            Ok(Either::Right(KafkaReplicationPayload::Error { code, text })) => {
+                (code, text.unwrap_or_else(|| "key owner failed".to_string()))
+            }
+            Ok(Either::Right(_)) => (ErrorCode::Crash, "unexpected reply from key owner".into()),
+            Err(RpcError::Timeout) => (
+                ErrorCode::Timeout,
+                "key owner did not answer in time".into(),
+            ),
+            Err(error) => (ErrorCode::Crash, error.to_string()),
+        };
+        let pending = self
+            .pending
+            .remove(&request_id)
+            .expect("request is pending");
+        let error = pending
+            .request
+            .into_error_reply(Some(&mut self.msg_id), code, text);
+        output.write(&error)
+    }
+
+    fn complete_request(
+        &mut self,
+        request_id: usize,
+        output: &mut StdoutJson,
+    ) -> anyhow::Result<()> {
+        let Some(pending) = self.pending.get(&request_id) else {
+            return Ok(());
+        };
+        if pending.remaining > 0 {
+            return Ok(());
+        }
+        let pending = self
+            .pending
+            .remove(&request_id)
+            .expect("request is pending");
+        let Some(gathered) = pending.reply else {
+            return Ok(());
+        };
+        let mut reply = pending.request.into_reply(Some(&mut self.msg_id));
+        reply.body.payload = Either::Left(gathered);
+        output.write(&reply)
+    }
+
+    /// Holds the `send_ok` of `request` until every follower has the message at `offset`,
+    /// and sends it to them right away.
+    fn await_replication(
+        &mut self,
+        key: String,
+        offset: usize,
+        request: Message<ReplicatedKafkaLogPayload>,
+        output: &mut StdoutJson,
+    ) -> anyhow::Result<()> {
+        self.unreplicated
+            .entry(key.clone())
+            .or_default()
+            .push_back(UnreplicatedSend {
+                request,
+                offset,
+                appended_at: self.timers.now(),
+            });
+        self.replicate_key(&key, output)
+    }
+
+    /// Answers the sends to `key` that every follower acknowledged.
+    fn release_replicated(&mut self, key: &str, output: &mut StdoutJson) -> anyhow::Result<()> {
+        let replicated_len = self
+            .followers_of(key)
+            .map(|follower| {
+                self.replicas
+                    .get(&(follower.clone(), key.to_string()))
+                    .map_or(0, |progress| progress.len)
+            })
+            .min()
+            .unwrap_or(usize::MAX);
+        let Some(unreplicated) = self.unreplicated.get_mut(key) else {
+            return Ok(());
+        };
+        let mut replicated = Vec::new();
+        while unreplicated
+            .front()
+            .is_some_and(|send| send.offset < replicated_len)
+        {
+            replicated.extend(unreplicated.pop_front());
+        }
+        for send in replicated {
+            let mut reply = send.request.into_reply(Some(&mut self.msg_id));
+            reply.body.payload = Either::Left(KafkaLogPayload::SendOk {
+                offset: send.offset,
+            });
+            output.write(&reply)?;
+        }
+        Ok(())
+    }
+
+    /// Answers with a timeout the sends whose followers did not acknowledge them in time:
+    /// the message stays in the log and may still reach them.
+    fn expire_unreplicated(&mut self, output: &mut StdoutJson) -> anyhow::Result<()> {
+        let now = self.timers.now();
+        let mut expired = Vec::new();
+        for unreplicated in self.unreplicated.values_mut() {
+            while unreplicated
+                .front()
+                .is_some_and(|send| now.saturating_sub(send.appended_at) >= REPLICATION_TIMEOUT)
+            {
+                expired.extend(unreplicated.pop_front());
+            }
+        }
+        // Nodes that forwarded a send decode the error as a `KafkaReplicationPayload::Error`.
+        for send in expired {
+            let error = send.request.into_error_reply(
+                Some(&mut self.msg_id),
+                ErrorCode::Timeout,
+                "followers did not acknowledge the message in time",
+            );
+            output.write(&error)?;
+        }
+        Ok(())
+    }
+
+    /// Sends each follower the part of the owned logs it has not acknowledged.
+    fn replicate(&mut self, output: &mut StdoutJson) -> anyhow::Result<()> {
+        let owned = self
+            .logs
+            .keys()
+            .filter(|key| self.owner_of(key) == self.id)
+            .cloned()
+            .collect::<Vec<_>>();
+        for key in owned {
+            self.replicate_key(&key, output)?;
+        }
+        Ok(())
+    }
+
+    fn replicate_key(&mut self, key: &str, output: &mut StdoutJson) -> anyhow::Result<()> {
+        let mut outgoing = Vec::new();
+        if let Some(log) = self.logs.get(key) {
+            for follower in self.followers_of(key).cloned().collect::<Vec<_>>() {
+                let progress = self
+                    .replicas
+                    .entry((follower.clone(), key.to_string()))
+                    .or_default();
+                let committed = log.get_committed_offset(DEFAULT_CONSUMER_GROUP);
+                let up_to_date = progress.len == log.len() && progress.committed == committed;
+                let unacked_for_long = self.ticks - progress.sent_at >= RESEND_TICKS;
+                let offset = if log.len() > progress.sent_len {
+                    progress.sent_len.max(progress.len)
+                } else if !up_to_date && unacked_for_long {
+                    progress.len
+                } else {
+                    continue;
+                };
+                progress.sent_len = log.len();
+                progress.sent_at = self.ticks;
+                outgoing.push((
+                    follower,
+                    KafkaReplicationPayload::Replicate {
+                        key: key.to_string(),
+                        offset,
+                        msgs: log.messages_from(offset),
+                        committed,
+                    },
+                ));
+            }
+        }
+        for (follower, payload) in outgoing {
+            self.send(&follower, payload, output)?;
+        }
+        Ok(())
+    }
+
+    fn handle_replication(
+        &mut self,
+        src: String,
+        payload: KafkaReplicationPayload,
+        output: &mut StdoutJson,
+    ) -> anyhow::Result<()> {
+        match payload {
+            KafkaReplicationPayload::Replicate {
+                key,
+                offset,
+                msgs,
+                committed,
+            } => {
+                let log = self
+                    .logs
+                    .entry(key.clone())
+                    .or_insert_with(|| KafkaLog::new(5, 100));
+                if log.append_from(offset, &msgs) {
+                    log.reset_offset(DEFAULT_CONSUMER_GROUP, committed);
+                }
+                let reply = KafkaReplicationPayload::ReplicateOk {
+                    key,
+                    len: log.len(),
+                    committed: log.get_committed_offset(DEFAULT_CONSUMER_GROUP),
+                };
+                self.send(&src, reply, output)
+            }
+            KafkaReplicationPayload::ReplicateOk {
+                key,
+                len,
+                committed,
+            } => {
+                if let Some(progress) = self.replicas.get_mut(&(src, key.clone())) {
+                    progress.len = progress.len.max(len);
+                    progress.committed = committed;
+                    // Resend from the follower's end next time, in case it missed
+                    // part of what was sent. Appends it already has are ignored.
+                    progress.sent_len = progress.len;
+                }
+                self.release_replicated(&key, output)
+            }
+            // Errors are only expected as replies to forwarded requests.
+            KafkaReplicationPayload::Error { .. } => Ok(()),
+        }
+    }
+
+    fn send(
+        &mut self,
+        dst: &str,
+        payload: KafkaReplicationPayload,
+        output: &mut StdoutJson,
+    ) -> anyhow::Result<()> {
+        let msg: Message<ReplicatedKafkaLogPayload> = Message {
+            src: self.id.clone(),
+            dst: dst.to_string(),
+            body: Body {
+                msg_id: Some(self.msg_id),
+                in_reply_to: None,
+                payload: Either::Right(payload),
+            },
+        };
+        self.msg_id += 1;
+        output.write(&msg)
+    }
+}
+
+/// Combines the replies of the owners of different keys.
+fn merge_replies(reply: Option<KafkaLogPayload>, part: KafkaLogPayload) -> KafkaLogPayload {
+    match (reply, part) {
+        (
+            Some(KafkaLogPayload::PollOk {
+                mut msgs,
+                mut out_of_range,
+                mut compacted,
+            }),
+            KafkaLogPayload::PollOk {
+                msgs: part_msgs,
+                out_of_range: part_out_of_range,
+                compacted: part_compacted,
+            },
+        ) => {
+            msgs.extend(part_msgs);
+            out_of_range.extend(part_out_of_range);
+            compacted.extend(part_compacted);
+            KafkaLogPayload::PollOk {
+                msgs,
+                out_of_range,
+                compacted,
+            }
+        }
+        (
+            Some(KafkaLogPayload::ListCommittedOffsetsOk { mut offsets }),
+            KafkaLogPayload::ListCommittedOffsetsOk {
+                offsets: part_offsets,
+            },
+        ) => {
+            offsets.extend(part_offsets);
+            KafkaLogPayload::ListCommittedOffsetsOk { offsets }
+        }
+        (
+            Some(KafkaLogPayload::ListGroupsOk { mut groups }),
+            KafkaLogPayload::ListGroupsOk {
+                groups: part_groups,
+            },
+        ) => {
+            groups.extend(part_groups);
+            groups.sort_unstable();
+            groups.dedup();
+            KafkaLogPayload::ListGroupsOk { groups }
+        }
+        (_, part) => part,
+    }
+}
+
+/// Reply to `request` when none of its keys exist, that owner replies are merged into.
+fn empty_reply(request: &KafkaLogPayload) -> Option<KafkaLogPayload> {
+    match request {
+        KafkaLogPayload::Poll { .. } => Some(KafkaLogPayload::PollOk {
+            msgs: HashMap::new(),
+            out_of_range: HashMap::new(),
+            compacted: HashMap::new(),
+        }),
+        KafkaLogPayload::CommitOffsets { .. } => Some(KafkaLogPayload::CommitOffsetsOk),
+        KafkaLogPayload::ListCommittedOffsets { .. } => {
+            Some(KafkaLogPayload::ListCommittedOffsetsOk {
+                offsets: HashMap::new(),
+            })
+        }
+        KafkaLogPayload::ResetOffsets { .. } => Some(KafkaLogPayload::ResetOffsetsOk),
+        KafkaLogPayload::ListGroups => Some(KafkaLogPayload::ListGroupsOk { groups: Vec::new() }),
+        _ => None,
+    }
+}
@@ -1,9 +1,9 @@
 use crate::Message;
 use crate::node::{Node, common_init_node};
-use crate::payloads::{Event, InitPayload, TxnOperation, TxnPayload};
+use crate::payloads::{ErrorCode, ErrorPayload, Event, InitPayload, TxnOperation, TxnPayload};
 use crate::stdout_json::StdoutJson;
+use crate::timer::Timers;
 use std::collections::HashMap;
-use std::sync::mpsc::Sender;
 
 #[derive(Debug, Clone)]
 pub struct SingleTxnNode {
@@ -16,7 +16,7 @@ impl Node<TxnPayload, ()> for SingleTxnNode {
     fn init(
         init_msg: Message<InitPayload>,
         output: &mut StdoutJson,
-        _tx_channel: Sender<Event<TxnPayload, ()>>,
+        _timers: &Timers<()>,
     ) -> anyhow::Result<Self>
     where
         Self: Sized,
@@ -38,7 +38,11 @@ impl Node<TxnPayload, ()> for SingleTxnNode {
             Event::Message(msg) => {
                 let mut reply = msg.into_reply(Some(&mut self.msg_id));
                 let TxnPayload::Txn { txn } = reply.body.payload else {
-                    panic!("invalid payload")
+                    let error = ErrorPayload::new(
+                        ErrorCode::NotSupported,
+                        "only txn requests are supported",
+                    );
+                    return output.write(&reply.map_payload(|_| error));
                 };
 
                 let mut txn_reply = Vec::with_capacity(txn.len());
@@ -1,4 +1,6 @@
-use crate::{Body, Message};
+use crate::crdt::intervals::as_elements;
+use crate::crdt::{CrdtWorkload, IntervalSet};
+use crate::{Body, FromBody, Message};
 use serde::de::Error;
 use serde::{Deserialize, Deserializer, Serialize};
 use serde_core::Serializer;
@@ -27,6 +29,12 @@ pub struct InitOkPayload {
     pub ty: String,
 }
 
+impl Default for InitOkPayload {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl InitOkPayload {
     const TYPE: &'static str = "init_ok";
     pub fn new() -> Self {
@@ -57,21 +65,60 @@ pub enum BroadcastPayload {
     BroadcastOk,
     Read,
     ReadOk {
-        messages: HashSet<usize>,
+        #[serde(with = "as_elements")]
+        messages: IntervalSet,
     },
     Topology {
         topology: HashMap<String, Vec<String>>,
     },
     TopologyOk,
     Gossip {
-        seen: HashSet<usize>,
+        #[serde(flatten)]
+        seen: GossipSet,
+        upto: u64,
+    },
+    GossipOk {
+        upto: u64,
+    },
+    Forward {
+        messages: HashSet<usize>,
     },
-    GossipOk,
+    ForwardOk,
+}
+
+/// Values carried by broadcast gossip: listed one by one in `seen`, or as the
+/// runs of consecutive values in `runs`, e.g. `[[0, 41], [43, 99]]`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum GossipSet {
+    Elements { seen: Vec<usize> },
+    Runs { runs: IntervalSet },
+}
+
+impl GossipSet {
+    pub fn encode(set: IntervalSet, compact: bool) -> Self {
+        if compact {
+            GossipSet::Runs { runs: set }
+        } else {
+            GossipSet::Elements {
+                seen: set.iter().collect(),
+            }
+        }
+    }
+
+    pub fn into_set(self) -> IntervalSet {
+        match self {
+            GossipSet::Elements { seen } => seen.into_iter().collect(),
+            GossipSet::Runs { runs } => runs,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 pub enum InjectedPayload {
     Gossip,
+    Flush,
+    Retransmit { peer: String },
 }
 
 #[derive(Debug, Clone)]
@@ -84,79 +131,7 @@ where
     InjectedPayload(IP),
 }
 
-#[derive(Debug, Clone, Deserialize)]
-pub enum GoCounterOrSeqKvPayload {
-    GoCounter(GoCounterPayload),
-    SeqKv(KvPayload),
-}
-
-impl Serialize for GoCounterOrSeqKvPayload {
-    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
-    where
-        S: Serializer,
-    {
-        match self {
-            GoCounterOrSeqKvPayload::GoCounter(go_counter_payload) => {
-                go_counter_payload.serialize(serializer)
-            }
-            GoCounterOrSeqKvPayload::SeqKv(seq_kv_payload) => seq_kv_payload.serialize(serializer),
-        }
-    }
-}
-
-impl<'de> Deserialize<'de> for Message<GoCounterOrSeqKvPayload> {
-    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
-    where
-        D: Deserializer<'de>,
-    {
-        let mut json = serde_json::Value::deserialize(deserializer)?;
-
-        let src = json
-            .get("src")
-            .and_then(|s| s.as_str())
-            .ok_or_else(|| Error::missing_field("src"))?
-            .to_string();
-
-        let dest = json
-            .get("dest")
-            .and_then(|s| s.as_str())
-            .ok_or_else(|| Error::missing_field("dest"))?
-            .to_string();
-
-        let body_val = json
-            .get_mut("body")
-            .ok_or_else(|| Error::missing_field("body"))?
-            .take();
-
-        if src == "seq-kv" {
-            let body = serde_json::from_value::<Body<KvPayload>>(body_val)
-                .map_err(|_| Error::custom("failed to deserialize body seq kv"))?;
-
-            Ok(Message {
-                src,
-                dst: dest,
-                body: Body {
-                    msg_id: body.msg_id,
-                    in_reply_to: body.in_reply_to,
-                    payload: GoCounterOrSeqKvPayload::SeqKv(body.payload),
-                },
-            })
-        } else {
-            let body = serde_json::from_value::<Body<GoCounterPayload>>(body_val)
-                .map_err(|_| Error::custom("failed to deserialize body seq kv"))?;
-
-            Ok(Message {
-                src,
-                dst: dest,
-                body: Body {
-                    msg_id: body.msg_id,
-                    in_reply_to: body.in_reply_to,
-                    payload: GoCounterOrSeqKvPayload::GoCounter(body.payload),
-                },
-            })
-        }
-    }
-}
+pub type GoCounterOrSeqKvPayload = OrService<GoCounterPayload, KvPayload>;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type")]
@@ -171,38 +146,136 @@ pub enum GoCounterPayload {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type")]
 #[serde(rename_all = "snake_case")]
-pub enum KvPayload {
+#[serde(bound = "C: CrdtWorkload")]
+pub enum CrdtPayload<C>
+where
+    C: CrdtWorkload,
+{
+    Add(C::Add),
+    AddOk,
+    Read,
+    ReadOk {
+        value: C::Value,
+    },
+    /// Updates the receiver is missing, to acknowledge up to `upto`.
+    Gossip {
+        delta: C,
+        upto: u64,
+    },
+    GossipOk {
+        upto: u64,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub enum CrdtTimer {
+    Gossip,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type")]
+#[serde(rename_all = "snake_case")]
+pub enum KvPayload<V = usize> {
     Read {
+        #[serde(deserialize_with = "string_or_number")]
         key: String,
     },
     ReadOk {
-        value: usize,
+        value: V,
     },
     Write {
+        #[serde(deserialize_with = "string_or_number")]
         key: String,
-        value: usize,
+        value: V,
     },
     WriteOk,
     Cas {
+        #[serde(deserialize_with = "string_or_number")]
         key: String,
-        from: usize,
-        to: usize,
+        from: V,
+        to: V,
+        #[serde(default)]
         create_if_not_exists: bool,
     },
     CasOk,
     Error {
-        code: usize,
+        code: ErrorCode,
         text: Option<String>,
     },
 }
 
+impl<V> ServicePayload for KvPayload<V> {
+    fn is_sent_by(src: &str) -> bool {
+        matches!(src, "seq-kv" | "lin-kv" | "lww-kv")
+    }
+}
+
+/// The lin-kv workload uses integer keys, the kv services string ones.
+fn string_or_number<'de, D>(deserializer: D) -> Result<String, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match serde_json::Value::deserialize(deserializer)? {
+        serde_json::Value::String(key) => Ok(key),
+        serde_json::Value::Number(key) => Ok(key.to_string()),
+        key => Err(Error::custom(format!("invalid key {}", key))),
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type")]
+#[serde(rename_all = "snake_case")]
+pub enum RaftPayload {
+    RequestVote {
+        term: usize,
+        candidate_id: String,
+        last_log_index: usize,
+        last_log_term: usize,
+    },
+    RequestVoteOk {
+        term: usize,
+        vote_granted: bool,
+    },
+    AppendEntries {
+        term: usize,
+        leader_id: String,
+        prev_log_index: usize,
+        prev_log_term: usize,
+        entries: Vec<RaftEntry>,
+        leader_commit: usize,
+    },
+    AppendEntriesOk {
+        term: usize,
+        success: bool,
+        /// Last index known to match the leader log on success, where to retry from otherwise.
+        match_index: usize,
+    },
+}
+
+/// A client request in the raft log, answered by `node` once applied.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RaftEntry {
+    pub term: usize,
+    pub node: String,
+    pub client: String,
+    pub client_msg_id: usize,
+    pub op: KvPayload,
+}
+
+/// Clients speak `KvPayload`, nodes among themselves `RaftPayload`.
+pub type RaftOrKvPayload = Either<KvPayload, RaftPayload>;
+
+#[derive(Debug, Clone)]
+pub enum RaftTimer {
+    Tick,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum SyncCounter {
     Sync,
     CheckWrites,
 }
 
-
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type")]
 #[serde(rename_all = "snake_case")]
@@ -219,98 +292,214 @@ pub enum KafkaLogPayload {
     },
     PollOk {
         msgs: HashMap<String, Vec<(usize, usize)>>,
+        /// Low-water mark of the keys polled below it, whose messages were dropped.
+        #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+        out_of_range: HashMap<String, usize>,
+        /// End of the compacted segments of the keys polled from within them: the offsets
+        /// below it missing from `msgs` were compacted away.
+        #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+        compacted: HashMap<String, usize>,
     },
+    /// Advances the committed offsets of a consumer group.
     CommitOffsets {
         offsets: HashMap<String, usize>,
+        /// The default group if missing, as in Maelstrom's requests.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        group: Option<String>,
     },
     CommitOffsetsOk,
     ListCommittedOffsets {
         keys: Vec<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        group: Option<String>,
     },
     ListCommittedOffsetsOk {
         offsets: HashMap<String, usize>,
     },
+    /// Moves the committed offsets of a consumer group, backwards too.
+    ResetOffsets {
+        offsets: HashMap<String, usize>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        group: Option<String>,
+    },
+    ResetOffsetsOk,
+    /// Lists the consumer groups that committed an offset.
+    ListGroups,
+    ListGroupsOk {
+        groups: Vec<String>,
+    },
+}
+
+/// Consumer group of the kafka requests that do not name one.
+pub const DEFAULT_CONSUMER_GROUP: &str = "default";
+
+/// Name of the consumer group of a kafka request.
+pub fn consumer_group(group: Option<String>) -> String {
+    group.unwrap_or_else(|| DEFAULT_CONSUMER_GROUP.to_string())
 }
 
+/// Value of a kv key used by the multi-node kafka log: the last offset of a log, a segment
+/// of it, holding the `[offset, msg]` pairs of a block of offsets, or the consumer groups.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum KafkaKvValue {
+    Offset(usize),
+    Segment(Vec<(usize, usize)>),
+    /// Consumer groups that committed an offset on any key, as `{"groups": [...]}`
+    /// so that an empty registry is not read as an empty segment.
+    Groups {
+        groups: Vec<String>,
+    },
+}
+
+pub type KafkaLogOrKvPayload = OrService<KafkaLogPayload, KvPayload<KafkaKvValue>>;
+
 #[derive(Debug, Clone)]
-pub enum KafkaLogOrKvPayload {
-    KafkaLog(KafkaLogPayload),
-    Kv(KvPayload),
+pub enum KafkaLogTimer {
+    /// Times out stalled requests and retries unanswered kv requests.
+    Expire,
 }
 
+/// Copy of the log of a key, sent by its owner to its followers.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type")]
+#[serde(rename_all = "snake_case")]
+pub enum KafkaReplicationPayload {
+    Replicate {
+        key: String,
+        /// Offset of the first of `msgs`.
+        offset: usize,
+        msgs: Vec<usize>,
+        committed: usize,
+    },
+    ReplicateOk {
+        key: String,
+        /// Length of the follower's copy of the log.
+        len: usize,
+        committed: usize,
+    },
+    /// The reply of an owner to a request forwarded to it that failed.
+    Error {
+        code: ErrorCode,
+        text: Option<String>,
+    },
+}
+
+/// Clients speak `KafkaLogPayload`, which nodes also forward to key owners;
+/// owners replicate to followers and report failed forwarded requests with
+/// `KafkaReplicationPayload`.
+pub type ReplicatedKafkaLogPayload = Either<KafkaLogPayload, KafkaReplicationPayload>;
+
+#[derive(Debug, Clone)]
+pub enum KafkaReplicationTimer {
+    Tick,
+}
+
+pub struct KvErrorCode;
+impl KvErrorCode {
+    pub const CAS_ERROR: ErrorCode = ErrorCode::PreconditionFailed;
+    pub const KEY_NOT_FOUND: ErrorCode = ErrorCode::KeyDoesNotExist;
+}
+
+/// Maelstrom error codes. Codes without a predefined meaning are kept as `Custom`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorCode {
+    Timeout,
+    NodeNotFound,
+    NotSupported,
+    TemporarilyUnavailable,
+    MalformedRequest,
+    Crash,
+    Abort,
+    KeyDoesNotExist,
+    KeyAlreadyExists,
+    PreconditionFailed,
+    TxnConflict,
+    Custom(usize),
+}
 
-impl Serialize for KafkaLogOrKvPayload {
+impl ErrorCode {
+    pub fn code(&self) -> usize {
+        match self {
+            ErrorCode::Timeout => 0,
+            ErrorCode::NodeNotFound => 1,
+            ErrorCode::NotSupported => 10,
+            ErrorCode::TemporarilyUnavailable => 11,
+            ErrorCode::MalformedRequest => 12,
+            ErrorCode::Crash => 13,
+            ErrorCode::Abort => 14,
+            ErrorCode::KeyDoesNotExist => 20,
+            ErrorCode::KeyAlreadyExists => 21,
+            ErrorCode::PreconditionFailed => 22,
+            ErrorCode::TxnConflict => 30,
+            ErrorCode::Custom(code) => *code,
+        }
+    }
+
+    /// Whether the error guarantees the request had no effect.
+    /// Timeouts and crashes are indefinite: the operation may or may not have happened.
+    /// Custom codes are treated as indefinite.
+    pub fn is_definite(&self) -> bool {
+        !matches!(
+            self,
+            ErrorCode::Timeout | ErrorCode::Crash | ErrorCode::Custom(_)
+        )
+    }
+}
+
+impl From<usize> for ErrorCode {
+    fn from(code: usize) -> Self {
+        match code {
+            0 => ErrorCode::Timeout,
+            1 => ErrorCode::NodeNotFound,
+            10 => ErrorCode::NotSupported,
+            11 => ErrorCode::TemporarilyUnavailable,
+            12 => ErrorCode::MalformedRequest,
+            13 => ErrorCode::Crash,
+            14 => ErrorCode::Abort,
+            20 => ErrorCode::KeyDoesNotExist,
+            21 => ErrorCode::KeyAlreadyExists,
+            22 => ErrorCode::PreconditionFailed,
+            30 => ErrorCode::TxnConflict,
+            code => ErrorCode::Custom(code),
+        }
+    }
+}
+
+impl Serialize for ErrorCode {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: Serializer,
     {
-        match self {
-            KafkaLogOrKvPayload::KafkaLog(kafka_log_payload) => {
-                kafka_log_payload.serialize(serializer)
-            }
-            KafkaLogOrKvPayload::Kv(kv_payload) => kv_payload.serialize(serializer),
-        }
+        serializer.serialize_u64(self.code() as u64)
     }
 }
 
-impl<'de> Deserialize<'de> for Message<KafkaLogOrKvPayload> {
+impl<'de> Deserialize<'de> for ErrorCode {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
         D: Deserializer<'de>,
     {
-        let mut json = serde_json::Value::deserialize(deserializer)?;
-
-        let src = json
-            .get("src")
-            .and_then(|s| s.as_str())
-            .ok_or_else(|| Error::missing_field("src"))?
-            .to_string();
-
-        let dest = json
-            .get("dest")
-            .and_then(|s| s.as_str())
-            .ok_or_else(|| Error::missing_field("dest"))?
-            .to_string();
-
-        let body_val = json
-            .get_mut("body")
-            .ok_or_else(|| Error::missing_field("body"))?
-            .take();
-
-        if src == "seq-kv" || src == "lin-kv" {
-            let body = serde_json::from_value::<Body<KvPayload>>(body_val)
-                .map_err(|_| Error::custom("failed to deserialize body kv"))?;
-
-            Ok(Message {
-                src,
-                dst: dest,
-                body: Body {
-                    msg_id: body.msg_id,
-                    in_reply_to: body.in_reply_to,
-                    payload: KafkaLogOrKvPayload::Kv(body.payload),
-                },
-            })
-        } else {
-            let body = serde_json::from_value::<Body<KafkaLogPayload>>(body_val)
-                .map_err(|_| Error::custom("failed to deserialize body seq kv"))?;
-
-            Ok(Message {
-                src,
-                dst: dest,
-                body: Body {
-                    msg_id: body.msg_id,
-                    in_reply_to: body.in_reply_to,
-                    payload: KafkaLogOrKvPayload::KafkaLog(body.payload),
-                },
-            })
-        }
+        Ok(ErrorCode::from(usize::deserialize(deserializer)?))
     }
 }
 
-pub struct KvErrorCode;
-impl KvErrorCode {
-    pub const CAS_ERROR: usize = 22;
-    pub const KEY_NOT_FOUND: usize = 20;
+/// `{"type": "error", "code": ..., "text": ...}`, the reply to a request that failed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename = "error")]
+pub struct ErrorPayload {
+    pub code: ErrorCode,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+}
+
+impl ErrorPayload {
+    pub fn new(code: ErrorCode, text: impl Into<String>) -> Self {
+        Self {
+            code,
+            text: Some(text.into()),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -330,15 +519,11 @@ pub enum TxnOperation {
 impl Serialize for TxnOperation {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
-        S: Serializer
+        S: Serializer,
     {
         match self {
-            TxnOperation::Read { key, value } => {
-                ("r", key, value).serialize(serializer)
-            }
-            TxnOperation::Write { key, value } => {
-                ("w", key, value).serialize(serializer)
-            }
+            TxnOperation::Read { key, value } => ("r", key, value).serialize(serializer),
+            TxnOperation::Write { key, value } => ("w", key, value).serialize(serializer),
         }
     }
 }
@@ -346,23 +531,183 @@ impl Serialize for TxnOperation {
 impl<'de> Deserialize<'de> for TxnOperation {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
-        D: Deserializer<'de>
+        D: Deserializer<'de>,
     {
         let json = serde_json::Value::deserialize(deserializer)?;
-        let array = json.as_array().expect("operation is required to be an array");
-        let operation_type = array[0].as_str().expect("operation type is required");
+        let array = json
+            .as_array()
+            .ok_or_else(|| Error::custom("operation is required to be an array"))?;
+        let operation_type = array
+            .first()
+            .and_then(|ty| ty.as_str())
+            .ok_or_else(|| Error::custom("operation type is required"))?;
+        let key = array
+            .get(1)
+            .and_then(|key| key.as_u64())
+            .ok_or_else(|| Error::custom("key is required"))? as usize;
+        let value = array
+            .get(2)
+            .and_then(|value| value.as_u64())
+            .map(|v| v as usize);
         match operation_type {
-            "r" => {
-                let key = array[1].as_u64().expect("key is required") as usize;
-                let value = array[2].as_u64().map(|v| v as usize);
-                Ok(TxnOperation::Read { key, value })
-            }
+            "r" => Ok(TxnOperation::Read { key, value }),
             "w" => {
-                let key = array[1].as_u64().expect("key is required") as usize;
-                let value = array[2].as_u64().map(|v| v as usize).expect("value is required");
+                let value = value.ok_or_else(|| Error::custom("value is required"))?;
                 Ok(TxnOperation::Write { key, value })
             }
             _ => Err(Error::custom("invalid operation type")),
         }
     }
-}
\ No newline at end of file
+}
+
+/// Payload of the messages a Maelstrom service sends.
+pub trait ServicePayload {
+    fn is_sent_by(src: &str) -> bool;
+}
+
+/// Payload of a node talking to a service: bodies sent by the service decode as `S`,
+/// error replies from anyone else as `Error`, every other body as `P`.
+#[derive(Debug, Clone)]
+pub enum OrService<P, S> {
+    Node(P),
+    Service(S),
+    Error(ErrorPayload),
+}
+
+impl<P, S> Serialize for OrService<P, S>
+where
+    P: Serialize,
+    S: Serialize,
+{
+    fn serialize<Ser>(&self, serializer: Ser) -> Result<Ser::Ok, Ser::Error>
+    where
+        Ser: Serializer,
+    {
+        match self {
+            OrService::Node(payload) => payload.serialize(serializer),
+            OrService::Service(payload) => payload.serialize(serializer),
+            OrService::Error(payload) => payload.serialize(serializer),
+        }
+    }
+}
+
+impl<P, S> FromBody for OrService<P, S>
+where
+    P: FromBody,
+    S: FromBody + ServicePayload,
+{
+    fn from_body(src: &str, body: serde_json::Value) -> serde_json::Result<Body<Self>> {
+        if S::is_sent_by(src) {
+            return Ok(S::from_body(src, body)?.map(OrService::Service));
+        }
+        if body["type"] == "error" {
+            return Ok(ErrorPayload::from_body(src, body)?.map(OrService::Error));
+        }
+        Ok(P::from_body(src, body)?.map(OrService::Node))
+    }
+
+    fn knows_type(src: &str, ty: &str) -> bool {
+        if S::is_sent_by(src) {
+            return S::knows_type(src, ty);
+        }
+        ty == "error" || P::knows_type(src, ty)
+    }
+}
+
+/// Payload of one of two types, told apart by `type`: bodies whose type `L` knows
+/// decode as `L`, the others as `R`.
+#[derive(Debug, Clone)]
+pub enum Either<L, R> {
+    Left(L),
+    Right(R),
+}
+
+impl<L, R> Serialize for Either<L, R>
+where
+    L: Serialize,
+    R: Serialize,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match self {
+            Either::Left(payload) => payload.serialize(serializer),
+            Either::Right(payload) => payload.serialize(serializer),
+        }
+    }
+}
+
+impl<L, R> FromBody for Either<L, R>
+where
+    L: FromBody,
+    R: FromBody,
+{
+    fn from_body(src: &str, body: serde_json::Value) -> serde_json::Result<Body<Self>> {
+        let ty = body["type"].as_str().unwrap_or_default();
+        if L::knows_type(src, ty) || !R::knows_type(src, ty) {
+            return Ok(L::from_body(src, body)?.map(Either::Left));
+        }
+        Ok(R::from_body(src, body)?.map(Either::Right))
+    }
+
+    fn knows_type(src: &str, ty: &str) -> bool {
+        L::knows_type(src, ty) || R::knows_type(src, ty)
+    }
+}
+
+impl<L, R> ServicePayload for Either<L, R>
+where
+    L: ServicePayload,
+    R: ServicePayload,
+{
+    fn is_sent_by(src: &str) -> bool {
+        L::is_sent_by(src) || R::is_sent_by(src)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn gossip_sets_are_encoded_as_elements_or_runs() {
+        let set = IntervalSet::from_iter([0, 1, 2, 5]);
+
+        let elements = GossipSet::encode(set.clone(), false);
+        assert_eq!(
+            serde_json::to_value(&elements).unwrap(),
+            json!({"seen": [0, 1, 2, 5]})
+        );
+        let runs = GossipSet::encode(set.clone(), true);
+        assert_eq!(
+            serde_json::to_value(&runs).unwrap(),
+            json!({"runs": [[0, 2], [5, 5]]})
+        );
+
+        for json in [
+            json!({"seen": [5, 0, 1, 2]}),
+            json!({"runs": [[0, 2], [5, 5]]}),
+        ] {
+            let gossip = serde_json::from_value::<GossipSet>(json).unwrap();
+            assert_eq!(gossip.into_set(), set);
+        }
+    }
+
+    #[test]
+    fn kafka_kv_values_decode_by_shape() {
+        for (json, value) in [
+            (json!(3), KafkaKvValue::Offset(3)),
+            (json!([]), KafkaKvValue::Segment(vec![])),
+            (json!([[0, 7]]), KafkaKvValue::Segment(vec![(0, 7)])),
+            (
+                json!({"groups": []}),
+                KafkaKvValue::Groups { groups: vec![] },
+            ),
+        ] {
+            assert_eq!(serde_json::to_value(&value).unwrap(), json);
+            assert_eq!(serde_json::from_value::<KafkaKvValue>(json).unwrap(), value);
+        }
+    }
+}
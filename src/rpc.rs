@@ -0,0 +1,624 @@
+use crate::stdout_json::StdoutJson;
+use crate::{Body, Message};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt::{Debug, Display, Formatter};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RpcOptions {
+    /// How long to wait for a reply to each attempt.
+    pub timeout: Duration,
+    /// How many times a request is sent again after a timeout.
+    pub retries: usize,
+    /// Wait before the first retry, doubled at every following one.
+    pub backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl RpcOptions {
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            timeout,
+            retries: 0,
+            backoff: Duration::ZERO,
+            max_backoff: Duration::ZERO,
+        }
+    }
+
+    pub fn with_retries(
+        mut self,
+        retries: usize,
+        backoff: Duration,
+        max_backoff: Duration,
+    ) -> Self {
+        self.retries = retries;
+        self.backoff = backoff;
+        self.max_backoff = max_backoff.max(backoff);
+        self
+    }
+
+    fn backoff(&self, attempt: usize) -> Duration {
+        let factor = 1u32.checked_shl(attempt as u32).unwrap_or(u32::MAX);
+        self.backoff.saturating_mul(factor).min(self.max_backoff)
+    }
+}
+
+impl Default for RpcOptions {
+    fn default() -> Self {
+        Self::new(Duration::from_millis(1000)).with_retries(
+            3,
+            Duration::from_millis(100),
+            Duration::from_millis(1000),
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RpcError {
+    /// No reply arrived within the timeout of the last attempt.
+    Timeout,
+    Cancelled,
+    /// The node output was closed before the request could be sent.
+    Closed,
+}
+
+impl Display for RpcError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RpcError::Timeout => write!(f, "rpc timed out"),
+            RpcError::Cancelled => write!(f, "rpc cancelled"),
+            RpcError::Closed => write!(f, "rpc output closed"),
+        }
+    }
+}
+
+impl std::error::Error for RpcError {}
+
+/// Request/response client for async nodes.
+///
+/// Replies must be handed to [`Rpc::handle_reply`] by the node when they arrive,
+/// so calls are awaited from a spawned task rather than from `AsyncNode::step`.
+/// A retry is sent under a new msg_id, and a reply to any attempt completes the call.
+/// Dropping a call future cancels it.
+#[derive(Debug)]
+pub struct Rpc<P>
+where
+    P: Debug,
+{
+    inner: Arc<RpcInner<P>>,
+}
+
+#[derive(Debug)]
+struct RpcInner<P>
+where
+    P: Debug,
+{
+    node_id: String,
+    free_msg_id: AtomicUsize,
+    options: RpcOptions,
+    output: tokio::sync::mpsc::UnboundedSender<Message<P>>,
+    pending: Mutex<PendingCalls<P>>,
+}
+
+/// Calls waiting for a reply, by the msg_id of their first attempt.
+#[derive(Debug)]
+struct PendingCalls<P>
+where
+    P: Debug,
+{
+    calls: HashMap<usize, tokio::sync::oneshot::Sender<Message<P>>>,
+    /// Call each attempt was sent for.
+    call_by_msg_id: HashMap<usize, usize>,
+}
+
+impl<P> PendingCalls<P>
+where
+    P: Debug,
+{
+    /// Removes `call` and the msg_ids of its attempts, returning its reply sender.
+    fn remove(&mut self, call: usize) -> Option<tokio::sync::oneshot::Sender<Message<P>>> {
+        self.call_by_msg_id
+            .retain(|_, attempt_call| *attempt_call != call);
+        self.calls.remove(&call)
+    }
+}
+
+impl<P> Clone for Rpc<P>
+where
+    P: Debug,
+{
+    fn clone(&self) -> Self {
+        Self {
+            inner: Arc::clone(&self.inner),
+        }
+    }
+}
+
+impl<P> Rpc<P>
+where
+    P: Debug + Clone,
+{
+    pub fn new(
+        node_id: String,
+        options: RpcOptions,
+        output: tokio::sync::mpsc::UnboundedSender<Message<P>>,
+    ) -> Self {
+        Self {
+            inner: Arc::new(RpcInner {
+                node_id,
+                free_msg_id: AtomicUsize::new(0),
+                options,
+                output,
+                pending: Mutex::new(PendingCalls {
+                    calls: HashMap::new(),
+                    call_by_msg_id: HashMap::new(),
+                }),
+            }),
+        }
+    }
+
+    /// Allocates a msg_id that will not collide with the ones used by calls.
+    pub fn next_msg_id(&self) -> usize {
+        self.inner.free_msg_id.fetch_add(1, Ordering::Relaxed)
+    }
+
+    pub async fn call(&self, dest: &str, payload: P) -> Result<Message<P>, RpcError> {
+        self.call_with(dest, payload, self.inner.options).await
+    }
+
+    pub async fn call_with(
+        &self,
+        dest: &str,
+        payload: P,
+        options: RpcOptions,
+    ) -> Result<Message<P>, RpcError> {
+        let call = self.next_msg_id();
+        let (reply_tx, mut reply_rx) = tokio::sync::oneshot::channel();
+        self.pending().calls.insert(call, reply_tx);
+        let _pending = PendingCall { rpc: self, call };
+
+        for attempt in 0..=options.retries {
+            let msg_id = if attempt == 0 {
+                call
+            } else {
+                // A late reply to an earlier attempt may still arrive during the backoff.
+                let backoff = options.backoff(attempt - 1);
+                if let Ok(reply) = tokio::time::timeout(backoff, &mut reply_rx).await {
+                    return reply.map_err(|_| RpcError::Cancelled);
+                }
+                self.next_msg_id()
+            };
+            self.pending().call_by_msg_id.insert(msg_id, call);
+            self.inner
+                .output
+                .send(Message {
+                    src: self.inner.node_id.clone(),
+                    dst: dest.to_string(),
+                    body: Body {
+                        msg_id: Some(msg_id),
+                        in_reply_to: None,
+                        payload: payload.clone(),
+                    },
+                })
+                .map_err(|_| RpcError::Closed)?;
+
+            match tokio::time::timeout(options.timeout, &mut reply_rx).await {
+                Ok(Ok(reply)) => return Ok(reply),
+                Ok(Err(_)) => return Err(RpcError::Cancelled),
+                Err(_) => {}
+            }
+        }
+        Err(RpcError::Timeout)
+    }
+
+    /// Completes the call `msg` replies to. Returns `msg` back if it is not a reply
+    /// to a pending call.
+    pub fn handle_reply(&self, msg: Message<P>) -> Option<Message<P>> {
+        let Some(in_reply_to) = msg.body.in_reply_to else {
+            return Some(msg);
+        };
+        let mut pending = self.pending();
+        let Some(call) = pending.call_by_msg_id.get(&in_reply_to).copied() else {
+            return Some(msg);
+        };
+        let Some(reply_tx) = pending.remove(call) else {
+            return Some(msg);
+        };
+        drop(pending);
+        let _ = reply_tx.send(msg);
+        None
+    }
+
+    /// Fails every pending call with [`RpcError::Cancelled`].
+    pub fn cancel_all(&self) {
+        let mut pending = self.pending();
+        pending.calls.clear();
+        pending.call_by_msg_id.clear();
+    }
+
+    pub fn pending_calls(&self) -> usize {
+        self.pending().calls.len()
+    }
+
+    fn pending(&self) -> std::sync::MutexGuard<'_, PendingCalls<P>> {
+        self.inner
+            .pending
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+struct PendingCall<'a, P>
+where
+    P: Debug + Clone,
+{
+    rpc: &'a Rpc<P>,
+    call: usize,
+}
+
+impl<P> Drop for PendingCall<'_, P>
+where
+    P: Debug + Clone,
+{
+    fn drop(&mut self) {
+        self.rpc.pending().remove(self.call);
+    }
+}
+
+pub type RpcCallback<N, P> =
+    Box<dyn FnOnce(&mut N, Result<Message<P>, RpcError>, &mut StdoutJson) -> anyhow::Result<()>>;
+
+/// Request/response client for sync nodes: the reply is handed to a callback
+/// together with the node itself.
+///
+/// The node passes incoming messages to [`RpcCallbacks::take_callback`] and
+/// calls [`RpcCallbacks::expire`] periodically to retry or time out calls.
+/// A retry is sent under a new msg_id, and a reply to any attempt completes the call.
+/// Deadlines are measured on the clock the node passes in as `now`, usually
+/// [`Timers::now`](crate::timer::Timers::now), so they follow the virtual clock in a simulation.
+pub struct RpcCallbacks<N, P>
+where
+    P: Debug,
+{
+    node_id: String,
+    options: RpcOptions,
+    /// Calls waiting for a reply, by the msg_id of their first attempt.
+    pending: HashMap<usize, PendingCallback<N, P>>,
+    /// Call each attempt was sent for.
+    call_by_msg_id: HashMap<usize, usize>,
+}
+
+struct PendingCallback<N, P>
+where
+    P: Debug,
+{
+    dest: String,
+    payload: P,
+    /// msg_ids of the attempts sent so far, the first one identifying the call.
+    msg_ids: Vec<usize>,
+    attempt: usize,
+    deadline: Duration,
+    awaiting_retry: bool,
+    callback: RpcCallback<N, P>,
+}
+
+impl<N, P> RpcCallbacks<N, P>
+where
+    P: Debug + Clone + Serialize,
+{
+    pub fn new(node_id: String, options: RpcOptions) -> Self {
+        Self {
+            node_id,
+            options,
+            pending: HashMap::new(),
+            call_by_msg_id: HashMap::new(),
+        }
+    }
+
+    /// Sends `payload` to `dest` at time `now` and registers `callback` for its reply.
+    /// Returns the msg_id of the request.
+    pub fn call<F>(
+        &mut self,
+        msg_id: &mut usize,
+        dest: &str,
+        payload: P,
+        now: Duration,
+        output: &mut StdoutJson,
+        callback: F,
+    ) -> anyhow::Result<usize>
+    where
+        F: FnOnce(&mut N, Result<Message<P>, RpcError>, &mut StdoutJson) -> anyhow::Result<()>
+            + 'static,
+    {
+        let pending = PendingCallback {
+            dest: dest.to_string(),
+            payload,
+            msg_ids: Vec::new(),
+            attempt: 0,
+            deadline: now + self.options.timeout,
+            awaiting_retry: false,
+            callback: Box::new(callback),
+        };
+        self.send(msg_id, pending, output)
+    }
+
+    /// Removes and returns the callback waiting for `msg`, if `msg` is a reply to any attempt
+    /// of a pending call.
+    pub fn take_callback(&mut self, msg: &Message<P>) -> Option<RpcCallback<N, P>> {
+        self.remove(msg.body.in_reply_to?)
+            .map(|pending| pending.callback)
+    }
+
+    /// Removes the call an attempt of which was sent as `msg_id` and returns its callback,
+    /// to be invoked with [`RpcError::Cancelled`].
+    pub fn cancel(&mut self, msg_id: usize) -> Option<RpcCallback<N, P>> {
+        self.remove(msg_id).map(|pending| pending.callback)
+    }
+
+    fn remove(&mut self, msg_id: usize) -> Option<PendingCallback<N, P>> {
+        let call = self.call_by_msg_id.get(&msg_id)?;
+        let pending = self.pending.remove(call)?;
+        for msg_id in &pending.msg_ids {
+            self.call_by_msg_id.remove(msg_id);
+        }
+        Some(pending)
+    }
+
+    /// Retries calls whose attempt timed out by `now` and returns the callbacks of calls
+    /// out of retries, to be invoked with [`RpcError::Timeout`].
+    pub fn expire(
+        &mut self,
+        msg_id: &mut usize,
+        now: Duration,
+        output: &mut StdoutJson,
+    ) -> anyhow::Result<Vec<RpcCallback<N, P>>> {
+        let expired_ids = self
+            .pending
+            .iter()
+            .filter(|(_, pending)| pending.deadline <= now)
+            .map(|(id, _)| *id)
+            .collect::<Vec<_>>();
+
+        let mut timed_out = Vec::new();
+        for id in expired_ids {
+            let mut pending = self.pending.remove(&id).expect("expired call is pending");
+            if pending.awaiting_retry {
+                pending.awaiting_retry = false;
+                pending.deadline = now + self.options.timeout;
+                self.send(msg_id, pending, output)?;
+            } else if pending.attempt < self.options.retries {
+                pending.awaiting_retry = true;
+                pending.deadline = now + self.options.backoff(pending.attempt);
+                pending.attempt += 1;
+                self.pending.insert(id, pending);
+            } else {
+                for msg_id in &pending.msg_ids {
+                    self.call_by_msg_id.remove(msg_id);
+                }
+                timed_out.push(pending.callback);
+            }
+        }
+        Ok(timed_out)
+    }
+
+    pub fn pending_calls(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Sends a new attempt of `pending`, keeping the msg_ids of the earlier ones.
+    fn send(
+        &mut self,
+        msg_id: &mut usize,
+        mut pending: PendingCallback<N, P>,
+        output: &mut StdoutJson,
+    ) -> anyhow::Result<usize> {
+        let id = *msg_id;
+        *msg_id += 1;
+        output.write(&Message {
+            src: self.node_id.clone(),
+            dst: pending.dest.clone(),
+            body: Body {
+                msg_id: Some(id),
+                in_reply_to: None,
+                payload: pending.payload.clone(),
+            },
+        })?;
+        let call = pending.msg_ids.first().copied().unwrap_or(id);
+        pending.msg_ids.push(id);
+        self.call_by_msg_id.insert(id, call);
+        self.pending.insert(call, pending);
+        Ok(id)
+    }
+}
+
+impl<N, P> Debug for RpcCallbacks<N, P>
+where
+    P: Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RpcCallbacks")
+            .field("node_id", &self.node_id)
+            .field("options", &self.options)
+            .field("pending", &self.pending.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{Value, json};
+
+    /// Outcomes of the calls, by the msg_id replied to.
+    type Outcomes = Vec<Result<Option<usize>, RpcError>>;
+
+    fn options(retries: usize) -> RpcOptions {
+        RpcOptions::new(Duration::from_millis(100)).with_retries(
+            retries,
+            Duration::from_millis(50),
+            Duration::from_millis(50),
+        )
+    }
+
+    fn call(
+        rpc: &mut RpcCallbacks<Outcomes, Value>,
+        msg_id: &mut usize,
+        output: &mut StdoutJson,
+    ) -> usize {
+        rpc.call(
+            msg_id,
+            "n1",
+            json!({"type": "read"}),
+            Duration::ZERO,
+            output,
+            |outcomes: &mut Outcomes, reply, _| {
+                outcomes.push(reply.map(|reply| reply.body.in_reply_to));
+                Ok(())
+            },
+        )
+        .unwrap()
+    }
+
+    fn reply_to(msg_id: usize) -> Message<Value> {
+        Message {
+            src: "n1".to_string(),
+            dst: "n0".to_string(),
+            body: Body {
+                msg_id: None,
+                in_reply_to: Some(msg_id),
+                payload: json!({"type": "read_ok"}),
+            },
+        }
+    }
+
+    fn millis(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn retries_after_the_backoff_under_a_new_msg_id() {
+        let mut rpc = RpcCallbacks::new("n0".to_string(), options(1));
+        let mut output = StdoutJson::buffered();
+        let mut msg_id = 0;
+        let first = call(&mut rpc, &mut msg_id, &mut output);
+        assert_eq!(output.take_lines().len(), 1);
+
+        assert!(
+            rpc.expire(&mut msg_id, millis(99), &mut output)
+                .unwrap()
+                .is_empty()
+        );
+        assert!(
+            rpc.expire(&mut msg_id, millis(100), &mut output)
+                .unwrap()
+                .is_empty()
+        );
+        assert!(output.take_lines().is_empty());
+        assert!(
+            rpc.expire(&mut msg_id, millis(150), &mut output)
+                .unwrap()
+                .is_empty()
+        );
+        let retry = output.take_lines();
+        assert_eq!(retry.len(), 1);
+        assert!(retry[0].contains(r#""msg_id":1"#), "{}", retry[0]);
+
+        let callback = rpc.take_callback(&reply_to(1)).unwrap();
+        let mut outcomes = Outcomes::new();
+        callback(&mut outcomes, Ok(reply_to(1)), &mut output).unwrap();
+        assert_eq!(outcomes, vec![Ok(Some(1))]);
+        assert_eq!(rpc.pending_calls(), 0);
+        assert!(rpc.take_callback(&reply_to(first)).is_none());
+    }
+
+    #[test]
+    fn a_late_reply_to_an_earlier_attempt_completes_the_call() {
+        let mut rpc = RpcCallbacks::new("n0".to_string(), options(2));
+        let mut output = StdoutJson::buffered();
+        let mut msg_id = 0;
+        let first = call(&mut rpc, &mut msg_id, &mut output);
+        rpc.expire(&mut msg_id, millis(100), &mut output).unwrap();
+        rpc.expire(&mut msg_id, millis(150), &mut output).unwrap();
+        assert_eq!(output.take_lines().len(), 2);
+
+        let callback = rpc.take_callback(&reply_to(first)).unwrap();
+        assert_eq!(rpc.pending_calls(), 0);
+        assert!(rpc.take_callback(&reply_to(1)).is_none());
+        assert!(
+            rpc.expire(&mut msg_id, millis(1000), &mut output)
+                .unwrap()
+                .is_empty()
+        );
+        assert!(output.take_lines().is_empty());
+
+        let mut outcomes = Outcomes::new();
+        callback(&mut outcomes, Ok(reply_to(first)), &mut output).unwrap();
+        assert_eq!(outcomes, vec![Ok(Some(first))]);
+    }
+
+    #[test]
+    fn times_out_once_out_of_retries() {
+        let mut rpc = RpcCallbacks::new("n0".to_string(), options(0));
+        let mut output = StdoutJson::buffered();
+        let mut msg_id = 0;
+        let sent = call(&mut rpc, &mut msg_id, &mut output);
+
+        let timed_out = rpc.expire(&mut msg_id, millis(100), &mut output).unwrap();
+        assert_eq!(timed_out.len(), 1);
+        assert_eq!(rpc.pending_calls(), 0);
+        assert!(rpc.take_callback(&reply_to(sent)).is_none());
+
+        let mut outcomes = Outcomes::new();
+        for callback in timed_out {
+            callback(&mut outcomes, Err(RpcError::Timeout), &mut output).unwrap();
+        }
+        assert_eq!(outcomes, vec![Err(RpcError::Timeout)]);
+    }
+
+    #[test]
+    fn cancel_removes_the_call() {
+        let mut rpc = RpcCallbacks::new("n0".to_string(), options(1));
+        let mut output = StdoutJson::buffered();
+        let mut msg_id = 0;
+        let cancelled = call(&mut rpc, &mut msg_id, &mut output);
+        let kept = call(&mut rpc, &mut msg_id, &mut output);
+
+        let callback = rpc.cancel(cancelled).unwrap();
+        assert!(rpc.cancel(cancelled).is_none());
+        assert!(rpc.take_callback(&reply_to(cancelled)).is_none());
+        assert_eq!(rpc.pending_calls(), 1);
+        assert!(rpc.take_callback(&reply_to(kept)).is_some());
+
+        let mut outcomes = Outcomes::new();
+        callback(&mut outcomes, Err(RpcError::Cancelled), &mut output).unwrap();
+        assert_eq!(outcomes, vec![Err(RpcError::Cancelled)]);
+    }
+
+    #[tokio::test]
+    async fn async_call_accepts_a_late_reply_to_an_earlier_attempt() {
+        let (output_tx, mut output_rx) = tokio::sync::mpsc::unbounded_channel();
+        let options = RpcOptions::new(Duration::from_millis(200)).with_retries(
+            1,
+            Duration::ZERO,
+            Duration::ZERO,
+        );
+        let rpc = Rpc::<Value>::new("n0".to_string(), options, output_tx);
+        let call = tokio::spawn({
+            let rpc = rpc.clone();
+            async move { rpc.call("n1", json!({"type": "read"})).await }
+        });
+
+        let first = output_rx.recv().await.unwrap().body.msg_id.unwrap();
+        let retry = output_rx.recv().await.unwrap().body.msg_id.unwrap();
+        assert_ne!(first, retry);
+        assert!(rpc.handle_reply(reply_to(first)).is_none());
+
+        let reply = call.await.unwrap().unwrap();
+        assert_eq!(reply.body.in_reply_to, Some(first));
+        assert_eq!(rpc.pending_calls(), 0);
+        assert!(rpc.handle_reply(reply_to(retry)).is_some());
+    }
+}
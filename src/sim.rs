@@ -0,0 +1,610 @@
+pub mod kv;
+pub mod nemesis;
+
+use crate::checker::History;
+use crate::mloop::{Decoded, decode};
+use crate::node::{AsyncNode, Node, init_ok_msg};
+use crate::payloads::{ErrorPayload, Event, InitPayload};
+use crate::rng::SimRng;
+use crate::sim::nemesis::Nemesis;
+use crate::stdout_json::StdoutJson;
+use crate::timer::Timers;
+use crate::{Body, FromBody, Message};
+use anyhow::Context;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use std::any::Any;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BinaryHeap, HashMap};
+use std::fmt::Debug;
+use std::marker::PhantomData;
+use std::time::Duration;
+
+/// Runs N copies of a [`Node`] in a single process.
+///
+/// Messages written by nodes are routed through a queue ordered by a virtual clock,
+/// with per-message latency drawn from a seeded generator, so a run is fully
+/// determined by its seed. Messages addressed to anything that is not a node
+/// (Maelstrom clients) are collected and can be inspected with
+/// [`Simulation::client_messages`].
+///
+/// The [`Timers`] nodes register fire on virtual time. More injected events can be
+/// scheduled with [`Simulation::every`] and [`Simulation::inject`].
+///
+/// Maelstrom services such as `seq-kv` are registered with [`Simulation::add_service`].
+/// Partitions, message loss, duplication and reordering between nodes are injected
+/// by a [`Nemesis`].
+pub struct Simulation<N, P, IP = ()>
+where
+    N: Node<P, IP>,
+    P: Debug,
+    IP: Debug + Clone,
+{
+    now: Duration,
+    seq: u64,
+    rng: SimRng,
+    latency: (Duration, Duration),
+    nemesis: Nemesis,
+    nodes: BTreeMap<String, N>,
+    timers: BTreeMap<String, Timers<IP>>,
+    services: BTreeMap<String, Box<dyn JsonService>>,
+    output: StdoutJson,
+    queue: BinaryHeap<Scheduled<IP>>,
+    periodic: Vec<(Duration, IP)>,
+    client_msg_id: usize,
+    client_msgs: Vec<serde_json::Value>,
+    client_log: Vec<(Duration, serde_json::Value)>,
+    _payload: PhantomData<P>,
+}
+
+impl<N, P, IP> Simulation<N, P, IP>
+where
+    N: Node<P, IP>,
+    P: Debug,
+    IP: Debug + Clone,
+    P: FromBody,
+{
+    const INIT_SRC: &'static str = "c0";
+
+    /// Initializes `node_count` nodes named `n0`, `n1`, ... as Maelstrom does.
+    pub fn new(node_count: usize, seed: u64) -> anyhow::Result<Self> {
+        let node_ids = (0..node_count)
+            .map(|i| format!("n{}", i))
+            .collect::<Vec<_>>();
+
+        let mut rng = SimRng::new(seed);
+        let mut output = StdoutJson::buffered();
+        let mut nodes = BTreeMap::new();
+        let mut timers = BTreeMap::new();
+        for (i, node_id) in node_ids.iter().enumerate() {
+            let init_msg = Message {
+                src: Self::INIT_SRC.to_string(),
+                dst: node_id.clone(),
+                body: Body {
+                    msg_id: Some(i),
+                    in_reply_to: None,
+                    payload: InitPayload {
+                        node_id: node_id.clone(),
+                        node_ids: node_ids.clone(),
+                    },
+                },
+            };
+            let node_timers = Timers::new(rng.next_u64());
+            let node = N::init(init_msg, &mut output, &node_timers)
+                .with_context(|| format!("failed to init node {}", node_id))?;
+            nodes.insert(node_id.clone(), node);
+            timers.insert(node_id.clone(), node_timers);
+        }
+        output.take_lines();
+
+        Ok(Self {
+            now: Duration::ZERO,
+            seq: 0,
+            rng,
+            latency: (Duration::ZERO, Duration::ZERO),
+            nemesis: Nemesis::new(),
+            nodes,
+            timers,
+            services: BTreeMap::new(),
+            output,
+            queue: BinaryHeap::new(),
+            periodic: Vec::new(),
+            client_msg_id: 0,
+            client_msgs: Vec::new(),
+            client_log: Vec::new(),
+            _payload: PhantomData,
+        })
+    }
+
+    /// Delays every message by a random duration in `[min, max]`.
+    pub fn with_latency(mut self, min: Duration, max: Duration) -> Self {
+        self.latency = (min, max.max(min));
+        self
+    }
+
+    pub fn with_nemesis(mut self, nemesis: Nemesis) -> Self {
+        self.nemesis = nemesis;
+        self
+    }
+
+    pub fn nemesis(&self) -> &Nemesis {
+        &self.nemesis
+    }
+
+    pub fn nemesis_mut(&mut self) -> &mut Nemesis {
+        &mut self.nemesis
+    }
+
+    /// Partitions the cluster into `groups` at virtual time `at`.
+    pub fn partition_at(&mut self, at: Duration, groups: Vec<Vec<String>>) {
+        self.schedule(at, SimEvent::Partition(groups));
+    }
+
+    pub fn heal_at(&mut self, at: Duration) {
+        self.schedule(at, SimEvent::Heal);
+    }
+
+    /// Every `interval` splits the cluster into two random halves and heals it `length` later.
+    pub fn partition_randomly(&mut self, interval: Duration, length: Duration) {
+        self.schedule(
+            self.now + interval,
+            SimEvent::RandomPartition { interval, length },
+        );
+    }
+
+    pub fn now(&self) -> Duration {
+        self.now
+    }
+
+    pub fn node_ids(&self) -> impl Iterator<Item = &String> {
+        self.nodes.keys()
+    }
+
+    pub fn node(&self, node_id: &str) -> Option<&N> {
+        self.nodes.get(node_id)
+    }
+
+    /// Answers every message addressed to `name` with `service`.
+    pub fn add_service<S>(&mut self, name: &str, service: S)
+    where
+        S: Service + 'static,
+        S::Payload: Serialize,
+        Message<S::Payload>: DeserializeOwned,
+    {
+        self.services.insert(name.to_string(), Box::new(service));
+    }
+
+    pub fn service<S>(&self, name: &str) -> Option<&S>
+    where
+        S: Service + 'static,
+    {
+        self.services
+            .get(name)
+            .and_then(|service| service.as_any().downcast_ref::<S>())
+    }
+
+    pub fn rng(&mut self) -> &mut SimRng {
+        &mut self.rng
+    }
+
+    /// Injects `payload` into every node each `interval` of virtual time,
+    /// on top of the timers nodes register themselves.
+    pub fn every(&mut self, interval: Duration, payload: IP) {
+        let index = self.periodic.len();
+        self.periodic.push((interval, payload));
+        self.schedule(self.now + interval, SimEvent::Periodic(index));
+    }
+
+    /// Injects `payload` into `node_id` at the current virtual time.
+    pub fn inject(&mut self, node_id: &str, payload: IP) {
+        self.schedule(
+            self.now,
+            SimEvent::Inject {
+                node_id: node_id.to_string(),
+                payload,
+            },
+        );
+    }
+
+    /// Sends a client request to `dest` and returns its `msg_id`.
+    pub fn send<T>(&mut self, client: &str, dest: &str, payload: T) -> anyhow::Result<usize>
+    where
+        T: Serialize + Debug,
+    {
+        let msg_id = self.client_msg_id;
+        self.client_msg_id += 1;
+        let msg = Message {
+            src: client.to_string(),
+            dst: dest.to_string(),
+            body: Body {
+                msg_id: Some(msg_id),
+                in_reply_to: None,
+                payload,
+            },
+        };
+        let msg = serde_json::to_value(&msg).context("client msg serialization failed")?;
+        self.client_log.push((self.now, msg.clone()));
+        self.route(msg);
+        Ok(msg_id)
+    }
+
+    /// Messages addressed to clients, in delivery order.
+    pub fn client_messages(&self) -> &[serde_json::Value] {
+        &self.client_msgs
+    }
+
+    pub fn take_client_messages(&mut self) -> Vec<serde_json::Value> {
+        std::mem::take(&mut self.client_msgs)
+    }
+
+    /// Returns the body of the reply `client` received for request `msg_id`, if any.
+    pub fn reply<T>(&self, client: &str, msg_id: usize) -> Option<Body<T>>
+    where
+        T: DeserializeOwned,
+    {
+        self.client_msgs
+            .iter()
+            .filter(|msg| msg["dest"] == client)
+            .find(|msg| msg["body"]["in_reply_to"] == msg_id)
+            .and_then(|msg| serde_json::from_value(msg["body"].clone()).ok())
+    }
+
+    /// Builds the history of every client request sent so far and of its reply.
+    /// Requests whose payload is not a `T` are skipped.
+    pub fn history<T>(&self) -> History<T>
+    where
+        T: DeserializeOwned,
+    {
+        let mut history = History::new();
+        let mut index_by_request = HashMap::new();
+        for (at, msg) in &self.client_log {
+            let (Some(src), Some(dest)) = (msg["src"].as_str(), msg["dest"].as_str()) else {
+                continue;
+            };
+
+            if let Some(in_reply_to) = msg["body"]["in_reply_to"].as_u64() {
+                let Some(index) = index_by_request.remove(&(dest.to_string(), in_reply_to)) else {
+                    continue;
+                };
+                if let Ok(error) = serde_json::from_value::<ErrorPayload>(msg["body"].clone()) {
+                    // Indefinite errors leave the operation as info: it may have taken effect.
+                    if error.code.is_definite() {
+                        history.fail(index, *at);
+                    }
+                } else if let Ok(body) = serde_json::from_value::<Body<T>>(msg["body"].clone()) {
+                    history.complete(index, *at, body.payload);
+                }
+                continue;
+            }
+
+            let (Some(msg_id), Ok(body)) = (
+                msg["body"]["msg_id"].as_u64(),
+                serde_json::from_value::<Body<T>>(msg["body"].clone()),
+            ) else {
+                continue;
+            };
+            let index = history.invoke(src, dest, *at, body.payload);
+            index_by_request.insert((src.to_string(), msg_id), index);
+        }
+        history
+    }
+
+    /// Processes the next scheduled event, advancing the clock to it.
+    /// Returns `false` if nothing is scheduled.
+    pub fn step(&mut self) -> anyhow::Result<bool> {
+        if let Some((at, node_id)) = self.next_timer()
+            && self.queue.peek().is_none_or(|next| at < next.at)
+        {
+            self.now = self.now.max(at);
+            self.fire_timer(&node_id)?;
+            return Ok(true);
+        }
+
+        let Some(scheduled) = self.queue.pop() else {
+            return Ok(false);
+        };
+        self.now = self.now.max(scheduled.at);
+
+        match scheduled.event {
+            SimEvent::Deliver(msg) => self.deliver(msg)?,
+            SimEvent::Inject { node_id, payload } => {
+                self.step_node(&node_id, Event::InjectedPayload(payload))?
+            }
+            SimEvent::Periodic(index) => {
+                let (interval, payload) = self.periodic[index].clone();
+                let node_ids = self.nodes.keys().cloned().collect::<Vec<_>>();
+                for node_id in node_ids {
+                    self.step_node(&node_id, Event::InjectedPayload(payload.clone()))?;
+                }
+                self.schedule(self.now + interval, SimEvent::Periodic(index));
+            }
+            SimEvent::Partition(groups) => self.nemesis.partition(groups),
+            SimEvent::Heal => self.nemesis.heal(),
+            SimEvent::RandomPartition { interval, length } => {
+                let node_ids = self.nodes.keys().cloned().collect::<Vec<_>>();
+                let groups = nemesis::random_halves(&node_ids, &mut self.rng);
+                self.nemesis.partition(groups);
+                self.schedule(self.now + length, SimEvent::Heal);
+                self.schedule(
+                    self.now + interval,
+                    SimEvent::RandomPartition { interval, length },
+                );
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// Processes every event scheduled within the next `duration` of virtual time.
+    pub fn run_for(&mut self, duration: Duration) -> anyhow::Result<()> {
+        let until = self.now + duration;
+        while self.next_at().is_some_and(|at| at <= until) {
+            self.step()?;
+        }
+        self.now = until;
+        Ok(())
+    }
+
+    fn deliver(&mut self, msg: serde_json::Value) -> anyhow::Result<()> {
+        let Some(dest) = msg["dest"].as_str().map(str::to_string) else {
+            return Ok(());
+        };
+        if let Some(service) = self.services.get_mut(&dest) {
+            let replies = service
+                .handle_json(msg, self.now, &mut self.rng)
+                .with_context(|| format!("service {} failed", dest))?;
+            for reply in replies {
+                self.route(reply);
+            }
+            return Ok(());
+        }
+        if !self.nodes.contains_key(&dest) {
+            self.client_log.push((self.now, msg.clone()));
+            self.client_msgs.push(msg);
+            return Ok(());
+        }
+        match decode::<P>(msg) {
+            Decoded::Message(msg) => self.step_node(&dest, Event::Message(msg)),
+            Decoded::Rejected { reply } => {
+                if let Some(reply) = reply {
+                    self.route(serde_json::to_value(reply)?);
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Time of the next event or node timer.
+    fn next_at(&self) -> Option<Duration> {
+        let next_timer = self.next_timer().map(|(at, _)| at);
+        let next_event = self.queue.peek().map(|next| next.at);
+        next_timer.into_iter().chain(next_event).min()
+    }
+
+    fn next_timer(&self) -> Option<(Duration, String)> {
+        self.timers
+            .iter()
+            .filter_map(|(node_id, timers)| Some((timers.next_deadline()?, node_id.clone())))
+            .min()
+    }
+
+    fn fire_timer(&mut self, node_id: &str) -> anyhow::Result<()> {
+        let Some(timers) = self.timers.get(node_id) else {
+            return Ok(());
+        };
+        timers.advance(self.now);
+        match timers.pop_due() {
+            Some(payload) => self.step_node(node_id, Event::InjectedPayload(payload)),
+            None => Ok(()),
+        }
+    }
+
+    fn step_node(&mut self, node_id: &str, event: Event<P, IP>) -> anyhow::Result<()> {
+        let Some(node) = self.nodes.get_mut(node_id) else {
+            return Ok(());
+        };
+        if let Some(timers) = self.timers.get(node_id) {
+            timers.advance(self.now);
+        }
+        node.step(event, &mut self.output)
+            .with_context(|| format!("node {} step function failed", node_id))?;
+
+        for line in self.output.take_lines() {
+            let msg = serde_json::from_str::<serde_json::Value>(&line)
+                .context("node wrote invalid json")?;
+            self.route(msg);
+        }
+        Ok(())
+    }
+
+    fn route(&mut self, msg: serde_json::Value) {
+        let (min, max) = self.latency;
+        let latency = min + self.rng.duration(max - min);
+
+        let (Some(src), Some(dest)) = (msg["src"].as_str(), msg["dest"].as_str()) else {
+            return;
+        };
+        if !self.nodes.contains_key(src) || !self.nodes.contains_key(dest) {
+            self.schedule(self.now + latency, SimEvent::Deliver(msg));
+            return;
+        }
+
+        let delays = self.nemesis.apply(src, dest, &mut self.rng);
+        for delay in delays {
+            self.schedule(self.now + latency + delay, SimEvent::Deliver(msg.clone()));
+        }
+    }
+
+    fn schedule(&mut self, at: Duration, event: SimEvent<IP>) {
+        self.queue.push(Scheduled {
+            at,
+            seq: self.seq,
+            event,
+        });
+        self.seq += 1;
+    }
+}
+
+/// Runs an [`AsyncNode`] as a [`Node`] on a single-threaded runtime,
+/// so that it can be driven by a [`Simulation`].
+///
+/// The node's [`Timers`] fire on the simulation's virtual clock, and events it sends
+/// through its channel are stepped right after the event that produced them.
+pub struct BlockingNode<N, P, IP = ()>
+where
+    P: Debug,
+    IP: Debug,
+{
+    node: N,
+    runtime: tokio::runtime::Runtime,
+    output_rx: tokio::sync::mpsc::UnboundedReceiver<Message<P>>,
+    events_rx: tokio::sync::mpsc::UnboundedReceiver<Event<P, IP>>,
+}
+
+impl<N, P, IP> BlockingNode<N, P, IP>
+where
+    P: Debug,
+    IP: Debug,
+{
+    pub fn inner(&self) -> &N {
+        &self.node
+    }
+}
+
+impl<N, P, IP> Node<P, IP> for BlockingNode<N, P, IP>
+where
+    N: AsyncNode<P, IP>,
+    P: Debug + Serialize,
+    IP: Debug,
+{
+    fn init(
+        init_msg: Message<InitPayload>,
+        output: &mut StdoutJson,
+        timers: &Timers<IP>,
+    ) -> anyhow::Result<Self>
+    where
+        Self: Sized,
+    {
+        output.write(&init_ok_msg(&init_msg))?;
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .context("failed to build runtime")?;
+        let (output_tx, output_rx) = tokio::sync::mpsc::unbounded_channel();
+        let (events_tx, events_rx) = tokio::sync::mpsc::unbounded_channel();
+        let node = {
+            let _guard = runtime.enter();
+            N::init(init_msg, output_tx, events_tx, timers)?
+        };
+        Ok(Self {
+            node,
+            runtime,
+            output_rx,
+            events_rx,
+        })
+    }
+
+    fn step(&mut self, event: Event<P, IP>, output: &mut StdoutJson) -> anyhow::Result<()> {
+        self.runtime.block_on(self.node.step(event))?;
+        // Events the node sends itself are handled before the simulation moves on.
+        while let Ok(event) = self.events_rx.try_recv() {
+            self.runtime.block_on(self.node.step(event))?;
+        }
+        while let Ok(msg) = self.output_rx.try_recv() {
+            output.write(&msg)?;
+        }
+        Ok(())
+    }
+}
+
+/// An in-process stand-in for a Maelstrom service.
+pub trait Service {
+    type Payload: Debug;
+
+    fn handle(
+        &mut self,
+        msg: Message<Self::Payload>,
+        now: Duration,
+        rng: &mut SimRng,
+    ) -> Vec<Message<Self::Payload>>;
+}
+
+trait JsonService {
+    fn handle_json(
+        &mut self,
+        msg: serde_json::Value,
+        now: Duration,
+        rng: &mut SimRng,
+    ) -> anyhow::Result<Vec<serde_json::Value>>;
+
+    fn as_any(&self) -> &dyn Any;
+}
+
+impl<S> JsonService for S
+where
+    S: Service + 'static,
+    S::Payload: Serialize,
+    Message<S::Payload>: DeserializeOwned,
+{
+    fn handle_json(
+        &mut self,
+        msg: serde_json::Value,
+        now: Duration,
+        rng: &mut SimRng,
+    ) -> anyhow::Result<Vec<serde_json::Value>> {
+        let msg = serde_json::from_value::<Message<S::Payload>>(msg)
+            .context("service msg deserialization failed")?;
+        self.handle(msg, now, rng)
+            .iter()
+            .map(|reply| serde_json::to_value(reply).context("service reply serialization failed"))
+            .collect()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+#[derive(Debug)]
+enum SimEvent<IP> {
+    Deliver(serde_json::Value),
+    Inject {
+        node_id: String,
+        payload: IP,
+    },
+    Periodic(usize),
+    Partition(Vec<Vec<String>>),
+    Heal,
+    RandomPartition {
+        interval: Duration,
+        length: Duration,
+    },
+}
+
+#[derive(Debug)]
+struct Scheduled<IP> {
+    at: Duration,
+    seq: u64,
+    event: SimEvent<IP>,
+}
+
+impl<IP> PartialEq for Scheduled<IP> {
+    fn eq(&self, other: &Self) -> bool {
+        (self.at, self.seq) == (other.at, other.seq)
+    }
+}
+
+impl<IP> Eq for Scheduled<IP> {}
+
+impl<IP> PartialOrd for Scheduled<IP> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<IP> Ord for Scheduled<IP> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // reversed: BinaryHeap is a max-heap and the earliest event must come first
+        (other.at, other.seq).cmp(&(self.at, self.seq))
+    }
+}
@@ -0,0 +1,191 @@
+use crate::Message;
+use crate::payloads::{KvErrorCode, KvPayload};
+use crate::rng::SimRng;
+use crate::sim::Service;
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KvConsistency {
+    /// Every operation observes the latest write (`lin-kv`).
+    Linearizable,
+    /// Reads may be stale, but each client observes a monotonic history
+    /// that includes its own writes (`seq-kv`).
+    Sequential,
+    /// Reads may return any recent value and cas may act on a stale one (`lww-kv`).
+    LastWriteWins,
+}
+
+/// Key-value store answering `KvPayload<V>` requests like Maelstrom's kv services.
+///
+/// Every write creates a new store version. A stale read picks a random version
+/// among those still current within `staleness` of virtual time; sequential
+/// stores never go behind the newest version a client has already observed.
+#[derive(Debug)]
+pub struct KvService<V = usize> {
+    consistency: KvConsistency,
+    staleness: Duration,
+    msg_id: usize,
+    written_at: Vec<Duration>,
+    history_by_key: HashMap<String, Vec<(usize, V)>>,
+    version_by_client: HashMap<String, usize>,
+}
+
+impl<V> KvService<V>
+where
+    V: Clone + PartialEq,
+{
+    pub fn new(consistency: KvConsistency, staleness: Duration) -> Self {
+        Self {
+            consistency,
+            staleness,
+            msg_id: 0,
+            written_at: vec![Duration::ZERO],
+            history_by_key: HashMap::new(),
+            version_by_client: HashMap::new(),
+        }
+    }
+
+    pub fn lin_kv() -> Self {
+        Self::new(KvConsistency::Linearizable, Duration::ZERO)
+    }
+
+    pub fn seq_kv(staleness: Duration) -> Self {
+        Self::new(KvConsistency::Sequential, staleness)
+    }
+
+    pub fn lww_kv(staleness: Duration) -> Self {
+        Self::new(KvConsistency::LastWriteWins, staleness)
+    }
+
+    /// Latest value of `key`, ignoring staleness.
+    pub fn get(&self, key: &str) -> Option<V> {
+        self.value_at(key, self.latest_version())
+    }
+
+    fn latest_version(&self) -> usize {
+        self.written_at.len() - 1
+    }
+
+    fn value_at(&self, key: &str, version: usize) -> Option<V> {
+        self.history_by_key
+            .get(key)?
+            .iter()
+            .rev()
+            .find(|(written_version, _)| *written_version <= version)
+            .map(|(_, value)| value.clone())
+    }
+
+    fn read_version(&mut self, client: &str, now: Duration, rng: &mut SimRng) -> usize {
+        let latest = self.latest_version();
+        if self.consistency == KvConsistency::Linearizable {
+            return latest;
+        }
+
+        let horizon = now.saturating_sub(self.staleness);
+        let oldest_current = self
+            .written_at
+            .iter()
+            .rposition(|written_at| *written_at <= horizon)
+            .unwrap_or(0);
+        let floor = match self.consistency {
+            KvConsistency::Sequential => self
+                .version_by_client
+                .get(client)
+                .copied()
+                .unwrap_or(0)
+                .max(oldest_current),
+            _ => oldest_current,
+        };
+
+        let version = floor + rng.below((latest - floor + 1) as u64) as usize;
+        self.observe(client, version);
+        version
+    }
+
+    fn write(&mut self, client: &str, key: String, value: V, now: Duration) {
+        self.written_at.push(now);
+        let version = self.latest_version();
+        self.history_by_key
+            .entry(key)
+            .or_default()
+            .push((version, value));
+        self.observe(client, version);
+    }
+
+    fn observe(&mut self, client: &str, version: usize) {
+        if self.consistency != KvConsistency::Sequential {
+            return;
+        }
+        let observed = self
+            .version_by_client
+            .entry(client.to_string())
+            .or_default();
+        *observed = (*observed).max(version);
+    }
+}
+
+impl<V> Service for KvService<V>
+where
+    V: Debug + Clone + PartialEq,
+{
+    type Payload = KvPayload<V>;
+
+    fn handle(
+        &mut self,
+        msg: Message<KvPayload<V>>,
+        now: Duration,
+        rng: &mut SimRng,
+    ) -> Vec<Message<KvPayload<V>>> {
+        let client = msg.src.clone();
+        let mut reply = msg.into_reply(Some(&mut self.msg_id));
+        reply.body.payload = match reply.body.payload {
+            KvPayload::Read { key } => {
+                let version = self.read_version(&client, now, rng);
+                match self.value_at(&key, version) {
+                    Some(value) => KvPayload::ReadOk { value },
+                    None => key_not_found(&key),
+                }
+            }
+            KvPayload::Write { key, value } => {
+                self.write(&client, key, value, now);
+                KvPayload::WriteOk
+            }
+            KvPayload::Cas {
+                key,
+                from,
+                to,
+                create_if_not_exists,
+            } => {
+                let version = match self.consistency {
+                    KvConsistency::LastWriteWins => self.read_version(&client, now, rng),
+                    _ => self.latest_version(),
+                };
+                match self.value_at(&key, version) {
+                    None if !create_if_not_exists => key_not_found(&key),
+                    Some(current) if current != from => KvPayload::Error {
+                        code: KvErrorCode::CAS_ERROR,
+                        text: Some(format!("expected {:?}, but had {:?}", from, current)),
+                    },
+                    _ => {
+                        self.write(&client, key, to, now);
+                        KvPayload::CasOk
+                    }
+                }
+            }
+            KvPayload::ReadOk { .. }
+            | KvPayload::WriteOk
+            | KvPayload::CasOk
+            | KvPayload::Error { .. } => return vec![],
+        };
+        vec![reply]
+    }
+}
+
+fn key_not_found<V>(key: &str) -> KvPayload<V> {
+    KvPayload::Error {
+        code: KvErrorCode::KEY_NOT_FOUND,
+        text: Some(format!("key {} does not exist", key)),
+    }
+}
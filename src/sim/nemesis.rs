@@ -0,0 +1,195 @@
+use crate::rng::SimRng;
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// Fault injector applied to node-to-node traffic of a [`Simulation`](crate::sim::Simulation).
+///
+/// Messages exchanged with clients and services are never affected, matching
+/// Maelstrom's `partition` nemesis.
+#[derive(Debug, Clone, Default)]
+pub struct Nemesis {
+    drop_probability: f64,
+    duplicate_probability: f64,
+    delay_probability: f64,
+    max_delay: Duration,
+    group_by_node_id: Option<HashMap<String, usize>>,
+    stats: NemesisStats,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct NemesisStats {
+    pub dropped: usize,
+    pub partitioned: usize,
+    pub duplicated: usize,
+    pub delayed: usize,
+}
+
+impl Nemesis {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_drop(mut self, probability: f64) -> Self {
+        self.drop_probability = probability;
+        self
+    }
+
+    pub fn with_duplicate(mut self, probability: f64) -> Self {
+        self.duplicate_probability = probability;
+        self
+    }
+
+    /// Delays a message by up to `max_delay` on top of the network latency,
+    /// which reorders it with respect to later messages.
+    pub fn with_delay(mut self, probability: f64, max_delay: Duration) -> Self {
+        self.delay_probability = probability;
+        self.max_delay = max_delay;
+        self
+    }
+
+    /// Splits the cluster: nodes in different groups can no longer talk to each other.
+    /// Nodes not listed in any group are isolated.
+    pub fn partition(&mut self, groups: Vec<Vec<String>>) {
+        self.group_by_node_id = Some(
+            groups
+                .into_iter()
+                .enumerate()
+                .flat_map(|(group, node_ids)| node_ids.into_iter().map(move |id| (id, group)))
+                .collect(),
+        );
+    }
+
+    pub fn heal(&mut self) {
+        self.group_by_node_id = None;
+    }
+
+    pub fn is_partitioned(&self) -> bool {
+        self.group_by_node_id.is_some()
+    }
+
+    pub fn can_communicate(&self, src: &str, dest: &str) -> bool {
+        let Some(group_by_node_id) = &self.group_by_node_id else {
+            return true;
+        };
+        match (group_by_node_id.get(src), group_by_node_id.get(dest)) {
+            (Some(src_group), Some(dest_group)) => src_group == dest_group,
+            _ => false,
+        }
+    }
+
+    pub fn stats(&self) -> NemesisStats {
+        self.stats
+    }
+
+    /// Decides the fate of a message: returns the extra delay of every copy to deliver,
+    /// or nothing if the message is lost.
+    pub(crate) fn apply(&mut self, src: &str, dest: &str, rng: &mut SimRng) -> Vec<Duration> {
+        if !self.can_communicate(src, dest) {
+            self.stats.partitioned += 1;
+            return vec![];
+        }
+        if rng.chance(self.drop_probability) {
+            self.stats.dropped += 1;
+            return vec![];
+        }
+
+        let copies = if rng.chance(self.duplicate_probability) {
+            self.stats.duplicated += 1;
+            2
+        } else {
+            1
+        };
+        (0..copies)
+            .map(|_| {
+                if rng.chance(self.delay_probability) {
+                    self.stats.delayed += 1;
+                    rng.duration(self.max_delay)
+                } else {
+                    Duration::ZERO
+                }
+            })
+            .collect()
+    }
+}
+
+/// Splits `node_ids` into two random non-empty halves, like Maelstrom's `partition` nemesis.
+pub fn random_halves(node_ids: &[String], rng: &mut SimRng) -> Vec<Vec<String>> {
+    let mut shuffled = node_ids.to_vec();
+    for i in (1..shuffled.len()).rev() {
+        let j = rng.below(i as u64 + 1) as usize;
+        shuffled.swap(i, j);
+    }
+    let split = shuffled.len().div_ceil(2);
+    let other_half = shuffled.split_off(split);
+    vec![shuffled, other_half]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn apply(nemesis: &mut Nemesis, rng: &mut SimRng) -> Vec<Duration> {
+        nemesis.apply("n0", "n1", rng)
+    }
+
+    #[test]
+    fn delivers_each_message_once_by_default() {
+        let mut nemesis = Nemesis::new();
+        let mut rng = SimRng::new(1);
+        for _ in 0..100 {
+            assert_eq!(apply(&mut nemesis, &mut rng), vec![Duration::ZERO]);
+        }
+        assert_eq!(nemesis.stats(), NemesisStats::default());
+    }
+
+    #[test]
+    fn drops_duplicates_and_delays_messages() {
+        let mut rng = SimRng::new(2);
+        let mut dropping = Nemesis::new().with_drop(1.0);
+        assert!(apply(&mut dropping, &mut rng).is_empty());
+        assert_eq!(dropping.stats().dropped, 1);
+
+        let mut duplicating = Nemesis::new().with_duplicate(1.0);
+        assert_eq!(apply(&mut duplicating, &mut rng).len(), 2);
+        assert_eq!(duplicating.stats().duplicated, 1);
+
+        let max_delay = Duration::from_millis(50);
+        let mut delaying = Nemesis::new().with_delay(1.0, max_delay);
+        let delays = (0..100)
+            .flat_map(|_| apply(&mut delaying, &mut rng))
+            .collect::<Vec<_>>();
+        assert!(delays.iter().all(|delay| *delay <= max_delay));
+        assert!(delays.iter().any(|delay| *delay > Duration::ZERO));
+        assert_eq!(delaying.stats().delayed, 100);
+    }
+
+    #[test]
+    fn partitions_isolate_groups_and_unlisted_nodes() {
+        let mut nemesis = Nemesis::new();
+        let mut rng = SimRng::new(3);
+        nemesis.partition(vec![
+            vec!["n0".to_string(), "n1".to_string()],
+            vec!["n2".to_string()],
+        ]);
+        assert!(nemesis.can_communicate("n0", "n1"));
+        assert!(!nemesis.can_communicate("n0", "n2"));
+        assert!(!nemesis.can_communicate("n3", "n0"));
+        assert!(nemesis.apply("n1", "n2", &mut rng).is_empty());
+        assert_eq!(nemesis.stats().partitioned, 1);
+
+        nemesis.heal();
+        assert!(!nemesis.is_partitioned());
+        assert_eq!(nemesis.apply("n1", "n2", &mut rng).len(), 1);
+    }
+
+    #[test]
+    fn random_halves_split_every_node_in_two_non_empty_groups() {
+        let node_ids = (0..5).map(|i| format!("n{}", i)).collect::<Vec<_>>();
+        let halves = random_halves(&node_ids, &mut SimRng::new(4));
+        assert_eq!(halves.len(), 2);
+        assert_eq!((halves[0].len(), halves[1].len()), (3, 2));
+        let mut all = halves.concat();
+        all.sort();
+        assert_eq!(all, node_ids);
+    }
+}
@@ -4,26 +4,56 @@ use std::io::{Stdout, StdoutLock, Write};
 
 #[derive(Debug)]
 pub struct StdoutJson {
-    stdout_lock: StdoutLock<'static>,
+    sink: Sink,
+}
+
+#[derive(Debug)]
+enum Sink {
+    Stdout(StdoutLock<'static>),
+    Buffer(Vec<u8>),
 }
 
 impl StdoutJson {
     pub fn new() -> Self {
         let stdout = std::io::stdout();
         let stdout_lock = stdout.lock();
-        StdoutJson { stdout_lock }
+        StdoutJson {
+            sink: Sink::Stdout(stdout_lock),
+        }
+    }
+
+    /// Creates an output that collects messages in memory instead of writing them to stdout.
+    /// Buffered lines are retrieved with [`StdoutJson::take_lines`].
+    pub fn buffered() -> Self {
+        StdoutJson {
+            sink: Sink::Buffer(Vec::new()),
+        }
     }
 
     pub fn write<T>(&mut self, message: &T) -> anyhow::Result<()>
     where
         T: Serialize,
     {
-        serde_json::to_writer(&mut self.stdout_lock, message).context("cannot write to stdout")?;
-        self.stdout_lock
-            .write_all(b"\n")
-            .context("cannot write to stdout")?;
+        let writer: &mut dyn Write = match &mut self.sink {
+            Sink::Stdout(stdout_lock) => stdout_lock,
+            Sink::Buffer(buffer) => buffer,
+        };
+        serde_json::to_writer(&mut *writer, message).context("cannot write to stdout")?;
+        writer.write_all(b"\n").context("cannot write to stdout")?;
         Ok(())
     }
+
+    /// Drains the lines written so far to a buffered output.
+    /// Always empty when writing to stdout.
+    pub fn take_lines(&mut self) -> Vec<String> {
+        let Sink::Buffer(buffer) = &mut self.sink else {
+            return vec![];
+        };
+        String::from_utf8_lossy(&std::mem::take(buffer))
+            .lines()
+            .map(str::to_string)
+            .collect()
+    }
 }
 
 impl Default for StdoutJson {
@@ -34,15 +64,20 @@ impl Default for StdoutJson {
 
 impl From<Stdout> for StdoutJson {
     fn from(stdout: Stdout) -> Self {
-        Self { stdout_lock: stdout.lock() }
+        Self {
+            sink: Sink::Stdout(stdout.lock()),
+        }
     }
 }
 
 impl From<StdoutJson> for StdoutLock<'static> {
     fn from(stdout_json: StdoutJson) -> Self {
-        stdout_json.stdout_lock
+        match stdout_json.sink {
+            Sink::Stdout(stdout_lock) => stdout_lock,
+            Sink::Buffer(_) => std::io::stdout().lock(),
+        }
     }
 }
 
 unsafe impl Send for StdoutJson {}
-unsafe impl Sync for StdoutJson {}
\ No newline at end of file
+unsafe impl Sync for StdoutJson {}
@@ -26,6 +26,10 @@ impl AsyncStdoutJson {
             .write_all(b"\n")
             .await
             .context("cannot write newline to stdout")?;
+        self.async_stdout
+            .flush()
+            .await
+            .context("cannot flush stdout")?;
         Ok(())
     }
 }
@@ -0,0 +1,282 @@
+use crate::rng::SimRng;
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+/// Named periodic and one-shot timers of a node.
+///
+/// Nodes register timers on the handle they get at init, and may keep it to schedule
+/// or cancel timers later. The loop driving the node owns the clock: it advances it
+/// and steps the node with the payload of each due timer, as an
+/// [`Event::InjectedPayload`](crate::payloads::Event::InjectedPayload).
+/// [`main_loop`](crate::mloop::main_loop) runs timers on wall-clock time and
+/// [`Simulation`](crate::sim::Simulation) on virtual time.
+///
+/// Scheduling a timer under a name already in use replaces it.
+pub struct Timers<IP> {
+    schedule: Arc<Mutex<Schedule<IP>>>,
+}
+
+struct Schedule<IP> {
+    now: Duration,
+    seq: u64,
+    rng: SimRng,
+    timers: BTreeMap<String, Timer<IP>>,
+}
+
+struct Timer<IP> {
+    deadline: Duration,
+    /// Orders timers due at the same time by scheduling order.
+    seq: u64,
+    period: Option<Duration>,
+    jitter: Duration,
+    payload: IP,
+}
+
+impl<IP> Timers<IP>
+where
+    IP: Clone,
+{
+    /// `seed` drives the jitter.
+    pub fn new(seed: u64) -> Self {
+        Self {
+            schedule: Arc::new(Mutex::new(Schedule {
+                now: Duration::ZERO,
+                seq: 0,
+                rng: SimRng::new(seed),
+                timers: BTreeMap::new(),
+            })),
+        }
+    }
+
+    /// Fires `payload` every `interval`, which must not be zero.
+    pub fn every(
+        &self,
+        name: impl Into<String>,
+        interval: Duration,
+        payload: IP,
+    ) -> anyhow::Result<()> {
+        self.every_with_jitter(name, interval, Duration::ZERO, payload)
+    }
+
+    /// Fires `payload` every `interval`, each time delayed by a random duration up to
+    /// `jitter` so that nodes started together do not fire in lockstep.
+    ///
+    /// A zero `interval` is rejected: the timer would be due again as soon as it fired.
+    pub fn every_with_jitter(
+        &self,
+        name: impl Into<String>,
+        interval: Duration,
+        jitter: Duration,
+        payload: IP,
+    ) -> anyhow::Result<()> {
+        let name = name.into();
+        anyhow::ensure!(
+            !interval.is_zero(),
+            "interval of timer {} must be greater than 0",
+            name
+        );
+        self.schedule(name, interval, Some(interval), jitter, payload);
+        Ok(())
+    }
+
+    /// Fires `payload` once, after `delay`.
+    pub fn after(&self, name: impl Into<String>, delay: Duration, payload: IP) {
+        self.schedule(name.into(), delay, None, Duration::ZERO, payload);
+    }
+
+    /// Returns `false` if no timer is scheduled under `name`.
+    pub fn cancel(&self, name: &str) -> bool {
+        self.lock().timers.remove(name).is_some()
+    }
+
+    pub fn is_scheduled(&self, name: &str) -> bool {
+        self.lock().timers.contains_key(name)
+    }
+
+    pub fn now(&self) -> Duration {
+        self.lock().now
+    }
+
+    /// Moves the clock forward to `now`.
+    pub fn advance(&self, now: Duration) {
+        let mut schedule = self.lock();
+        schedule.now = schedule.now.max(now);
+    }
+
+    /// Time at which the next timer is due.
+    pub fn next_deadline(&self) -> Option<Duration> {
+        self.lock()
+            .timers
+            .values()
+            .map(|timer| timer.deadline)
+            .min()
+    }
+
+    /// Takes the payload of the earliest timer due by now, rescheduling it if periodic.
+    /// A periodic timer that fell behind fires once and resumes one period later.
+    pub fn pop_due(&self) -> Option<IP> {
+        let mut schedule = self.lock();
+        let now = schedule.now;
+        let name = schedule
+            .timers
+            .iter()
+            .filter(|(_, timer)| timer.deadline <= now)
+            .min_by_key(|(_, timer)| (timer.deadline, timer.seq))
+            .map(|(name, _)| name.clone())?;
+
+        let timer = schedule.timers.remove(&name)?;
+        let payload = timer.payload.clone();
+        if let Some(period) = timer.period {
+            let jitter = schedule.rng.duration(timer.jitter);
+            let mut deadline = timer.deadline + period;
+            if deadline <= now {
+                deadline = now + period;
+            }
+            let deadline = deadline + jitter;
+            schedule.insert(name, deadline, timer);
+        }
+        Some(payload)
+    }
+
+    fn schedule(
+        &self,
+        name: String,
+        delay: Duration,
+        period: Option<Duration>,
+        jitter: Duration,
+        payload: IP,
+    ) {
+        let mut schedule = self.lock();
+        let deadline = schedule.now + delay + schedule.rng.duration(jitter);
+        let timer = Timer {
+            deadline,
+            seq: 0,
+            period,
+            jitter,
+            payload,
+        };
+        schedule.insert(name, deadline, timer);
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Schedule<IP>> {
+        self.schedule
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl<IP> Schedule<IP> {
+    fn insert(&mut self, name: String, deadline: Duration, mut timer: Timer<IP>) {
+        timer.deadline = deadline;
+        timer.seq = self.seq;
+        self.seq += 1;
+        self.timers.insert(name, timer);
+    }
+}
+
+impl<IP> Clone for Timers<IP> {
+    fn clone(&self) -> Self {
+        Self {
+            schedule: Arc::clone(&self.schedule),
+        }
+    }
+}
+
+impl<IP> Debug for Timers<IP> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let schedule = self
+            .schedule
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        f.debug_map()
+            .entries(
+                schedule
+                    .timers
+                    .iter()
+                    .map(|(name, timer)| (name, timer.deadline)),
+            )
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn due(timers: &Timers<&'static str>, now: u64) -> Vec<&'static str> {
+        timers.advance(Duration::from_millis(now));
+        std::iter::from_fn(|| timers.pop_due()).collect()
+    }
+
+    #[test]
+    fn fires_periodic_and_one_shot_timers_in_deadline_order() {
+        let timers = Timers::new(1);
+        timers
+            .every("tick", Duration::from_millis(10), "tick")
+            .unwrap();
+        timers.after("once", Duration::from_millis(15), "once");
+
+        assert_eq!(due(&timers, 9), Vec::<&str>::new());
+        assert_eq!(due(&timers, 10), vec!["tick"]);
+        assert_eq!(due(&timers, 20), vec!["once", "tick"]);
+        assert!(!timers.is_scheduled("once"));
+        assert_eq!(timers.next_deadline(), Some(Duration::from_millis(30)));
+    }
+
+    #[test]
+    fn a_late_periodic_timer_fires_once_and_resumes_a_period_later() {
+        let timers = Timers::new(1);
+        timers
+            .every("tick", Duration::from_millis(10), "tick")
+            .unwrap();
+
+        assert_eq!(due(&timers, 55), vec!["tick"]);
+        assert_eq!(timers.next_deadline(), Some(Duration::from_millis(65)));
+    }
+
+    #[test]
+    fn rescheduling_replaces_and_cancel_removes() {
+        let timers = Timers::new(1);
+        timers.after("retry", Duration::from_millis(10), "first");
+        timers.after("retry", Duration::from_millis(20), "second");
+        assert_eq!(due(&timers, 15), Vec::<&str>::new());
+        assert_eq!(due(&timers, 20), vec!["second"]);
+
+        timers.after("retry", Duration::from_millis(10), "third");
+        assert!(timers.cancel("retry"));
+        assert!(!timers.cancel("retry"));
+        assert_eq!(due(&timers, 100), Vec::<&str>::new());
+    }
+
+    #[test]
+    fn jitter_delays_each_period_by_at_most_the_jitter() {
+        let timers = Timers::new(7);
+        let (interval, jitter) = (Duration::from_millis(100), Duration::from_millis(10));
+        timers
+            .every_with_jitter("gossip", interval, jitter, "gossip")
+            .unwrap();
+
+        let mut previous = Duration::ZERO;
+        for _ in 0..20 {
+            let deadline = timers.next_deadline().unwrap();
+            assert!(deadline >= previous + interval && deadline <= previous + interval + jitter);
+            timers.advance(deadline);
+            assert_eq!(timers.pop_due(), Some("gossip"));
+            previous = deadline;
+        }
+    }
+
+    #[test]
+    fn rejects_a_zero_interval() {
+        let timers = Timers::new(1);
+        assert!(timers.every("tick", Duration::ZERO, "tick").is_err());
+        assert!(
+            timers
+                .every_with_jitter("tick", Duration::ZERO, Duration::from_millis(1), "tick")
+                .is_err()
+        );
+        assert_eq!(timers.next_deadline(), None);
+    }
+}
@@ -0,0 +1,125 @@
+use rustorm::checker::broadcast::check_broadcast;
+use rustorm::node::Node;
+use rustorm::node::broadcast::BroadcastNode;
+use rustorm::node::multibroadcast::MultiNodeBroadcast;
+use rustorm::payloads::{BroadcastPayload, InjectedPayload};
+use rustorm::sim::Simulation;
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::time::Duration;
+
+/// Sends every node a line topology, n0 - n1 - ... - n4, so that values take several hops.
+fn send_topology<N, IP>(sim: &mut Simulation<N, BroadcastPayload, IP>)
+where
+    N: Node<BroadcastPayload, IP>,
+    IP: Debug + Clone,
+{
+    let node_ids = sim.node_ids().cloned().collect::<Vec<_>>();
+    let topology = node_ids
+        .iter()
+        .enumerate()
+        .map(|(i, node_id)| {
+            let neighbors = [i.wrapping_sub(1), i + 1]
+                .into_iter()
+                .filter_map(|j| node_ids.get(j).cloned())
+                .collect();
+            (node_id.clone(), neighbors)
+        })
+        .collect::<HashMap<_, _>>();
+    for node_id in &node_ids {
+        sim.send(
+            "c9",
+            node_id,
+            BroadcastPayload::Topology {
+                topology: topology.clone(),
+            },
+        )
+        .unwrap();
+    }
+}
+
+fn broadcast_and_read<N, IP>(sim: &mut Simulation<N, BroadcastPayload, IP>, messages: usize)
+where
+    N: Node<BroadcastPayload, IP>,
+    IP: Debug + Clone,
+{
+    let node_ids = sim.node_ids().cloned().collect::<Vec<_>>();
+    for message in 0..messages {
+        let node_id = &node_ids[message % node_ids.len()];
+        sim.send("c1", node_id, BroadcastPayload::Broadcast { message })
+            .unwrap();
+        sim.send("c2", node_id, BroadcastPayload::Read).unwrap();
+        sim.run_for(Duration::from_millis(20)).unwrap();
+    }
+}
+
+fn final_reads<N, IP>(sim: &mut Simulation<N, BroadcastPayload, IP>)
+where
+    N: Node<BroadcastPayload, IP>,
+    IP: Debug + Clone,
+{
+    let node_ids = sim.node_ids().cloned().collect::<Vec<_>>();
+    for node_id in &node_ids {
+        sim.send("c3", node_id, BroadcastPayload::Read).unwrap();
+    }
+    sim.run_for(Duration::from_millis(200)).unwrap();
+}
+
+#[test]
+fn single_node_reads_every_broadcast() {
+    let mut sim = Simulation::<BroadcastNode, BroadcastPayload>::new(1, 1).unwrap();
+    send_topology(&mut sim);
+    broadcast_and_read(&mut sim, 20);
+    final_reads(&mut sim);
+
+    let report = check_broadcast(&sim.history::<BroadcastPayload>());
+    assert!(report.is_valid(), "{:?}", report);
+    assert!(report.never_read.is_empty());
+}
+
+#[test]
+fn multi_node_broadcasts_reach_every_node() {
+    let mut sim = Simulation::<MultiNodeBroadcast, BroadcastPayload, InjectedPayload>::new(5, 42)
+        .unwrap()
+        .with_latency(Duration::from_millis(5), Duration::from_millis(50));
+    send_topology(&mut sim);
+    broadcast_and_read(&mut sim, 50);
+    sim.run_for(Duration::from_secs(10)).unwrap();
+    final_reads(&mut sim);
+
+    let report = check_broadcast(&sim.history::<BroadcastPayload>());
+    assert!(report.is_valid(), "{:?}", report);
+    assert!(report.never_read.is_empty(), "{:?}", report.never_read);
+}
+
+#[test]
+fn multi_node_broadcasts_survive_partitions() {
+    let mut sim = Simulation::<MultiNodeBroadcast, BroadcastPayload, InjectedPayload>::new(5, 7)
+        .unwrap()
+        .with_latency(Duration::from_millis(5), Duration::from_millis(50));
+    let halves = vec![
+        vec!["n0".to_string(), "n1".to_string()],
+        vec!["n2".to_string(), "n3".to_string(), "n4".to_string()],
+    ];
+    sim.partition_at(Duration::from_millis(100), halves);
+    sim.heal_at(Duration::from_secs(3));
+    send_topology(&mut sim);
+    broadcast_and_read(&mut sim, 50);
+    sim.run_for(Duration::from_secs(1)).unwrap();
+    assert!(sim.nemesis().is_partitioned());
+    let read = sim.send("c3", "n4", BroadcastPayload::Read).unwrap();
+    sim.run_for(Duration::from_millis(100)).unwrap();
+    let Some(BroadcastPayload::ReadOk { messages }) = sim
+        .reply::<BroadcastPayload>("c3", read)
+        .map(|body| body.payload)
+    else {
+        panic!("n4 did not answer the read");
+    };
+    assert!(!messages.contains(&10));
+    sim.run_for(Duration::from_secs(10)).unwrap();
+    final_reads(&mut sim);
+
+    let report = check_broadcast(&sim.history::<BroadcastPayload>());
+    assert!(report.is_valid(), "{:?}", report);
+    assert!(report.never_read.is_empty(), "{:?}", report.never_read);
+}
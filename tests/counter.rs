@@ -0,0 +1,123 @@
+use rustorm::FromBody;
+use rustorm::checker::counter::check_g_counter;
+use rustorm::crdt::{GCounter, GSet, PnCounter};
+use rustorm::node::Node;
+use rustorm::node::crdt::CrdtNode;
+use rustorm::node::gocounter::GrowOnlyCounterNode;
+use rustorm::payloads::{
+    CrdtPayload, CrdtTimer, GoCounterOrSeqKvPayload, GoCounterPayload, SyncCounter,
+};
+use rustorm::sim::Simulation;
+use rustorm::sim::kv::KvService;
+use serde_json::json;
+use std::fmt::Debug;
+use std::time::Duration;
+
+/// Sends the `add` requests with these fields spread over the nodes, with the cluster split
+/// in two halves for the first second.
+fn add_under_partition<N, P, IP>(sim: &mut Simulation<N, P, IP>, adds: &[serde_json::Value])
+where
+    N: Node<P, IP>,
+    P: Debug + FromBody,
+    IP: Debug + Clone,
+{
+    let node_ids = sim.node_ids().cloned().collect::<Vec<_>>();
+    let (left, right) = node_ids.split_at(node_ids.len() / 2);
+    sim.partition_at(Duration::ZERO, vec![left.to_vec(), right.to_vec()]);
+    sim.heal_at(Duration::from_secs(1));
+    for (i, add) in adds.iter().enumerate() {
+        let mut request = add.clone();
+        request["type"] = json!("add");
+        sim.send("c1", &node_ids[i % node_ids.len()], request)
+            .unwrap();
+        sim.run_for(Duration::from_millis(10)).unwrap();
+    }
+}
+
+/// Reads from every node once the cluster had time to converge and returns the values read.
+fn final_reads<N, P, IP>(sim: &mut Simulation<N, P, IP>) -> Vec<serde_json::Value>
+where
+    N: Node<P, IP>,
+    P: Debug + FromBody,
+    IP: Debug + Clone,
+{
+    sim.run_for(Duration::from_secs(5)).unwrap();
+    let node_ids = sim.node_ids().cloned().collect::<Vec<_>>();
+    let reads = node_ids
+        .iter()
+        .map(|node_id| sim.send("c2", node_id, json!({"type": "read"})).unwrap())
+        .collect::<Vec<_>>();
+    sim.run_for(Duration::from_millis(100)).unwrap();
+    reads
+        .into_iter()
+        .map(|msg_id| {
+            sim.reply::<serde_json::Value>("c2", msg_id)
+                .expect("every node answers a read")
+                .payload["value"]
+                .clone()
+        })
+        .collect()
+}
+
+#[test]
+fn gocounter_sums_adds_through_seq_kv() {
+    let mut sim =
+        Simulation::<GrowOnlyCounterNode, GoCounterOrSeqKvPayload, SyncCounter>::new(3, 5)
+            .unwrap()
+            .with_latency(Duration::from_millis(1), Duration::from_millis(10));
+    sim.add_service(
+        "seq-kv",
+        KvService::<usize>::seq_kv(Duration::from_millis(100)),
+    );
+    let adds = (1..=30)
+        .map(|delta| json!({"delta": delta}))
+        .collect::<Vec<_>>();
+    add_under_partition(&mut sim, &adds);
+
+    assert_eq!(final_reads(&mut sim), vec![json!(465); 3]);
+    let report = check_g_counter(&sim.history::<GoCounterPayload>(), Duration::from_secs(1));
+    assert!(report.is_valid(), "{:?}", report);
+}
+
+#[test]
+fn crdt_g_counter_converges_after_a_partition() {
+    let mut sim = Simulation::<CrdtNode<GCounter>, CrdtPayload<GCounter>, CrdtTimer>::new(5, 9)
+        .unwrap()
+        .with_latency(Duration::from_millis(1), Duration::from_millis(10));
+    let adds = (1..=30)
+        .map(|delta| json!({"delta": delta}))
+        .collect::<Vec<_>>();
+    add_under_partition(&mut sim, &adds);
+
+    assert_eq!(final_reads(&mut sim), vec![json!(465); 5]);
+    let report = check_g_counter(&sim.history::<GoCounterPayload>(), Duration::from_secs(1));
+    assert!(report.is_valid(), "{:?}", report);
+}
+
+#[test]
+fn crdt_pn_counter_converges_after_a_partition() {
+    let mut sim = Simulation::<CrdtNode<PnCounter>, CrdtPayload<PnCounter>, CrdtTimer>::new(3, 13)
+        .unwrap()
+        .with_latency(Duration::from_millis(1), Duration::from_millis(10));
+    let adds = (1..=30)
+        .map(|delta: i64| json!({"delta": if delta % 3 == 0 { -delta } else { delta }}))
+        .collect::<Vec<_>>();
+    add_under_partition(&mut sim, &adds);
+
+    assert_eq!(final_reads(&mut sim), vec![json!(135); 3]);
+}
+
+#[test]
+fn crdt_g_set_converges_after_a_partition() {
+    let mut sim =
+        Simulation::<CrdtNode<GSet<usize>>, CrdtPayload<GSet<usize>>, CrdtTimer>::new(3, 17)
+            .unwrap()
+            .with_latency(Duration::from_millis(1), Duration::from_millis(10));
+    let adds = (0..30)
+        .map(|element| json!({"element": element}))
+        .collect::<Vec<_>>();
+    add_under_partition(&mut sim, &adds);
+
+    let elements = json!((0..30).collect::<Vec<_>>());
+    assert_eq!(final_reads(&mut sim), vec![elements; 3]);
+}
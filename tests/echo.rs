@@ -0,0 +1,52 @@
+use rustorm::node::echo::EchoNode;
+use rustorm::node::generate::GenerateNode;
+use rustorm::payloads::{EchoPayload, GeneratePayload};
+use rustorm::sim::Simulation;
+use std::collections::HashSet;
+use std::time::Duration;
+
+#[test]
+fn echo_replies_with_the_request() {
+    let mut sim = Simulation::<EchoNode, EchoPayload>::new(1, 1).unwrap();
+    let msg_id = sim
+        .send(
+            "c1",
+            "n0",
+            EchoPayload::Echo {
+                echo: "hello".to_string(),
+            },
+        )
+        .unwrap();
+    sim.run_for(Duration::from_millis(10)).unwrap();
+
+    let reply = sim.reply::<EchoPayload>("c1", msg_id).unwrap();
+    assert_eq!(reply.in_reply_to, Some(msg_id));
+    assert!(matches!(reply.payload, EchoPayload::EchoOk { echo } if echo == "hello"));
+}
+
+#[test]
+fn generated_ids_are_unique_across_nodes() {
+    let mut sim = Simulation::<GenerateNode, GeneratePayload>::new(3, 1)
+        .unwrap()
+        .with_latency(Duration::from_millis(1), Duration::from_millis(10));
+    sim.partition_randomly(Duration::from_millis(50), Duration::from_millis(20));
+    let requests = (0..300)
+        .map(|i| {
+            let node = format!("n{}", i % 3);
+            sim.send("c1", &node, GeneratePayload::Generate).unwrap()
+        })
+        .collect::<Vec<_>>();
+    sim.run_for(Duration::from_millis(100)).unwrap();
+
+    let ids = requests
+        .into_iter()
+        .map(|msg_id| match sim.reply::<GeneratePayload>("c1", msg_id) {
+            Some(reply) => match reply.payload {
+                GeneratePayload::GenerateOk { guid } => guid,
+                GeneratePayload::Generate => panic!("unexpected generate"),
+            },
+            None => panic!("request {} was not answered", msg_id),
+        })
+        .collect::<HashSet<_>>();
+    assert_eq!(ids.len(), 300);
+}
@@ -0,0 +1,243 @@
+use rustorm::FromBody;
+use rustorm::checker::kafka::check_kafka;
+use rustorm::node::Node;
+use rustorm::node::kafkalog::KafkaLogNode;
+use rustorm::node::multikafkalog::{KafkaLogLimits, MultiKafkaLogNode};
+use rustorm::node::replicatedkafkalog::ReplicatedKafkaLogNode;
+use rustorm::payloads::{
+    ErrorCode, ErrorPayload, KafkaKvValue, KafkaLogOrKvPayload, KafkaLogPayload, KafkaLogTimer,
+    KafkaReplicationTimer, ReplicatedKafkaLogPayload,
+};
+use rustorm::sim::kv::KvService;
+use rustorm::sim::{BlockingNode, Simulation};
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::time::Duration;
+
+const KEYS: usize = 4;
+
+/// Sends 30 messages spread over the keys and nodes, then polls every key from each node.
+fn run_workload<N, P, IP>(sim: &mut Simulation<N, P, IP>)
+where
+    N: Node<P, IP>,
+    P: Debug + FromBody,
+    IP: Debug + Clone,
+{
+    let node_ids = sim.node_ids().cloned().collect::<Vec<_>>();
+    for msg in 0..30 {
+        let node = &node_ids[msg % node_ids.len()];
+        sim.send(
+            "c1",
+            node,
+            KafkaLogPayload::Send {
+                key: format!("k{}", msg % KEYS),
+                msg,
+            },
+        )
+        .unwrap();
+        sim.run_for(Duration::from_millis(3)).unwrap();
+    }
+    sim.run_for(Duration::from_millis(500)).unwrap();
+
+    for node in &node_ids {
+        let offsets = (0..KEYS)
+            .map(|key| (format!("k{}", key), 0))
+            .collect::<HashMap<_, _>>();
+        sim.send("c2", node, KafkaLogPayload::Poll { offsets })
+            .unwrap();
+    }
+    sim.run_for(Duration::from_millis(500)).unwrap();
+
+    let report = check_kafka(&sim.history::<KafkaLogPayload>());
+    assert_eq!(report.sends, 30);
+    assert_eq!(report.polls, node_ids.len());
+    assert!(report.is_valid(), "{:?}", report);
+}
+
+#[test]
+fn kafkalog_assigns_consecutive_offsets() {
+    let mut sim = Simulation::<KafkaLogNode, KafkaLogPayload>::new(1, 1).unwrap();
+    run_workload(&mut sim);
+}
+
+#[test]
+fn multikafkalog_shares_the_log_through_lin_kv() {
+    let mut sim = MultiSim::new(2, 3)
+        .unwrap()
+        .with_latency(Duration::from_millis(1), Duration::from_millis(5));
+    sim.add_service("lin-kv", KvService::<KafkaKvValue>::lin_kv());
+    run_workload(&mut sim);
+}
+
+type MultiSim = Simulation<
+    BlockingNode<MultiKafkaLogNode, KafkaLogOrKvPayload, KafkaLogTimer>,
+    KafkaLogOrKvPayload,
+    KafkaLogTimer,
+>;
+
+/// Sends `request` from `c1` to `node` and returns the reply.
+fn request(sim: &mut MultiSim, node: &str, request: KafkaLogPayload) -> KafkaLogPayload {
+    let msg_id = sim.send("c1", node, request).unwrap();
+    sim.run_for(Duration::from_millis(100)).unwrap();
+    sim.reply::<KafkaLogPayload>("c1", msg_id).unwrap().payload
+}
+
+fn committed(sim: &mut MultiSim, node: &str, group: Option<&str>) -> HashMap<String, usize> {
+    let list = KafkaLogPayload::ListCommittedOffsets {
+        keys: vec!["k".to_string()],
+        group: group.map(str::to_string),
+    };
+    match request(sim, node, list) {
+        KafkaLogPayload::ListCommittedOffsetsOk { offsets } => offsets,
+        reply => panic!("unexpected reply {:?}", reply),
+    }
+}
+
+#[test]
+fn multikafkalog_keeps_committed_offsets_per_consumer_group() {
+    let mut sim = MultiSim::new(2, 13)
+        .unwrap()
+        .with_latency(Duration::from_millis(1), Duration::from_millis(5));
+    sim.add_service("lin-kv", KvService::<KafkaKvValue>::lin_kv());
+    for msg in 0..3 {
+        let send = KafkaLogPayload::Send {
+            key: "k".to_string(),
+            msg,
+        };
+        request(&mut sim, &format!("n{}", msg % 2), send);
+    }
+
+    let commit = |offset, group: Option<&str>| KafkaLogPayload::CommitOffsets {
+        offsets: HashMap::from([("k".to_string(), offset)]),
+        group: group.map(str::to_string),
+    };
+    for (node, offset, group) in [("n0", 2, None), ("n1", 3, Some("audit")), ("n0", 1, None)] {
+        let reply = request(&mut sim, node, commit(offset, group));
+        assert!(
+            matches!(reply, KafkaLogPayload::CommitOffsetsOk),
+            "{:?}",
+            reply
+        );
+    }
+    assert_eq!(
+        committed(&mut sim, "n0", None),
+        HashMap::from([("k".to_string(), 2)])
+    );
+    assert_eq!(
+        committed(&mut sim, "n0", Some("audit")),
+        HashMap::from([("k".to_string(), 3)])
+    );
+
+    let reply = request(&mut sim, "n0", KafkaLogPayload::ListGroups);
+    assert!(
+        matches!(&reply, KafkaLogPayload::ListGroupsOk { groups } if groups == &["audit", "default"]),
+        "{:?}",
+        reply
+    );
+
+    let reset = KafkaLogPayload::ResetOffsets {
+        offsets: HashMap::from([("k".to_string(), 1)]),
+        group: Some("audit".to_string()),
+    };
+    let reply = request(&mut sim, "n1", reset);
+    assert!(
+        matches!(reply, KafkaLogPayload::ResetOffsetsOk),
+        "{:?}",
+        reply
+    );
+    assert_eq!(
+        committed(&mut sim, "n1", Some("audit")),
+        HashMap::from([("k".to_string(), 1)])
+    );
+    assert_eq!(
+        committed(&mut sim, "n1", None),
+        HashMap::from([("k".to_string(), 2)])
+    );
+}
+
+type ReplicatedSim =
+    Simulation<ReplicatedKafkaLogNode, ReplicatedKafkaLogPayload, KafkaReplicationTimer>;
+
+#[test]
+fn replicatedkafkalog_polls_acknowledged_sends_from_any_node() {
+    let mut sim = ReplicatedSim::new(3, 7)
+        .unwrap()
+        .with_latency(Duration::from_millis(1), Duration::from_millis(5));
+    run_workload(&mut sim);
+}
+
+#[test]
+fn replicatedkafkalog_times_out_sends_a_follower_did_not_acknowledge() {
+    let mut sim = ReplicatedSim::new(3, 11)
+        .unwrap()
+        .with_latency(Duration::from_millis(1), Duration::from_millis(5));
+    let node = sim.node("n0").unwrap();
+    let owner = node.owner_of("k").to_string();
+    let follower = node.followers_of("k").next().unwrap().clone();
+    let other = sim
+        .node_ids()
+        .find(|node_id| **node_id != owner && **node_id != follower)
+        .unwrap()
+        .clone();
+    sim.partition_at(
+        Duration::ZERO,
+        vec![vec![owner.clone(), other.clone()], vec![follower]],
+    );
+    sim.run_for(Duration::from_millis(1)).unwrap();
+
+    let send = KafkaLogPayload::Send {
+        key: "k".to_string(),
+        msg: 1,
+    };
+    let direct = sim.send("c1", &owner, send.clone()).unwrap();
+    let forwarded = sim.send("c1", &other, send).unwrap();
+    sim.run_for(Duration::from_secs(2)).unwrap();
+
+    for msg_id in [direct, forwarded] {
+        let reply = sim.reply::<ErrorPayload>("c1", msg_id).unwrap();
+        assert!(matches!(reply.payload.code, ErrorCode::Timeout));
+        assert_eq!(
+            reply.payload.text.as_deref(),
+            Some("followers did not acknowledge the message in time")
+        );
+    }
+}
+
+#[test]
+fn multikafkalog_rejects_requests_over_the_limits_and_times_out_the_others() {
+    // Without a lin-kv service no kv request is ever answered.
+    let mut sim = MultiSim::new(1, 5).unwrap();
+    let limits = KafkaLogLimits::from_config().unwrap();
+    let accepted = limits.max_in_flight + limits.max_queued;
+    let msg_ids = (0..=accepted)
+        .map(|msg| {
+            let send = KafkaLogPayload::Send {
+                key: "k".to_string(),
+                msg,
+            };
+            sim.send("c1", "n0", send).unwrap()
+        })
+        .collect::<Vec<_>>();
+    sim.run_for(limits.request_timeout / 2).unwrap();
+
+    let replies = |sim: &MultiSim| {
+        msg_ids
+            .iter()
+            .filter_map(|msg_id| sim.reply::<ErrorPayload>("c1", *msg_id))
+            .map(|reply| reply.payload.code)
+            .collect::<Vec<_>>()
+    };
+    let rejected = replies(&sim);
+    assert_eq!(rejected.len(), 1);
+    assert!(matches!(rejected[0], ErrorCode::TemporarilyUnavailable));
+
+    sim.run_for(limits.request_timeout).unwrap();
+    let replies = replies(&sim);
+    assert_eq!(replies.len(), msg_ids.len());
+    let timed_out = replies
+        .iter()
+        .filter(|code| matches!(code, ErrorCode::Timeout))
+        .count();
+    assert_eq!(timed_out, accepted);
+    assert_eq!(sim.node("n0").unwrap().inner().pending_requests(), 0);
+}
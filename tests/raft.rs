@@ -0,0 +1,128 @@
+use rustorm::node::raft::{RaftNode, Role};
+use rustorm::payloads::{KvPayload, RaftOrKvPayload, RaftTimer};
+use rustorm::sim::Simulation;
+use std::time::Duration;
+
+type Sim = Simulation<RaftNode, RaftOrKvPayload, RaftTimer>;
+
+fn cluster(seed: u64) -> Sim {
+    let mut sim = Sim::new(5, seed)
+        .unwrap()
+        .with_latency(Duration::from_millis(1), Duration::from_millis(5));
+    sim.run_for(Duration::from_secs(1)).unwrap();
+    sim
+}
+
+fn leaders(sim: &Sim) -> Vec<String> {
+    sim.node_ids()
+        .filter(|node_id| sim.node(node_id).unwrap().role() == Role::Leader)
+        .cloned()
+        .collect()
+}
+
+/// Sends `request` to `node` and returns its reply after a second.
+fn request(sim: &mut Sim, node: &str, request: KvPayload) -> serde_json::Value {
+    let msg_id = sim.send("c1", node, request).unwrap();
+    sim.run_for(Duration::from_secs(1)).unwrap();
+    sim.reply::<serde_json::Value>("c1", msg_id)
+        .expect("the request was answered")
+        .payload
+}
+
+fn write(sim: &mut Sim, node: &str, value: usize) -> serde_json::Value {
+    let write = KvPayload::Write {
+        key: "x".to_string(),
+        value,
+    };
+    request(sim, node, write)
+}
+
+fn read(sim: &mut Sim, node: &str) -> serde_json::Value {
+    let read = KvPayload::Read {
+        key: "x".to_string(),
+    };
+    request(sim, node, read)["value"].clone()
+}
+
+#[test]
+fn elects_a_single_leader() {
+    let sim = cluster(1);
+
+    let leaders = leaders(&sim);
+    assert_eq!(leaders.len(), 1, "{:?}", leaders);
+    let term = sim.node(&leaders[0]).unwrap().current_term();
+    for node_id in sim.node_ids() {
+        assert_eq!(sim.node(node_id).unwrap().current_term(), term);
+    }
+}
+
+#[test]
+fn operations_sent_to_any_node_go_through_the_leader() {
+    let mut sim = cluster(2);
+
+    assert_eq!(write(&mut sim, "n0", 1)["type"], "write_ok");
+    assert_eq!(read(&mut sim, "n3"), 1);
+    let cas = KvPayload::Cas {
+        key: "x".to_string(),
+        from: 2,
+        to: 3,
+        create_if_not_exists: false,
+    };
+    let reply = request(&mut sim, "n4", cas.clone());
+    assert_eq!(reply["code"], 22);
+    assert_eq!(write(&mut sim, "n2", 2)["type"], "write_ok");
+    assert_eq!(request(&mut sim, "n1", cas)["type"], "cas_ok");
+    assert_eq!(read(&mut sim, "n0"), 3);
+
+    let missing = KvPayload::Read {
+        key: "y".to_string(),
+    };
+    assert_eq!(request(&mut sim, "n1", missing)["code"], 20);
+    assert!(
+        sim.node_ids()
+            .all(|node_id| sim.node(node_id).unwrap().commit_index() >= 6)
+    );
+}
+
+#[test]
+fn a_partitioned_leader_is_replaced_and_its_writes_are_not_committed() {
+    let mut sim = cluster(3);
+    let old_leader = leaders(&sim).remove(0);
+    assert_eq!(write(&mut sim, &old_leader, 1)["type"], "write_ok");
+
+    let majority = sim
+        .node_ids()
+        .filter(|node_id| **node_id != old_leader)
+        .cloned()
+        .collect::<Vec<_>>();
+    let at = sim.now();
+    sim.partition_at(at, vec![vec![old_leader.clone()], majority.clone()]);
+    let lost_write = sim
+        .send(
+            "c2",
+            &old_leader,
+            KvPayload::Write {
+                key: "x".to_string(),
+                value: 2,
+            },
+        )
+        .unwrap();
+    sim.run_for(Duration::from_secs(2)).unwrap();
+
+    let new_leaders = leaders(&sim)
+        .into_iter()
+        .filter(|leader| *leader != old_leader)
+        .collect::<Vec<_>>();
+    assert_eq!(new_leaders.len(), 1);
+    assert_eq!(write(&mut sim, &majority[0], 3)["type"], "write_ok");
+
+    sim.heal_at(sim.now());
+    sim.run_for(Duration::from_secs(1)).unwrap();
+    assert_eq!(leaders(&sim), new_leaders);
+    assert_eq!(read(&mut sim, &old_leader), 3);
+    let reply = sim.reply::<serde_json::Value>("c2", lost_write);
+    assert_ne!(
+        reply.map(|reply| reply.payload["type"].clone()),
+        Some("write_ok".into())
+    );
+}
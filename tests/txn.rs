@@ -0,0 +1,66 @@
+use rustorm::checker::txn::{ConsistencyModel, check_txn};
+use rustorm::node::Node;
+use rustorm::node::multitxn::MultiTxnNode;
+use rustorm::node::singletxn::SingleTxnNode;
+use rustorm::payloads::{TxnOperation, TxnPayload};
+use rustorm::sim::Simulation;
+use std::time::Duration;
+
+/// Runs 100 transactions of reads and unique writes over three keys, spread over the nodes.
+fn run_workload<N>(sim: &mut Simulation<N, TxnPayload>)
+where
+    N: Node<TxnPayload>,
+{
+    let node_ids = sim.node_ids().cloned().collect::<Vec<_>>();
+    for i in 0..100 {
+        let txn = vec![
+            TxnOperation::Read {
+                key: i % 3,
+                value: None,
+            },
+            TxnOperation::Write {
+                key: (i + 1) % 3,
+                value: i,
+            },
+            TxnOperation::Read {
+                key: (i + 2) % 3,
+                value: None,
+            },
+        ];
+        sim.send("c1", &node_ids[i % node_ids.len()], TxnPayload::Txn { txn })
+            .unwrap();
+        sim.run_for(Duration::from_millis(10)).unwrap();
+    }
+    sim.run_for(Duration::from_secs(2)).unwrap();
+}
+
+#[test]
+fn single_node_transactions_are_read_uncommitted() {
+    let mut sim = Simulation::<SingleTxnNode, TxnPayload>::new(1, 1).unwrap();
+    run_workload(&mut sim);
+
+    let report = check_txn(&sim.history::<TxnPayload>());
+    assert_eq!(report.committed, 100);
+    assert!(
+        report.is_valid(ConsistencyModel::ReadUncommitted),
+        "{:?}",
+        report
+    );
+}
+
+#[test]
+fn multi_node_transactions_are_read_committed_under_partitions() {
+    let mut sim = Simulation::<MultiTxnNode, TxnPayload>::new(3, 21)
+        .unwrap()
+        .with_latency(Duration::from_millis(1), Duration::from_millis(10));
+    sim.partition_randomly(Duration::from_millis(200), Duration::from_millis(100));
+    run_workload(&mut sim);
+
+    let report = check_txn(&sim.history::<TxnPayload>());
+    assert_eq!(report.committed, 100);
+    assert!(
+        report.is_valid(ConsistencyModel::ReadCommitted),
+        "{:?}",
+        report
+    );
+}